@@ -0,0 +1,62 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use futures::{stream, TryStreamExt};
+use multi_stream_synchronizer::{sync, Config, WithTimestamp};
+use std::time::Duration;
+
+const MESSAGES_PER_STREAM: u64 = 1000;
+
+#[derive(Debug, Clone)]
+struct BenchMessage(Duration);
+
+impl WithTimestamp for BenchMessage {
+    fn timestamp(&self) -> Duration {
+        self.0
+    }
+}
+
+/// Generates interleaved messages at 100 Hz for each stream, where
+/// stream `i` lags behind by `i` hundred microseconds.
+fn generate_messages(key_count: usize) -> Vec<(usize, BenchMessage)> {
+    (0..MESSAGES_PER_STREAM)
+        .flat_map(|index| {
+            (0..key_count).map(move |key| {
+                let ts = Duration::from_millis(1000 + index * 10)
+                    + Duration::from_micros(key as u64 * 100);
+                (key, BenchMessage(ts))
+            })
+        })
+        .collect()
+}
+
+fn key_count_scaling(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("key_count_scaling");
+
+    for key_count in [2, 4, 8, 16, 32] {
+        let messages = generate_messages(key_count);
+
+        // Each group is one element, so that the reported throughput
+        // is in groups per second.
+        group.throughput(Throughput::Elements(MESSAGES_PER_STREAM));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(key_count),
+            &messages,
+            |b, messages| {
+                b.iter(|| {
+                    runtime.block_on(async {
+                        let input = stream::iter(messages.iter().cloned().map(Ok));
+                        let config = Config::basic(Duration::from_millis(10), None, 16);
+                        let (output, _feedback_rx) = sync(input, 0..key_count, config).unwrap();
+                        let groups: Vec<_> = output.try_collect().await.unwrap();
+                        groups
+                    })
+                });
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, key_count_scaling);
+criterion_main!(benches);
@@ -0,0 +1,8 @@
+fn main() {
+    #[cfg(feature = "proto")]
+    {
+        println!("cargo:rerun-if-changed=proto/sync.proto");
+        prost_build::compile_protos(&["proto/sync.proto"], &["proto/"])
+            .expect("unable to compile protobuf schema");
+    }
+}
@@ -0,0 +1,129 @@
+//! Derive macros for `multi-stream-synchronizer`. Enable the `derive`
+//! feature of the main crate instead of depending on this crate
+//! directly.
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Field, Fields, Type};
+
+/// Derives `WithTimestamp` for a struct with named fields.
+///
+/// The timestamp is read from the field marked with `#[timestamp]`,
+/// or from the field named `timestamp` if no field is marked. The
+/// field must be of type `Duration`.
+///
+/// ```ignore
+/// #[derive(WithTimestamp)]
+/// struct Msg {
+///     #[timestamp]
+///     ts: Duration,
+///     data: Vec<u8>,
+/// }
+/// ```
+#[proc_macro_derive(WithTimestamp, attributes(timestamp))]
+pub fn derive_with_timestamp(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let Data::Struct(data) = &input.data else {
+        return Err(Error::new(
+            Span::call_site(),
+            "WithTimestamp can only be derived for structs",
+        ));
+    };
+    let Fields::Named(fields) = &data.fields else {
+        return Err(Error::new(
+            Span::call_site(),
+            "WithTimestamp can only be derived for structs with named fields",
+        ));
+    };
+
+    let field = find_timestamp_field(fields.named.iter())?;
+    if !is_duration(&field.ty) {
+        return Err(Error::new_spanned(
+            &field.ty,
+            "the timestamp field must be of type `Duration`",
+        ));
+    }
+
+    let ident = &input.ident;
+    let field_ident = &field.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::multi_stream_synchronizer::WithTimestamp for #ident #ty_generics
+        #where_clause
+        {
+            fn timestamp(&self) -> ::std::time::Duration {
+                self.#field_ident
+            }
+        }
+    })
+}
+
+/// Finds the field marked with `#[timestamp]`, or the field named
+/// `timestamp` if no field is marked.
+fn find_timestamp_field<'a>(
+    mut fields: impl Iterator<Item = &'a Field> + Clone,
+) -> syn::Result<&'a Field> {
+    let mut marked = fields.clone().filter(|field| {
+        field
+            .attrs
+            .iter()
+            .any(|attr| attr.path().is_ident("timestamp"))
+    });
+
+    if let Some(field) = marked.next() {
+        if let Some(other) = marked.next() {
+            return Err(Error::new_spanned(
+                other,
+                "only one field can be marked with #[timestamp]",
+            ));
+        }
+        return Ok(field);
+    }
+
+    fields
+        .find(|field| {
+            field
+                .ident
+                .as_ref()
+                .is_some_and(|ident| ident == "timestamp")
+        })
+        .ok_or_else(|| {
+            Error::new(
+                Span::call_site(),
+                "no field is marked with #[timestamp] or named `timestamp`",
+            )
+        })
+}
+
+/// Accepts `Duration`, `time::Duration`, `std::time::Duration` and
+/// `core::time::Duration`, with or without a leading `::`.
+fn is_duration(ty: &Type) -> bool {
+    let Type::Path(path) = ty else {
+        return false;
+    };
+    if path.qself.is_some() {
+        return false;
+    }
+
+    let mut idents = vec![];
+    for segment in &path.path.segments {
+        if !segment.arguments.is_empty() {
+            return false;
+        }
+        idents.push(segment.ident.to_string());
+    }
+    let idents: Vec<_> = idents.iter().map(String::as_str).collect();
+
+    matches!(
+        idents.as_slice(),
+        ["Duration"] | ["time", "Duration"] | ["std" | "core", "time", "Duration"]
+    )
+}
@@ -0,0 +1,70 @@
+use futures::{stream, StreamExt, TryStreamExt};
+use multi_stream_synchronizer::{sync_any, AnyMessage, Config, WithTimestamp};
+use std::time::Duration;
+
+// A camera image
+#[derive(Debug)]
+struct Image {
+    timestamp: Duration,
+    width: u32,
+    height: u32,
+}
+
+impl WithTimestamp for Image {
+    fn timestamp(&self) -> Duration {
+        self.timestamp
+    }
+}
+
+// A LiDAR point cloud
+#[derive(Debug)]
+struct PointCloud {
+    timestamp: Duration,
+    points: Vec<[f32; 3]>,
+}
+
+impl WithTimestamp for PointCloud {
+    fn timestamp(&self) -> Duration {
+        self.timestamp
+    }
+}
+
+#[tokio::main]
+async fn main() -> eyre::Result<()> {
+    let images = stream::iter([1000, 1100, 1200]).map(|ts| {
+        let image = Image {
+            timestamp: Duration::from_millis(ts),
+            width: 640,
+            height: 480,
+        };
+        ("camera", AnyMessage::new(image))
+    });
+    let clouds = stream::iter([1003, 1098, 1205]).map(|ts| {
+        let cloud = PointCloud {
+            timestamp: Duration::from_millis(ts),
+            points: vec![[0.0, 0.0, 0.0]; 3],
+        };
+        ("lidar", AnyMessage::new(cloud))
+    });
+
+    // Messages of different types share one input stream.
+    let input = stream::select(images, clouds).map(eyre::Ok);
+    let config = Config::basic(Duration::from_millis(20), None, 16);
+    let (output, _feedback_rx) = sync_any(input, ["camera", "lidar"], config)?;
+
+    let groups: Vec<_> = output.try_collect().await?;
+    for group in groups {
+        let image: &Image = group.get_as(&"camera").unwrap();
+        let cloud: &PointCloud = group.get_as(&"lidar").unwrap();
+        println!(
+            "image {}x{} at {:?}, {} points at {:?}",
+            image.width,
+            image.height,
+            image.timestamp,
+            cloud.points.len(),
+            cloud.timestamp
+        );
+    }
+
+    Ok(())
+}
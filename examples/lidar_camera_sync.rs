@@ -0,0 +1,61 @@
+use futures::{channel::mpsc, StreamExt};
+use multi_stream_synchronizer::{sync, utils::duration_diff, Config, WithTimestamp};
+use rand::Rng;
+use std::time::{Duration, Instant};
+
+// A sensor frame stamped with the time it is captured
+#[derive(Debug, Clone)]
+struct Frame {
+    timestamp: Duration,
+}
+
+impl WithTimestamp for Frame {
+    fn timestamp(&self) -> Duration {
+        self.timestamp
+    }
+}
+
+#[tokio::main]
+async fn main() -> eyre::Result<()> {
+    let (input_tx, input_rx) = mpsc::unbounded();
+    let config = Config::basic(Duration::from_millis(20), None, 16);
+    let (mut sync_stream, _feedback_rx) = sync(input_rx, ["lidar", "camera"], config)?;
+
+    // Simulate a 10 Hz LiDAR and a 30 Hz camera for 3 seconds. Each
+    // frame is delayed by a random jitter of up to 5 ms.
+    let start = Instant::now();
+    for (key, period_ms, count) in [("lidar", 100, 30), ("camera", 33, 90)] {
+        let input_tx = input_tx.clone();
+
+        tokio::spawn(async move {
+            for _ in 0..count {
+                let jitter = rand::rng().random_range(0..5000);
+                let period = Duration::from_millis(period_ms) + Duration::from_micros(jitter);
+                tokio::time::sleep(period).await;
+
+                let frame = Frame {
+                    timestamp: start.elapsed(),
+                };
+                if input_tx.unbounded_send(Ok((key, frame))).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    drop(input_tx);
+
+    // Print the timestamp spread of each group
+    while let Some(group) = sync_stream.next().await {
+        let group = group?;
+        let lidar_ts = group["lidar"].timestamp();
+        let camera_ts = group["camera"].timestamp();
+        let spread = duration_diff(lidar_ts, camera_ts);
+
+        println!(
+            "lidar: {:>8.3?}  camera: {:>8.3?}  spread: {:>8.3?}",
+            lidar_ts, camera_ts, spread
+        );
+    }
+
+    Ok(())
+}
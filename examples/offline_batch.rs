@@ -0,0 +1,39 @@
+use indexmap::IndexMap;
+use multi_stream_synchronizer::{drain_to_vec, sync_from_iter, Config, WithTimestamp};
+use std::time::Duration;
+
+// Define your message type
+#[derive(Debug, Clone)]
+struct MyMessage(Duration);
+
+impl WithTimestamp for MyMessage {
+    fn timestamp(&self) -> Duration {
+        self.0
+    }
+}
+
+fn main() -> eyre::Result<()> {
+    // Recorded messages, e.g. loaded from a log file, sorted by
+    // timestamp
+    let x_seq = [1001, 1999, 3000, 4002];
+    let y_seq = [998, 2003, 3002, 3997];
+    let mut messages: Vec<_> = x_seq
+        .iter()
+        .map(|&ts| ("X", ts))
+        .chain(y_seq.iter().map(|&ts| ("Y", ts)))
+        .map(|(key, ts)| (key, MyMessage(Duration::from_millis(ts))))
+        .collect();
+    messages.sort_by_key(|(_, msg)| msg.0);
+
+    // No async runtime is needed to process recorded data.
+    let config = Config::basic(Duration::from_millis(500), None, 16);
+    let (mut groups, mut state): (Vec<IndexMap<&str, MyMessage>>, _) =
+        sync_from_iter(messages, ["X", "Y"], config)?;
+
+    // Flush the messages remaining in the buffers
+    groups.extend(drain_to_vec(&mut state));
+
+    println!("{groups:#?}");
+
+    Ok(())
+}
@@ -0,0 +1,69 @@
+use futures::{channel::mpsc, TryStreamExt};
+use indexmap::IndexMap;
+use multi_stream_synchronizer::{sync, Config, WithTimestamp};
+use std::time::Duration;
+
+// Define your message type
+#[derive(Debug, Clone)]
+struct MyMessage(Duration);
+
+impl WithTimestamp for MyMessage {
+    fn timestamp(&self) -> Duration {
+        self.0
+    }
+}
+
+#[tokio::main]
+async fn main() -> eyre::Result<()> {
+    let (input_tx, input_rx) = mpsc::unbounded();
+    let config = Config::basic(Duration::from_millis(50), None, 4);
+    let (sync_stream, feedback_rx) = sync(input_rx, ["fast", "medium", "slow"], config)?;
+
+    // The fast producer generates messages without delay. It waits
+    // for the feedback before sending each message, so that its
+    // buffer never overflows while the slow streams catch up.
+    {
+        let input_tx = input_tx.clone();
+        let mut feedback_rx = feedback_rx.clone();
+
+        tokio::spawn(async move {
+            for index in 1..=40 {
+                // Give up if the synchronizer stalls or is dropped.
+                let accepted = feedback_rx
+                    .wait_for_key_accepted(&"fast", Duration::from_secs(1))
+                    .await;
+                if !accepted {
+                    break;
+                }
+
+                let msg = MyMessage(Duration::from_millis(index * 25));
+                if input_tx.unbounded_send(Ok(("fast", msg))).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    // The other producers send messages in real time.
+    for (key, period_ms, count) in [("medium", 50, 20), ("slow", 100, 10)] {
+        let input_tx = input_tx.clone();
+
+        tokio::spawn(async move {
+            for index in 1..=count {
+                tokio::time::sleep(Duration::from_millis(period_ms)).await;
+                let msg = MyMessage(Duration::from_millis(index * period_ms));
+                if input_tx.unbounded_send(Ok((key, msg))).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    drop(input_tx);
+    drop(feedback_rx);
+
+    // Collect the groups
+    let groups: Vec<IndexMap<&str, MyMessage>> = sync_stream.try_collect().await?;
+    println!("{groups:#?}");
+
+    Ok(())
+}
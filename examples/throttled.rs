@@ -0,0 +1,47 @@
+use futures::{channel::mpsc, stream, SinkExt, StreamExt, TryStreamExt};
+use indexmap::IndexMap;
+use multi_stream_synchronizer::{sync, Config, ThrottledSource, WithTimestamp};
+use std::time::Duration;
+
+// Define your message type
+#[derive(Debug, Clone)]
+struct MyMessage(Duration);
+
+impl WithTimestamp for MyMessage {
+    fn timestamp(&self) -> Duration {
+        self.0
+    }
+}
+
+#[tokio::main]
+async fn main() -> eyre::Result<()> {
+    // The synchronizer reads from a channel, so that sources can be
+    // connected after the feedback receiver is available.
+    let (input_tx, input_rx) = mpsc::unbounded();
+    let config = Config::basic(Duration::from_millis(50), None, 4);
+    let (sync_stream, feedback_rx) = sync(input_rx, ["X", "Y"], config)?;
+
+    // Each source is throttled on its own. A source waits whenever
+    // the buffer for its key is full.
+    for (key, period_ms) in [("X", 100), ("Y", 100)] {
+        let source = stream::iter(1..=20)
+            .map(move |index| Ok((key, MyMessage(Duration::from_millis(index * period_ms)))));
+        let mut throttled = ThrottledSource::new(source, feedback_rx.clone());
+        let mut input_tx = input_tx.clone();
+
+        tokio::spawn(async move {
+            while let Some(item) = throttled.next().await {
+                if input_tx.send(item).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    drop(input_tx);
+
+    // Collect the groups
+    let groups: Vec<IndexMap<&str, MyMessage>> = sync_stream.try_collect().await?;
+    println!("{groups:#?}");
+
+    Ok(())
+}
@@ -1,15 +1,21 @@
 use crate::types::WithTimestamp;
-use std::{collections::VecDeque, time::Duration};
+use std::{collections::VecDeque, fmt, time::Duration};
 
 /// A buffer to store a sequence of messages with monotonically
 /// increasing timestamps.
-#[derive(Debug)]
+///
+/// Each message is stored along with the timestamp used for ordering,
+/// which may differ from the timestamp on the message if it is
+/// adjusted by a clock offset.
+#[derive(Debug, Clone)]
 pub struct Buffer<T>
 where
     T: WithTimestamp,
 {
-    buffer: VecDeque<T>,
+    buffer: VecDeque<(Duration, T)>,
     last_ts: Option<Duration>,
+    capacity_limit: Option<usize>,
+    out_of_order_tolerance: Duration,
 }
 
 impl<T> Buffer<T>
@@ -20,9 +26,59 @@ where
         Self {
             buffer: VecDeque::with_capacity(capacity),
             last_ts: None,
+            capacity_limit: None,
+            out_of_order_tolerance: Duration::ZERO,
         }
     }
 
+    /// Creates a buffer holding the messages, which must be sorted by
+    /// strictly increasing timestamps. The order is only checked in
+    /// debug builds. Use [collect](Iterator::collect) to build a
+    /// buffer with checks.
+    pub fn from_sorted_unchecked(items: Vec<T>) -> Self {
+        let buffer: VecDeque<_> = items
+            .into_iter()
+            .map(|item| (item.timestamp(), item))
+            .collect();
+        let last_ts = buffer.back().map(|&(ts, _)| ts);
+        let buffer = Self {
+            buffer,
+            last_ts,
+            capacity_limit: None,
+            out_of_order_tolerance: Duration::ZERO,
+        };
+        debug_assert!(buffer.is_monotonic(), "messages are not sorted");
+        buffer
+    }
+
+    /// Sets the maximum number of messages kept in the buffer. Once
+    /// the limit is reached, [try_push](Buffer::try_push) rejects new
+    /// messages with [PushError::BufferFull]. The buffer grows
+    /// without limit if it is `None`, which is the default.
+    pub fn enforce_capacity(&mut self, capacity_limit: Option<usize>) {
+        self.capacity_limit = capacity_limit;
+    }
+
+    /// Gets the maximum number of messages kept in the buffer, or
+    /// `None` if it is unlimited.
+    pub fn capacity_limit(&self) -> Option<usize> {
+        self.capacity_limit
+    }
+
+    /// Sets how far behind the latest timestamp a message may be and
+    /// still be accepted. Such a message is inserted in timestamp
+    /// order instead of being rejected with
+    /// [PushError::OutOfOrder]. It is zero by default.
+    pub fn set_out_of_order_tolerance(&mut self, tolerance: Duration) {
+        self.out_of_order_tolerance = tolerance;
+    }
+
+    /// Gets how far behind the latest timestamp a message may be. See
+    /// [set_out_of_order_tolerance](Buffer::set_out_of_order_tolerance).
+    pub fn out_of_order_tolerance(&self) -> Duration {
+        self.out_of_order_tolerance
+    }
+
     pub fn len(&self) -> usize {
         self.buffer.len()
     }
@@ -32,15 +88,99 @@ where
     }
 
     pub fn front(&self) -> Option<&T> {
-        self.buffer.front()
+        self.buffer.front().map(|(_, item)| item)
     }
 
     pub fn back(&self) -> Option<&T> {
-        self.buffer.back()
+        self.buffer.back().map(|(_, item)| item)
+    }
+
+    /// Gets the adjusted timestamp of the first message.
+    pub fn front_ts(&self) -> Option<Duration> {
+        self.buffer.front().map(|&(ts, _)| ts)
+    }
+
+    /// Gets the adjusted timestamp of the last message.
+    pub fn back_ts(&self) -> Option<Duration> {
+        self.buffer.back().map(|&(ts, _)| ts)
+    }
+
+    /// Gets the adjusted timestamps of the first and the last
+    /// messages.
+    pub fn time_span(&self) -> Option<(Duration, Duration)> {
+        self.front_ts().zip(self.back_ts())
+    }
+
+    /// Gets the duration from the first to the last message.
+    pub fn duration_span(&self) -> Option<Duration> {
+        let (front_ts, back_ts) = self.time_span()?;
+        Some(back_ts - front_ts)
+    }
+
+    /// Finds the message whose adjusted timestamp is closest to the
+    /// target. The earlier message is preferred if two messages are
+    /// equally close.
+    pub fn find_closest(&self, target: Duration) -> Option<&T> {
+        // Messages are sorted by timestamp, so the closest one is
+        // next to the insertion point of the target.
+        let index = self.buffer.partition_point(|&(ts, _)| ts < target);
+        let before = index
+            .checked_sub(1)
+            .and_then(|index| self.buffer.get(index));
+        let after = self.buffer.get(index);
+
+        let (_, item) = match (before, after) {
+            (Some(before), Some(after)) => {
+                if target - before.0 <= after.0 - target {
+                    before
+                } else {
+                    after
+                }
+            }
+            (Some(closest), None) | (None, Some(closest)) => closest,
+            (None, None) => return None,
+        };
+        Some(item)
+    }
+
+    /// Iterates over messages in timestamp order without consuming
+    /// them.
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = &T> + DoubleEndedIterator {
+        self.buffer.iter().map(|(_, item)| item)
+    }
+
+    /// Iterates over messages with adjusted timestamps within
+    /// `start..=end` in timestamp order.
+    pub fn iter_in_window(&self, start: Duration, end: Duration) -> impl Iterator<Item = &T> {
+        let lower = self.buffer.partition_point(|&(ts, _)| ts < start);
+        let upper = self.buffer.partition_point(|&(ts, _)| ts <= end);
+        self.buffer
+            .range(lower..upper.max(lower))
+            .map(|(_, item)| item)
+    }
+
+    /// Counts messages with adjusted timestamps within `start..=end`
+    /// by binary search, without visiting the messages.
+    pub fn count_in_range(&self, start: Duration, end: Duration) -> usize {
+        let lower = self.buffer.partition_point(|&(ts, _)| ts < start);
+        let upper = self.buffer.partition_point(|&(ts, _)| ts <= end);
+        upper.saturating_sub(lower)
+    }
+
+    /// Checks if any message has an adjusted timestamp within
+    /// `start..=end`.
+    pub fn has_message_in_range(&self, start: Duration, end: Duration) -> bool {
+        let lower = self.buffer.partition_point(|&(ts, _)| ts < start);
+        self.buffer.get(lower).is_some_and(|&(ts, _)| ts <= end)
+    }
+
+    /// Iterates over messages along with their adjusted timestamps.
+    pub(crate) fn iter_with_timestamps(&self) -> impl Iterator<Item = (Duration, &T)> + Clone {
+        self.buffer.iter().map(|(ts, item)| (*ts, item))
     }
 
     pub fn pop_front(&mut self) -> Option<T> {
-        self.buffer.pop_front()
+        self.buffer.pop_front().map(|(_, item)| item)
     }
 
     pub fn front_entry(&mut self) -> Option<FrontEntry<'_, T>> {
@@ -63,17 +203,47 @@ where
     //     self.buffer.pop_back()
     // }
 
-    // pub fn front_ts(&self) -> Option<Duration> {
-    //     self.buffer
-    //         .front()
-    //         .map(|item| item.timestamp())
-    //         .or(self.last_ts)
-    // }
-
     // pub fn last_ts(&self) -> Option<Duration> {
     //     self.last_ts
     // }
 
+    /// Checks if timestamps of buffered messages are strictly
+    /// increasing.
+    pub fn is_monotonic(&self) -> bool {
+        self.buffer
+            .iter()
+            .zip(self.buffer.iter().skip(1))
+            .all(|((prev_ts, _), (next_ts, _))| prev_ts < next_ts)
+    }
+
+    /// Checks if timestamps of buffered messages are non-decreasing,
+    /// which holds for messages pushed by
+    /// [try_push_equal](Buffer::try_push_equal).
+    pub fn is_non_decreasing(&self) -> bool {
+        self.buffer
+            .iter()
+            .zip(self.buffer.iter().skip(1))
+            .all(|((prev_ts, _), (next_ts, _))| prev_ts <= next_ts)
+    }
+
+    /// Checks if the latest message is after the one before it.
+    pub(crate) fn is_back_monotonic(&self) -> bool {
+        let mut latest = self.buffer.iter().rev().map(|(ts, _)| ts);
+        match (latest.next(), latest.next()) {
+            (Some(back_ts), Some(prev_ts)) => prev_ts < back_ts,
+            _ => true,
+        }
+    }
+
+    /// Checks if the latest message is not before the one before it.
+    pub(crate) fn is_back_non_decreasing(&self) -> bool {
+        let mut latest = self.buffer.iter().rev().map(|(ts, _)| ts);
+        match (latest.next(), latest.next()) {
+            (Some(back_ts), Some(prev_ts)) => prev_ts <= back_ts,
+            _ => true,
+        }
+    }
+
     /// Drops messages before the a specific timestamp and returns the
     /// number of dropped messages.
     pub fn drop_before(&mut self, ts: Duration) -> usize {
@@ -84,7 +254,7 @@ where
                 break;
             };
 
-            if entry.value().timestamp() >= ts {
+            if entry.timestamp() >= ts {
                 break;
             } else {
                 let _ = entry.take();
@@ -95,6 +265,50 @@ where
         count
     }
 
+    /// Removes messages before a specific timestamp and returns them
+    /// in order. Unlike [drop_before](Buffer::drop_before), the
+    /// removed messages are kept for the caller, such as for audit
+    /// logging.
+    pub fn drain_before(&mut self, ts: Duration) -> Vec<T> {
+        let end = self.buffer.partition_point(|&(item_ts, _)| item_ts < ts);
+        self.buffer.drain(..end).map(|(_, item)| item).collect()
+    }
+
+    /// Removes all messages and returns them in order. The latest
+    /// timestamp is cleared as well, so that the buffer accepts any
+    /// message like a new one. The capacity limit and the
+    /// out-of-order tolerance are kept.
+    pub fn drain_all(&mut self) -> Vec<T> {
+        self.last_ts = None;
+        self.buffer.drain(..).map(|(_, item)| item).collect()
+    }
+
+    /// Removes messages with timestamps within `start..=end` and
+    /// returns them in order.
+    ///
+    /// If the last message is removed, the latest timestamp falls
+    /// back to that of the new last message, or is cleared if the
+    /// buffer becomes empty, so that messages within the range can be
+    /// pushed again.
+    pub fn pop_range(&mut self, start: Duration, end: Duration) -> Vec<T> {
+        let lower = self.buffer.partition_point(|&(ts, _)| ts < start);
+        let upper = self.buffer.partition_point(|&(ts, _)| ts <= end);
+        if lower >= upper {
+            return vec![];
+        }
+
+        let removes_back = upper == self.buffer.len();
+        let items: Vec<T> = self
+            .buffer
+            .drain(lower..upper)
+            .map(|(_, item)| item)
+            .collect();
+        if removes_back {
+            self.last_ts = self.back_ts();
+        }
+        items
+    }
+
     /// Drop expired messages based on their timeout and reference timestamp.
     /// Returns the number of dropped messages.
     pub fn drop_expired(&mut self, reference_timestamp: Duration) -> usize {
@@ -106,7 +320,7 @@ where
             };
 
             let message = entry.value();
-            let message_time = message.timestamp();
+            let message_time = entry.timestamp();
 
             // Check if message has expired based on its timeout
             if let Some(timeout) = message.timeout() {
@@ -124,34 +338,226 @@ where
         count
     }
 
+    /// Removes expired messages like
+    /// [drop_expired](Buffer::drop_expired) and returns them in
+    /// order.
+    pub fn drain_expired(&mut self, reference_timestamp: Duration) -> Vec<T> {
+        let end = self
+            .buffer
+            .iter()
+            .take_while(|(ts, item)| {
+                item.timeout()
+                    .is_some_and(|timeout| reference_timestamp.saturating_sub(*ts) >= timeout)
+            })
+            .count();
+        self.buffer.drain(..end).map(|(_, item)| item).collect()
+    }
+
     /// Try to push a message into the buffer.
     ///
     /// If the timestamp on the message is below that of the
-    /// previously inserted message, the message is dropped and the
-    /// method returns false. Otherwise, it stores and message and
-    /// returns true.
-    pub fn try_push(&mut self, item: T) -> Result<(), T> {
+    /// previously inserted message, the message is returned in
+    /// [PushError::OutOfOrder]. If the timestamp equals that of the
+    /// last message in the buffer, the last message is replaced, so
+    /// that the latest of equal-timestamp messages is kept. If the
+    /// buffer reaches the capacity limit, the message is returned in
+    /// [PushError::BufferFull]. Otherwise, it stores the message.
+    pub fn try_push(&mut self, item: T) -> Result<(), PushError<T>> {
         let timestamp = item.timestamp();
+        self.try_push_with_timestamp(timestamp, item)
+    }
 
+    /// Try to push a message into the buffer ordered by the given
+    /// adjusted timestamp instead of the timestamp on the message.
+    pub fn try_push_with_timestamp(
+        &mut self,
+        timestamp: Duration,
+        item: T,
+    ) -> Result<(), PushError<T>> {
+        self.push_inner(timestamp, item, false)
+    }
+
+    /// Try to push a message into the buffer like
+    /// [try_push](Buffer::try_push), except that a message with the
+    /// same timestamp as the last message is kept along with it, such
+    /// as for a sensor publishing messages in batches.
+    pub fn try_push_equal(&mut self, item: T) -> Result<(), PushError<T>> {
+        let timestamp = item.timestamp();
+        self.try_push_equal_with_timestamp(timestamp, item)
+    }
+
+    /// Try to push a message like
+    /// [try_push_equal](Buffer::try_push_equal) ordered by the given
+    /// adjusted timestamp.
+    pub fn try_push_equal_with_timestamp(
+        &mut self,
+        timestamp: Duration,
+        item: T,
+    ) -> Result<(), PushError<T>> {
+        self.push_inner(timestamp, item, true)
+    }
+
+    fn push_inner(
+        &mut self,
+        timestamp: Duration,
+        item: T,
+        allow_equal: bool,
+    ) -> Result<(), PushError<T>> {
         // Ensure that the inserted message has greater timestamp than
-        // the latest timestamp.
+        // the latest timestamp, unless it is late within the tolerance.
         match self.last_ts {
-            Some(last_ts) if last_ts >= timestamp => return Err(item),
+            Some(last_ts) if last_ts.saturating_sub(timestamp) > self.out_of_order_tolerance => {
+                return Err(PushError::OutOfOrder(item))
+            }
+            Some(last_ts) if last_ts > timestamp => {
+                return self.insert_sorted(timestamp, item, allow_equal)
+            }
+            Some(last_ts) if last_ts == timestamp && !allow_equal => {
+                // Keep the last of equal-timestamp messages. It is
+                // rejected if the previous one is already consumed.
+                return match self.buffer.back_mut() {
+                    Some((_, back)) => {
+                        *back = item;
+                        Ok(())
+                    }
+                    None => Err(PushError::OutOfOrder(item)),
+                };
+            }
             _ => {}
         }
 
+        if self
+            .capacity_limit
+            .is_some_and(|capacity_limit| self.buffer.len() >= capacity_limit)
+        {
+            return Err(PushError::BufferFull(item));
+        }
+
         self.last_ts = Some(timestamp);
-        self.buffer.push_back(item);
+        self.buffer.push_back((timestamp, item));
+        Ok(())
+    }
+
+    /// Inserts a message behind the latest timestamp within the
+    /// out-of-order tolerance. A message with the same timestamp as a
+    /// buffered one replaces it unless equal timestamps are allowed.
+    fn insert_sorted(
+        &mut self,
+        timestamp: Duration,
+        item: T,
+        allow_equal: bool,
+    ) -> Result<(), PushError<T>> {
+        let index = self.buffer.partition_point(|&(ts, _)| ts < timestamp);
+        if !allow_equal {
+            if let Some((ts, existing)) = self.buffer.get_mut(index) {
+                if *ts == timestamp {
+                    *existing = item;
+                    return Ok(());
+                }
+            }
+        }
+
+        if self
+            .capacity_limit
+            .is_some_and(|capacity_limit| self.buffer.len() >= capacity_limit)
+        {
+            return Err(PushError::BufferFull(item));
+        }
+
+        let index = if allow_equal {
+            self.buffer.partition_point(|&(ts, _)| ts <= timestamp)
+        } else {
+            index
+        };
+        self.buffer.insert(index, (timestamp, item));
         Ok(())
     }
+
+    /// Merges messages from another buffer, such as the one collected
+    /// by a reconnected stream, after the messages in this buffer.
+    ///
+    /// Messages from `other` before the latest timestamp of this
+    /// buffer by more than the out-of-order tolerance, or beyond the
+    /// capacity limit, are dropped. A message at the latest timestamp
+    /// replaces the last message. It returns the number of dropped
+    /// messages.
+    pub fn merge(&mut self, other: Buffer<T>) -> usize {
+        let Buffer {
+            buffer, last_ts, ..
+        } = other;
+
+        let mut dropped = 0;
+        for (ts, item) in buffer {
+            if self.try_push_with_timestamp(ts, item).is_err() {
+                dropped += 1;
+            }
+        }
+
+        self.last_ts = self.last_ts.max(last_ts);
+        dropped
+    }
+}
+
+/// Collects messages pushed with [try_push](Buffer::try_push) in order.
+///
+/// # Panics
+///
+/// Panics if a message is earlier than the previous one.
+impl<T> FromIterator<T> for Buffer<T>
+where
+    T: WithTimestamp,
+{
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let iter = iter.into_iter();
+        let mut buffer = Self::with_capacity(iter.size_hint().0);
+        for item in iter {
+            if buffer.try_push(item).is_err() {
+                panic!("messages are not sorted by timestamp");
+            }
+        }
+        buffer
+    }
+}
+
+/// The error returned when a message cannot be pushed into a
+/// [Buffer]. The rejected message is carried in the error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PushError<T> {
+    /// The timestamp is below that of the last pushed message.
+    OutOfOrder(T),
+    /// The buffer reaches its capacity limit.
+    BufferFull(T),
+}
+
+impl<T> PushError<T> {
+    /// Unwraps the rejected message.
+    pub fn into_inner(self) -> T {
+        match self {
+            Self::OutOfOrder(item) | Self::BufferFull(item) => item,
+        }
+    }
+}
+
+impl<T> fmt::Display for PushError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OutOfOrder(_) => write!(f, "message is out of order"),
+            Self::BufferFull(_) => write!(f, "buffer is full"),
+        }
+    }
 }
 
+impl<T: fmt::Debug> std::error::Error for PushError<T> {}
+
 pub struct FrontEntry<'a, T>
 where
     T: WithTimestamp,
 {
     buffer: &'a mut Buffer<T>,
-    item: Option<T>,
+    item: Option<(Duration, T)>,
 }
 
 impl<'a, T> FrontEntry<'a, T>
@@ -159,11 +565,16 @@ where
     T: WithTimestamp,
 {
     pub fn take(mut self) -> T {
-        self.item.take().unwrap()
+        self.item.take().unwrap().1
     }
 
     pub fn value(&self) -> &T {
-        self.item.as_ref().unwrap()
+        &self.item.as_ref().unwrap().1
+    }
+
+    /// Gets the adjusted timestamp of the message.
+    pub fn timestamp(&self) -> Duration {
+        self.item.as_ref().unwrap().0
     }
 }
 
@@ -371,6 +782,79 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_buffer_is_monotonic() {
+        let mut buffer = Buffer::with_capacity(3);
+        assert!(buffer.is_monotonic());
+
+        for msg in create_messages(&[1000, 2000, 3000]) {
+            buffer.try_push(msg).unwrap();
+        }
+        assert!(buffer.is_monotonic());
+    }
+
+    #[test]
+    fn test_buffer_try_push_with_timestamp() {
+        let mut buffer = Buffer::with_capacity(3);
+
+        buffer
+            .try_push_with_timestamp(Duration::from_millis(500), create_message(1000))
+            .unwrap();
+        assert!(buffer
+            .try_push_with_timestamp(Duration::from_millis(400), create_message(1100))
+            .is_err());
+
+        assert_eq!(buffer.front_ts(), Some(Duration::from_millis(500)));
+        assert_eq!(buffer.back_ts(), Some(Duration::from_millis(500)));
+        assert_eq!(
+            buffer.front().unwrap().timestamp,
+            Duration::from_millis(1000)
+        );
+
+        // Dropping is based on the adjusted timestamp.
+        assert_eq!(buffer.drop_before(Duration::from_millis(600)), 1);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_buffer_merge() {
+        let mut buffer = Buffer::with_capacity(8);
+        for msg in create_messages(&[1000, 2000, 3000]) {
+            buffer.try_push(msg).unwrap();
+        }
+
+        let mut other = Buffer::with_capacity(8);
+        for msg in create_messages(&[2500, 3000, 3500, 4000]) {
+            other.try_push(msg).unwrap();
+        }
+
+        assert_eq!(buffer.merge(other), 1);
+        assert_eq!(buffer.len(), 5);
+        assert!(buffer.is_monotonic());
+        assert_eq!(
+            buffer.back().unwrap().timestamp,
+            Duration::from_millis(4000)
+        );
+
+        // The latest timestamp is kept after the merged messages are
+        // consumed.
+        while buffer.pop_front().is_some() {}
+        assert!(buffer.try_push(create_message(4000)).is_err());
+    }
+
+    #[test]
+    fn test_buffer_merge_into_empty() {
+        let mut buffer = Buffer::with_capacity(4);
+        let mut other = Buffer::with_capacity(4);
+        for msg in create_messages(&[1000, 2000]) {
+            other.try_push(msg).unwrap();
+        }
+
+        assert_eq!(buffer.merge(other), 0);
+        assert_eq!(buffer.len(), 2);
+        assert!(buffer.is_monotonic());
+    }
+
     #[test]
     fn test_buffer_try_push_valid_timestamp() {
         let mut buffer = Buffer::with_capacity(3);
@@ -394,9 +878,391 @@ mod tests {
         buffer.try_push(msg1).unwrap();
 
         let msg2 = create_message(1000);
-        let result = buffer.try_push(msg2);
-        assert!(result.is_err());
+        let result = buffer.try_push(msg2.clone());
+        assert_eq!(result, Err(PushError::OutOfOrder(msg2)));
+        assert_eq!(buffer.len(), 1);
+    }
+
+    #[test]
+    fn test_buffer_find_closest() {
+        let mut buffer = Buffer::with_capacity(4);
+        assert_eq!(buffer.find_closest(Duration::from_millis(1000)), None);
+
+        for msg in create_messages(&[1000, 2000, 3000]) {
+            buffer.try_push(msg).unwrap();
+        }
+        let closest = |ms| {
+            buffer
+                .find_closest(Duration::from_millis(ms))
+                .unwrap()
+                .timestamp
+        };
+
+        assert_eq!(closest(0), Duration::from_millis(1000));
+        assert_eq!(closest(1400), Duration::from_millis(1000));
+        assert_eq!(closest(1600), Duration::from_millis(2000));
+        assert_eq!(closest(2000), Duration::from_millis(2000));
+        assert_eq!(closest(9000), Duration::from_millis(3000));
+
+        // The earlier message wins a tie.
+        assert_eq!(closest(1500), Duration::from_millis(1000));
+        assert_eq!(closest(2500), Duration::from_millis(2000));
+    }
+
+    #[test]
+    fn test_buffer_find_closest_random() {
+        use crate::utils::duration_diff;
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(7);
+
+        for _ in 0..500 {
+            let mut buffer = Buffer::with_capacity(16);
+            let len = rng.random_range(1..16);
+            let mut ts = 0;
+            for _ in 0..len {
+                ts += rng.random_range(0..100);
+                let _ = buffer.try_push(create_message(ts));
+            }
+            let target = Duration::from_millis(rng.random_range(0..ts + 100));
+
+            let distance = |msg: &TestMessage| duration_diff(msg.timestamp, target);
+            let closest = buffer.find_closest(target).unwrap();
+
+            // No message is closer than the returned one, and none
+            // earlier is equally close.
+            for (_, msg) in buffer.iter_with_timestamps() {
+                assert!(distance(closest) <= distance(msg));
+                if msg.timestamp < closest.timestamp {
+                    assert!(distance(closest) < distance(msg));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_buffer_drain_before() {
+        let mut buffer = Buffer::with_capacity(4);
+        for msg in create_messages(&[1000, 2000, 3000, 4000]) {
+            buffer.try_push(msg).unwrap();
+        }
+
+        let drained = buffer.drain_before(Duration::from_millis(3000));
+        assert_eq!(drained, create_messages(&[1000, 2000]));
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.front_ts(), Some(Duration::from_millis(3000)));
+
+        assert!(buffer.drain_before(Duration::from_millis(3000)).is_empty());
+        assert_eq!(buffer.drain_before(Duration::MAX).len(), 2);
+        assert!(buffer.is_empty());
+
+        // The latest timestamp is kept like drop_before.
+        assert!(matches!(
+            buffer.try_push(create_message(3500)),
+            Err(PushError::OutOfOrder(_))
+        ));
+        buffer.try_push(create_message(5000)).unwrap();
+    }
+
+    #[test]
+    fn test_buffer_from_iter() {
+        let buffer: Buffer<_> = create_messages(&[1000, 2000, 3000]).into_iter().collect();
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.back_ts(), Some(Duration::from_millis(3000)));
+        assert!(buffer.is_monotonic());
+
+        // The later of equal-timestamp messages is kept.
+        let buffer: Buffer<_> = [
+            TestMessage::new(1000, "first"),
+            TestMessage::new(1000, "second"),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(buffer.len(), 1);
+        assert_eq!(buffer.front().unwrap().data, "second");
+    }
+
+    #[test]
+    #[should_panic(expected = "not sorted")]
+    fn test_buffer_from_iter_out_of_order() {
+        let _: Buffer<_> = create_messages(&[2000, 1000]).into_iter().collect();
+    }
+
+    #[test]
+    fn test_buffer_from_sorted_unchecked() {
+        let mut buffer = Buffer::from_sorted_unchecked(create_messages(&[1000, 2000]));
+        assert_eq!(buffer.len(), 2);
+        assert!(matches!(
+            buffer.try_push(create_message(1500)),
+            Err(PushError::OutOfOrder(_))
+        ));
+        buffer.try_push(create_message(2500)).unwrap();
+        assert_eq!(buffer.len(), 3);
+    }
+
+    #[test]
+    fn test_buffer_time_span() {
+        let mut buffer = Buffer::with_capacity(3);
+        assert_eq!(buffer.time_span(), None);
+        assert_eq!(buffer.duration_span(), None);
+
+        buffer.try_push(create_message(1000)).unwrap();
+        assert_eq!(buffer.time_span(), Some((ms(1000), ms(1000))));
+        assert_eq!(buffer.duration_span(), Some(Duration::ZERO));
+
+        buffer.try_push(create_message(1500)).unwrap();
+        buffer.try_push(create_message(2500)).unwrap();
+        assert_eq!(buffer.time_span(), Some((ms(1000), ms(2500))));
+        assert_eq!(buffer.duration_span(), Some(ms(1500)));
+    }
+
+    #[test]
+    fn test_buffer_drain_all() {
+        let mut buffer = create_buffer(&[1000, 2000, 3000]);
+        buffer.enforce_capacity(Some(3));
+
+        assert_eq!(buffer.drain_all(), create_messages(&[1000, 2000, 3000]));
+        assert!(buffer.is_empty());
+        assert!(buffer.drain_all().is_empty());
+
+        // The buffer accepts earlier messages again.
+        buffer.try_push(create_message(500)).unwrap();
+        assert_eq!(buffer.back_ts(), Some(Duration::from_millis(500)));
+        assert_eq!(buffer.capacity_limit(), Some(3));
+    }
+
+    fn create_buffer(timestamps_ms: &[u64]) -> Buffer<TestMessage> {
+        let mut buffer = Buffer::with_capacity(timestamps_ms.len());
+        for msg in create_messages(timestamps_ms) {
+            buffer.try_push(msg).unwrap();
+        }
+        buffer
+    }
+
+    fn ms(timestamp_ms: u64) -> Duration {
+        Duration::from_millis(timestamp_ms)
+    }
+
+    #[test]
+    fn test_buffer_iter() {
+        let buffer = create_buffer(&[1000, 2000, 3000]);
+        let expect = create_messages(&[1000, 2000, 3000]);
+
+        assert_eq!(buffer.iter().len(), 3);
+        assert!(buffer.iter().eq(expect.iter()));
+        assert!(buffer.iter().rev().eq(expect.iter().rev()));
+        assert_eq!(buffer.len(), 3);
+    }
+
+    #[test]
+    fn test_buffer_iter_in_window() {
+        let buffer = create_buffer(&[1000, 2000, 3000, 4000]);
+
+        let in_window: Vec<_> = buffer.iter_in_window(ms(2000), ms(3000)).cloned().collect();
+        assert_eq!(in_window, create_messages(&[2000, 3000]));
+        assert_eq!(buffer.iter_in_window(ms(1500), ms(3500)).count(), 2);
+        assert_eq!(buffer.iter_in_window(ms(0), ms(999)).count(), 0);
+        assert_eq!(buffer.iter_in_window(ms(3000), ms(2000)).count(), 0);
+        assert_eq!(buffer.len(), 4);
+    }
+
+    #[test]
+    fn test_buffer_count_in_range() {
+        let buffer = create_buffer(&[1000, 2000, 3000, 4000]);
+
+        assert_eq!(buffer.count_in_range(ms(2000), ms(3000)), 2);
+        assert_eq!(buffer.count_in_range(ms(1500), ms(3500)), 2);
+        assert_eq!(buffer.count_in_range(ms(0), ms(5000)), 4);
+        assert_eq!(buffer.count_in_range(ms(0), ms(999)), 0);
+        assert_eq!(buffer.count_in_range(ms(3000), ms(2000)), 0);
+
+        assert!(buffer.has_message_in_range(ms(4000), ms(4000)));
+        assert!(buffer.has_message_in_range(ms(1500), ms(2500)));
+        assert!(!buffer.has_message_in_range(ms(2100), ms(2900)));
+        assert!(!buffer.has_message_in_range(ms(4001), ms(5000)));
+        assert!(!buffer.has_message_in_range(ms(3000), ms(2000)));
+
+        let empty: Buffer<TestMessage> = Buffer::with_capacity(4);
+        assert_eq!(empty.count_in_range(ms(0), ms(5000)), 0);
+        assert!(!empty.has_message_in_range(ms(0), ms(5000)));
+    }
+
+    #[test]
+    fn test_buffer_pop_range_empty_buffer() {
+        let mut buffer: Buffer<TestMessage> = Buffer::with_capacity(4);
+        assert!(buffer.pop_range(ms(0), ms(5000)).is_empty());
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_buffer_pop_range_matches_nothing() {
+        let mut buffer = create_buffer(&[1000, 2000, 3000]);
+
+        assert!(buffer.pop_range(ms(1500), ms(1900)).is_empty());
+        assert!(buffer.pop_range(ms(0), ms(999)).is_empty());
+        assert!(buffer.pop_range(ms(3001), ms(5000)).is_empty());
+        assert!(buffer.pop_range(ms(2500), ms(1500)).is_empty());
+        assert_eq!(buffer.len(), 3);
+    }
+
+    #[test]
+    fn test_buffer_pop_range_matches_all() {
+        let mut buffer = create_buffer(&[1000, 2000, 3000]);
+
+        let popped = buffer.pop_range(ms(1000), ms(3000));
+        assert_eq!(popped, create_messages(&[1000, 2000, 3000]));
+        assert!(buffer.is_empty());
+
+        // The latest timestamp is cleared along with the messages.
+        buffer.try_push(create_message(500)).unwrap();
+    }
+
+    #[test]
+    fn test_buffer_pop_range_front() {
+        let mut buffer = create_buffer(&[1000, 2000, 3000, 4000]);
+
+        let popped = buffer.pop_range(ms(0), ms(2000));
+        assert_eq!(popped, create_messages(&[1000, 2000]));
+        assert_eq!(buffer.front_ts(), Some(ms(3000)));
+
+        // The latest timestamp is kept.
+        assert!(buffer.try_push(create_message(3500)).is_err());
+    }
+
+    #[test]
+    fn test_buffer_pop_range_back() {
+        let mut buffer = create_buffer(&[1000, 2000, 3000, 4000]);
+
+        let popped = buffer.pop_range(ms(3000), ms(5000));
+        assert_eq!(popped, create_messages(&[3000, 4000]));
+        assert_eq!(buffer.back_ts(), Some(ms(2000)));
+
+        // The latest timestamp falls back to the new last message.
+        assert!(buffer.try_push(create_message(2000)).is_ok());
+        assert!(buffer.try_push(create_message(1500)).is_err());
+        buffer.try_push(create_message(2500)).unwrap();
+        assert_eq!(buffer.len(), 3);
+    }
+
+    #[test]
+    fn test_buffer_pop_range_middle() {
+        let mut buffer = create_buffer(&[1000, 2000, 3000, 4000]);
+
+        let popped = buffer.pop_range(ms(1500), ms(3000));
+        assert_eq!(popped, create_messages(&[2000, 3000]));
+        assert_eq!(buffer.len(), 2);
+        assert!(buffer.is_monotonic());
+    }
+
+    #[test]
+    fn test_buffer_enforce_capacity() {
+        let mut buffer = Buffer::with_capacity(3);
+        buffer.enforce_capacity(Some(3));
+        assert_eq!(buffer.capacity_limit(), Some(3));
+
+        for ts in [1000, 2000, 3000] {
+            buffer.try_push(create_message(ts)).unwrap();
+        }
+        assert_eq!(buffer.len(), 3);
+
+        let result = buffer.try_push(create_message(4000));
+        assert_eq!(result, Err(PushError::BufferFull(create_message(4000))));
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.back_ts(), Some(Duration::from_millis(3000)));
+
+        // Replacing the last message does not grow the buffer.
+        buffer.try_push(TestMessage::new(3000, "last")).unwrap();
+        assert_eq!(buffer.back().unwrap().data, "last");
+
+        // Out-of-order messages are reported as such even if full.
+        let result = buffer.try_push(create_message(500));
+        assert!(matches!(result, Err(PushError::OutOfOrder(_))));
+
+        buffer.pop_front();
+        buffer.try_push(create_message(4000)).unwrap();
+        assert_eq!(buffer.len(), 3);
+    }
+
+    #[test]
+    fn test_buffer_try_push_equal_timestamp_keeps_last() {
+        let mut buffer = Buffer::with_capacity(3);
+        buffer.try_push(create_message(1000)).unwrap();
+
+        buffer.try_push(TestMessage::new(1000, "last")).unwrap();
         assert_eq!(buffer.len(), 1);
+        assert_eq!(buffer.back().unwrap().data, "last");
+        assert!(buffer.is_monotonic());
+    }
+
+    #[test]
+    fn test_buffer_out_of_order_tolerance() {
+        let mut buffer = create_buffer(&[1000, 2000, 3000]);
+        assert!(matches!(
+            buffer.try_push(create_message(2500)),
+            Err(PushError::OutOfOrder(_))
+        ));
+
+        buffer.set_out_of_order_tolerance(ms(1500));
+        buffer.try_push(create_message(2500)).unwrap();
+        buffer.try_push(create_message(1500)).unwrap();
+        assert!(matches!(
+            buffer.try_push(create_message(1499)),
+            Err(PushError::OutOfOrder(_))
+        ));
+        assert_eq!(
+            buffer.iter().cloned().collect::<Vec<_>>(),
+            create_messages(&[1000, 1500, 2000, 2500, 3000])
+        );
+        assert_eq!(buffer.back_ts(), Some(ms(3000)));
+        assert!(buffer.is_monotonic());
+
+        // A late message at a buffered timestamp replaces it.
+        buffer.try_push(TestMessage::new(2000, "late")).unwrap();
+        assert_eq!(buffer.len(), 5);
+        assert!(buffer.iter().any(|message| message.data == "late"));
+
+        buffer.enforce_capacity(Some(5));
+        assert!(matches!(
+            buffer.try_push(create_message(2200)),
+            Err(PushError::BufferFull(_))
+        ));
+    }
+
+    #[test]
+    fn test_buffer_try_push_equal_keeps_both() {
+        let mut buffer = Buffer::with_capacity(3);
+        buffer
+            .try_push_equal(TestMessage::new(1000, "first"))
+            .unwrap();
+        buffer
+            .try_push_equal(TestMessage::new(1000, "second"))
+            .unwrap();
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.front().unwrap().data, "first");
+        assert_eq!(buffer.back().unwrap().data, "second");
+        assert!(!buffer.is_monotonic());
+        assert!(buffer.is_non_decreasing());
+
+        assert!(matches!(
+            buffer.try_push_equal(create_message(999)),
+            Err(PushError::OutOfOrder(_))
+        ));
+        buffer.try_push_equal(create_message(2000)).unwrap();
+        assert_eq!(buffer.len(), 3);
+    }
+
+    #[test]
+    fn test_buffer_try_push_1khz_no_drop() {
+        // 1 kHz samples stamped with a delay of up to 1.1 ms by a
+        // clock with 1 ms resolution. Neighboring samples can share
+        // the same timestamp.
+        let mut buffer = Buffer::with_capacity(1000);
+        for index in 0..1000_u64 {
+            let time_us = index * 1000 + [0, 600, 1100][index as usize % 3];
+            buffer.try_push(create_message(time_us / 1000)).unwrap();
+        }
+        assert!(buffer.is_monotonic());
+        assert_eq!(buffer.back_ts(), Some(Duration::from_millis(999)));
     }
 
     #[test]
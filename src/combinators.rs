@@ -0,0 +1,348 @@
+use crate::{
+    types::{Group, GroupAnnotation, Key, OutputStream, WithTimestamp},
+    SyncError,
+};
+use eyre::Result;
+use futures::{future, stream, stream::BoxStream, Stream, StreamExt, TryStreamExt};
+use indexmap::IndexMap;
+use std::time::Duration;
+
+/// Checks that the minimum timestamp of each group does not go
+/// backward compared to the previous group.
+///
+/// A temporal inversion panics in debug builds and is emitted as
+/// [SyncError::TemporalInversion] in release builds. Groups without
+/// messages are passed through unchecked.
+pub fn validate_ordering<K, T, S>(stream: S) -> impl Stream<Item = Result<IndexMap<K, T>>>
+where
+    K: Key,
+    T: WithTimestamp,
+    S: Stream<Item = Result<IndexMap<K, T>>>,
+{
+    let mut prev_min_ts: Option<Duration> = None;
+
+    stream.map(move |group| {
+        let group = group?;
+        let Some(min_ts) = group.values().map(|item| item.timestamp()).min() else {
+            return Ok(group);
+        };
+
+        if let Some(prev_min_ts) = prev_min_ts {
+            let inversion = SyncError::TemporalInversion {
+                timestamp: min_ts,
+                previous: prev_min_ts,
+            };
+            debug_assert!(min_ts >= prev_min_ts, "{inversion}");
+            if min_ts < prev_min_ts {
+                return Err(inversion.into());
+            }
+        }
+
+        prev_min_ts = Some(min_ts);
+        Ok(group)
+    })
+}
+
+/// A synchronized group used as a message for another
+/// synchronizer. Its timestamp is the minimum timestamp of the group.
+#[derive(Debug, Clone)]
+pub struct GroupTimestamped<K, T> {
+    group: IndexMap<K, T>,
+    timestamp: Duration,
+}
+
+impl<K, T> GroupTimestamped<K, T>
+where
+    T: WithTimestamp,
+{
+    /// Wraps the group. Returns `None` if the group is empty.
+    pub fn new(group: IndexMap<K, T>) -> Option<Self> {
+        let timestamp = group.values().map(|item| item.timestamp()).min()?;
+        Some(Self { group, timestamp })
+    }
+
+    pub fn group(&self) -> &IndexMap<K, T> {
+        &self.group
+    }
+
+    pub fn into_group(self) -> IndexMap<K, T> {
+        self.group
+    }
+}
+
+impl<K, T> WithTimestamp for GroupTimestamped<K, T>
+where
+    K: Send,
+    T: WithTimestamp,
+{
+    fn timestamp(&self) -> Duration {
+        self.timestamp
+    }
+}
+
+/// Converts the groups emitted by [sync](crate::sync()) to messages
+/// identified by `outer_key`, so that they can be synchronized with
+/// other streams by a second-level synchronizer. Empty groups are
+/// skipped.
+pub fn group_to_stream<K1, K2, T, S>(
+    groups: S,
+    outer_key: K2,
+) -> impl Stream<Item = Result<(K2, GroupTimestamped<K1, T>)>> + Unpin + Send
+where
+    K1: Key,
+    K2: Key,
+    T: WithTimestamp,
+    S: Stream<Item = Result<IndexMap<K1, T>>> + Unpin + Send,
+{
+    groups.try_filter_map(move |group| {
+        let item = GroupTimestamped::new(group).map(|group| (outer_key.clone(), group));
+        future::ready(Ok(item))
+    })
+}
+
+/// Runs the closure on every group emitted by the stream without
+/// consuming it, e.g. for logging or metrics. Errors are passed
+/// through.
+pub fn inspect_groups<'a, K, T, F>(stream: OutputStream<'a, K, T>, f: F) -> OutputStream<'a, K, T>
+where
+    K: 'a,
+    T: 'a,
+    F: Fn(&IndexMap<K, T>) + Send + 'a,
+{
+    OutputStream::new(stream.into_inner().inspect_ok(f).boxed())
+}
+
+/// Runs the closure on every error emitted by the stream. Groups are
+/// passed through.
+pub fn inspect_errors<'a, K, T, F>(stream: OutputStream<'a, K, T>, f: F) -> OutputStream<'a, K, T>
+where
+    K: 'a,
+    T: 'a,
+    F: Fn(&eyre::Report) + Send + 'a,
+{
+    OutputStream::new(stream.into_inner().inspect_err(f).boxed())
+}
+
+/// Runs the closure on every item emitted by the stream, either a
+/// group or an error.
+pub fn inspect_all<'a, K, T, F>(stream: OutputStream<'a, K, T>, f: F) -> OutputStream<'a, K, T>
+where
+    K: 'a,
+    T: 'a,
+    F: Fn(Result<&IndexMap<K, T>, &eyre::Report>) + Send + 'a,
+{
+    OutputStream::new(
+        stream
+            .into_inner()
+            .inspect(move |item| f(item.as_ref()))
+            .boxed(),
+    )
+}
+
+/// Inserts synthetic groups for missing windows, e.g. for real-time
+/// display where stale data is better than none.
+///
+/// The anchor of a group is its minimum timestamp. When consecutive
+/// groups are more than `2 * window_size` apart, the last group is
+/// repeated every `2 * window_size` in between, annotated with
+/// [GroupAnnotation::Synthetic]. Errors are passed through.
+pub fn fill_gaps<'a, K, T>(
+    stream: OutputStream<'a, K, T>,
+    window_size: Duration,
+) -> BoxStream<'a, Result<Group<K, T>>>
+where
+    K: Key + 'a,
+    T: WithTimestamp + Clone + 'a,
+{
+    let slot = window_size.saturating_mul(2);
+    let mut last: Option<(Duration, IndexMap<K, T>)> = None;
+
+    stream
+        .flat_map(move |group| {
+            let group = match group {
+                Ok(group) => group,
+                Err(err) => return stream::iter(vec![Err(err)]),
+            };
+            let Some(anchor) = group.values().map(|item| item.timestamp()).min() else {
+                return stream::iter(vec![Ok(group.into())]);
+            };
+
+            let mut groups = vec![];
+            if let Some((mut last_anchor, last_group)) = last.take() {
+                while !slot.is_zero() && anchor.saturating_sub(last_anchor) > slot {
+                    last_anchor += slot;
+                    groups.push(Ok(Group {
+                        items: last_group.clone(),
+                        annotation: GroupAnnotation::Synthetic {
+                            timestamp: last_anchor,
+                        },
+                    }));
+                }
+            }
+
+            last = Some((anchor, group.clone()));
+            groups.push(Ok(group.into()));
+            stream::iter(groups)
+        })
+        .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{stream, TryStreamExt};
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct TestMessage(Duration);
+
+    impl WithTimestamp for TestMessage {
+        fn timestamp(&self) -> Duration {
+            self.0
+        }
+    }
+
+    fn create_group(timestamps_ms: &[(&'static str, u64)]) -> IndexMap<&'static str, TestMessage> {
+        timestamps_ms
+            .iter()
+            .map(|&(key, ts)| (key, TestMessage(Duration::from_millis(ts))))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_validate_ordering_accepts_monotonic_groups() {
+        let groups = vec![
+            Ok(create_group(&[("A", 1000), ("B", 1010)])),
+            Ok(create_group(&[("A", 1100), ("B", 1100)])),
+            Ok(create_group(&[("A", 1200), ("B", 1190)])),
+        ];
+
+        let output: Vec<_> = validate_ordering(stream::iter(groups))
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(output.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_group_to_stream_hierarchical_sync() {
+        let config = || crate::Config::basic(Duration::from_millis(50), None, 4);
+
+        // Synchronize the cameras first.
+        let cameras = [1000, 1100, 1200]
+            .into_iter()
+            .flat_map(|ts| [("A", ts), ("B", ts + 5)])
+            .map(|(key, ts)| Ok((key, TestMessage(Duration::from_millis(ts)))));
+        let (camera_groups, _feedback_rx) =
+            crate::sync(stream::iter(cameras), ["A", "B"], config()).unwrap();
+
+        // Then synchronize the camera groups with the LiDAR.
+        let lidar = [1010, 1110, 1210].into_iter().map(|ts| {
+            let group = IndexMap::from([("L", TestMessage(Duration::from_millis(ts)))]);
+            Ok(("lidar", GroupTimestamped::new(group).unwrap()))
+        });
+        let input = stream::select(
+            group_to_stream(camera_groups, "camera"),
+            stream::iter(lidar),
+        );
+        let (output, _feedback_rx) = crate::sync(input, ["camera", "lidar"], config()).unwrap();
+        let groups: Vec<_> = output.try_collect().await.unwrap();
+
+        assert_eq!(groups.len(), 3);
+        for (group, ts) in groups.iter().zip([1000, 1100, 1200]) {
+            let camera = group["camera"].group();
+            assert_eq!(group["camera"].timestamp(), Duration::from_millis(ts));
+            assert_eq!(camera["B"], TestMessage(Duration::from_millis(ts + 5)));
+            assert_eq!(group["lidar"].timestamp(), Duration::from_millis(ts + 10));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_inspect_groups_and_errors() {
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering::SeqCst},
+            Arc,
+        };
+
+        let output = || {
+            let items = vec![
+                Ok(create_group(&[("A", 1000), ("B", 1010)])),
+                Err(eyre::eyre!("error")),
+                Ok(create_group(&[("A", 1100), ("B", 1110)])),
+            ];
+            OutputStream::new(stream::iter(items).boxed())
+        };
+        let groups = Arc::new(AtomicUsize::new(0));
+        let errors = Arc::new(AtomicUsize::new(0));
+        let items = Arc::new(AtomicUsize::new(0));
+
+        let counter = groups.clone();
+        let stream = inspect_groups(output(), move |_| {
+            counter.fetch_add(1, SeqCst);
+        });
+        let counter = errors.clone();
+        let stream = inspect_errors(stream, move |err| {
+            assert_eq!(err.to_string(), "error");
+            counter.fetch_add(1, SeqCst);
+        });
+        let counter = items.clone();
+        let stream = inspect_all(stream, move |_| {
+            counter.fetch_add(1, SeqCst);
+        });
+
+        let output: Vec<_> = stream.collect().await;
+        assert_eq!(output.len(), 3);
+        assert_eq!(groups.load(SeqCst), 2);
+        assert_eq!(errors.load(SeqCst), 1);
+        assert_eq!(items.load(SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_fill_gaps() {
+        let items = vec![
+            Ok(create_group(&[("A", 1000), ("B", 1010)])),
+            Ok(create_group(&[("A", 1100), ("B", 1110)])),
+            Ok(create_group(&[("A", 1450), ("B", 1440)])),
+        ];
+        let output = OutputStream::new(stream::iter(items).boxed());
+
+        let groups: Vec<_> = fill_gaps(output, Duration::from_millis(50))
+            .try_collect()
+            .await
+            .unwrap();
+        let annotations: Vec<_> = groups.iter().map(|group| group.annotation).collect();
+        let synthetic = |ms| GroupAnnotation::Synthetic {
+            timestamp: Duration::from_millis(ms),
+        };
+        assert_eq!(
+            annotations,
+            [
+                GroupAnnotation::Real,
+                GroupAnnotation::Real,
+                synthetic(1200),
+                synthetic(1300),
+                synthetic(1400),
+                GroupAnnotation::Real,
+            ]
+        );
+        assert_eq!(groups[2].items, groups[1].items);
+        assert!(groups[3].is_synthetic());
+    }
+
+    #[tokio::test]
+    #[cfg_attr(debug_assertions, should_panic(expected = "temporal inversion"))]
+    async fn test_validate_ordering_rejects_inversion() {
+        let groups = vec![
+            Ok(create_group(&[("A", 1100), ("B", 1110)])),
+            Ok(create_group(&[("A", 1000), ("B", 1010)])),
+        ];
+
+        let result: Result<Vec<_>> = validate_ordering(stream::iter(groups)).try_collect().await;
+        assert_eq!(
+            result.unwrap_err().downcast_ref::<SyncError>(),
+            Some(&SyncError::TemporalInversion {
+                timestamp: Duration::from_millis(1000),
+                previous: Duration::from_millis(1100),
+            })
+        );
+    }
+}
@@ -1,17 +1,289 @@
-use crate::staleness::StalenessConfig;
-use std::time::Duration;
+use crate::{staleness::StalenessConfig, types::Key, SyncError};
+use indexmap::IndexMap;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    ops::Deref,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
 /// Configuration parameters that are passed to [sync](crate::sync());
+///
+/// New options may be added in minor releases. Create the config with
+/// a constructor such as [Config::basic] or [Config::builder] and set
+/// the options with the `with_*` methods.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
 pub struct Config {
     /// The time span that the grouped frames must fit within.
+    #[cfg_attr(feature = "serde", serde(with = "crate::duration_millis"))]
     pub window_size: Duration,
-    /// Accepted minimum timestamps for input frames.
+    /// The exclusive lower bound of accepted timestamps for input
+    /// frames. Frames with `timestamp <= start_time` are rejected.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::duration_millis::option")
+    )]
     pub start_time: Option<Duration>,
     /// The maximum number of frames kept for each input stream.
     pub buf_size: usize,
     /// Staleness detection configuration (optional)
     pub staleness_config: Option<StalenessConfig>,
+    /// The windowing mode used to group messages.
+    pub window_mode: WindowMode,
+    /// The policy that decides whether the window size changes over
+    /// time.
+    pub window_size_policy: WindowSizePolicy,
+    /// The maximum number of frames kept across all input streams
+    /// combined (optional).
+    pub max_total_pending: Option<usize>,
+    /// The minimum number of streams that must remain registered.
+    /// Synchronization terminates when ended streams leave fewer
+    /// streams than this number.
+    pub minimum_streams_required: usize,
+    /// The callback invoked once the feedback receiver is dropped
+    /// (optional).
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub on_feedback_dropped: Option<FeedbackDroppedCallback>,
+    /// Skip emitted groups identical to a recently emitted one, which
+    /// happens when an at-least-once source redelivers messages.
+    pub dedup_output: bool,
+    /// The number of recent group fingerprints remembered for
+    /// deduplication. It must be positive if `dedup_output` is set.
+    pub dedup_history: usize,
+    /// Send a separate feedback to each stream telling whether its
+    /// messages are accepted, in addition to the shared feedback.
+    pub per_key_feedback: bool,
+    /// The expected worst-case one-way timing variance per stream,
+    /// not the total spread (optional). It is added to the window
+    /// size, widening both window boundaries.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::duration_millis::option")
+    )]
+    pub jitter_tolerance: Option<Duration>,
+    /// The strategy that picks the message of each stream within the
+    /// window. A message priority only breaks its ties.
+    pub matching_strategy: MatchingStrategy,
+    /// The wall-clock time to wait for a new message before forming a
+    /// group from the buffered messages anyway (optional). It is
+    /// ignored by [SyncIter](crate::SyncIter).
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::duration_millis::option")
+    )]
+    pub flush_timeout: Option<Duration>,
+    /// Whether to keep messages of the same stream sharing a
+    /// timestamp instead of replacing the buffered one with the newer
+    /// one.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub allow_equal_timestamps: bool,
+    /// What to do when a buffer reaches its capacity.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub drop_policy: DropPolicy,
+    /// How far behind the latest message of the same stream a message
+    /// may be and still be buffered in timestamp order. Messages
+    /// behind by more are dropped.
+    #[cfg_attr(feature = "serde", serde(default, with = "crate::duration_millis"))]
+    pub out_of_order_tolerance: Duration,
+}
+
+/// Formats the config with human-readable durations, e.g.
+/// `Config { window_size: 50ms, buf_size: 16, start_time: none }`.
+///
+/// Other options are only listed if they differ from
+/// [Config::basic], e.g. `Config { ..., matching_strategy: Closest }`.
+#[cfg(feature = "humantime")]
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use humantime::format_duration;
+
+        write!(
+            f,
+            "Config {{ window_size: {}, buf_size: {}, start_time: ",
+            format_duration(self.window_size),
+            self.buf_size
+        )?;
+        match self.start_time {
+            Some(start_time) => write!(f, "{}", format_duration(start_time))?,
+            None => write!(f, "none")?,
+        }
+        if self.staleness_config.is_some() {
+            write!(f, ", staleness_config: set")?;
+        }
+        if let WindowMode::Session { gap } = self.window_mode {
+            write!(
+                f,
+                ", window_mode: session with gap {}",
+                format_duration(gap)
+            )?;
+        }
+        if let WindowSizePolicy::Adaptive {
+            min,
+            max,
+            percentile,
+        } = self.window_size_policy
+        {
+            write!(
+                f,
+                ", window_size_policy: adaptive within {} to {} at percentile {percentile}",
+                format_duration(min),
+                format_duration(max)
+            )?;
+        }
+        if let Some(max_total_pending) = self.max_total_pending {
+            write!(f, ", max_total_pending: {max_total_pending}")?;
+        }
+        if self.minimum_streams_required != 1 {
+            write!(
+                f,
+                ", minimum_streams_required: {}",
+                self.minimum_streams_required
+            )?;
+        }
+        if self.on_feedback_dropped.is_some() {
+            write!(f, ", on_feedback_dropped: set")?;
+        }
+        if self.dedup_output {
+            write!(f, ", dedup_history: {}", self.dedup_history)?;
+        }
+        if self.per_key_feedback {
+            write!(f, ", per_key_feedback: true")?;
+        }
+        if let Some(jitter_tolerance) = self.jitter_tolerance {
+            write!(
+                f,
+                ", jitter_tolerance: {}",
+                format_duration(jitter_tolerance)
+            )?;
+        }
+        if self.matching_strategy != MatchingStrategy::First {
+            write!(f, ", matching_strategy: {:?}", self.matching_strategy)?;
+        }
+        if let Some(flush_timeout) = self.flush_timeout {
+            write!(f, ", flush_timeout: {}", format_duration(flush_timeout))?;
+        }
+        if self.allow_equal_timestamps {
+            write!(f, ", allow_equal_timestamps: true")?;
+        }
+        if self.drop_policy != DropPolicy::DropOldest {
+            write!(f, ", drop_policy: {:?}", self.drop_policy)?;
+        }
+        if self.out_of_order_tolerance > Duration::ZERO {
+            write!(
+                f,
+                ", out_of_order_tolerance: {}",
+                format_duration(self.out_of_order_tolerance)
+            )?;
+        }
+        write!(f, " }}")
+    }
+}
+
+/// A callback invoked when the feedback receiver is dropped, so that
+/// input sources can learn that backpressure is no longer available.
+#[derive(Clone)]
+pub struct FeedbackDroppedCallback(Arc<dyn Fn() + Send + Sync>);
+
+impl FeedbackDroppedCallback {
+    pub fn new<F>(callback: F) -> Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        Self(Arc::new(callback))
+    }
+
+    pub fn call(&self) {
+        (self.0)()
+    }
+}
+
+impl fmt::Debug for FeedbackDroppedCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("FeedbackDroppedCallback")
+    }
+}
+
+/// The windowing mode that decides how messages are grouped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum WindowMode {
+    /// Group one message from each stream within a time window that
+    /// slides along with the input.
+    #[default]
+    Sliding,
+    /// Group messages by activity. A session is closed when no
+    /// message arrives for `gap` and the latest message of each
+    /// stream within the session is emitted.
+    ///
+    /// Sessions keep only the latest message of each stream, so
+    /// `per_key_feedback`, `jitter_tolerance`, `flush_timeout`,
+    /// `allow_equal_timestamps`, `drop_policy` and
+    /// `out_of_order_tolerance` are not supported and must be left at
+    /// their defaults. Neither are the `timestamp_offsets`,
+    /// `required_keys` and `per_key_buf_sizes` of [KeyOptions].
+    Session {
+        #[cfg_attr(feature = "serde", serde(with = "crate::duration_millis"))]
+        gap: Duration,
+    },
+}
+
+/// The strategy that picks the message of each stream among those
+/// within the time window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum MatchingStrategy {
+    /// Pick the earliest message within the window.
+    #[default]
+    First,
+    /// Pick the latest message within the window.
+    Last,
+    /// Pick the message closest to the window anchor, which is the
+    /// latest front timestamp unless anchor weights are set.
+    Closest,
+    /// Pick the message closest to the midpoint between the latest
+    /// front timestamp and the earliest back timestamp.
+    ClosestToCenter,
+}
+
+/// The policy applied when a buffer reaches its capacity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum DropPolicy {
+    /// Drop the oldest message among all buffers if no group can be
+    /// formed while all buffers are full.
+    #[default]
+    DropOldest,
+    /// Reject a message arriving to a full buffer. The oldest message
+    /// is still dropped if all buffers are full and no group can be
+    /// formed, since no more messages could be accepted otherwise.
+    DropNewest,
+    /// Yield [SyncError::BufferOverflow] from the output stream and
+    /// stop consuming the input if no group can be formed while all
+    /// buffers are full.
+    ReturnError,
+}
+
+/// The policy that decides how the window size evolves.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum WindowSizePolicy {
+    /// Keep `window_size` unchanged.
+    #[default]
+    Fixed,
+    /// Adjust the window size after each group to the `percentile`
+    /// (within 0.0 to 1.0) of recent group timestamp spreads, scaled
+    /// by a safety factor and clamped to `min` and `max`.
+    Adaptive {
+        #[cfg_attr(feature = "serde", serde(with = "crate::duration_millis"))]
+        min: Duration,
+        #[cfg_attr(feature = "serde", serde(with = "crate::duration_millis"))]
+        max: Duration,
+        percentile: f64,
+    },
 }
 
 impl Config {
@@ -23,26 +295,560 @@ impl Config {
         staleness_config: StalenessConfig,
     ) -> Self {
         Self {
-            window_size,
-            start_time,
-            buf_size,
             staleness_config: Some(staleness_config),
+            ..Self::basic(window_size, start_time, buf_size)
         }
     }
 
+    /// Create a [ConfigBuilder] with the default parameters.
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+
     /// Create a basic Config without staleness detection
     pub fn basic(window_size: Duration, start_time: Option<Duration>, buf_size: usize) -> Self {
         Self {
             window_size,
             start_time,
             buf_size,
-            staleness_config: None,
+            ..Default::default()
         }
     }
 
+    /// Create a basic Config for streams publishing at `rate_hz`.
+    ///
+    /// The window size is `jitter_fraction` of the message period,
+    /// and each buffer holds `buffer_seconds` of messages.
+    pub fn from_rate(
+        rate_hz: f64,
+        jitter_fraction: f64,
+        buffer_seconds: f64,
+    ) -> Result<Self, SyncError> {
+        let mut errors = vec![];
+        let is_positive = |value: f64| value.is_finite() && value > 0.0;
+        if !is_positive(rate_hz) {
+            errors.push(ConfigError::InvalidRate(rate_hz));
+        }
+        if !is_positive(jitter_fraction) {
+            errors.push(ConfigError::InvalidJitterFraction(jitter_fraction));
+        }
+        if !is_positive(buffer_seconds) {
+            errors.push(ConfigError::InvalidBufferSeconds(buffer_seconds));
+        }
+        if !errors.is_empty() {
+            return Err(errors.into());
+        }
+
+        // A window too large to represent is rejected like a zero
+        // window.
+        let window_size =
+            Duration::try_from_secs_f64(jitter_fraction / rate_hz).unwrap_or(Duration::ZERO);
+        if window_size == Duration::ZERO {
+            errors.push(ConfigError::WindowSizeZero);
+        }
+        let buf_size = (buffer_seconds * rate_hz).ceil() as usize;
+        if buf_size < 2 {
+            errors.push(ConfigError::BufSizeTooSmall(buf_size));
+        }
+        if !errors.is_empty() {
+            return Err(errors.into());
+        }
+
+        Ok(Self::basic(window_size, None, buf_size))
+    }
+
+    /// Set the start time. Frames with timestamps at or before it are
+    /// rejected
+    pub fn starting_at(mut self, start_time: Duration) -> Self {
+        self.start_time = Some(start_time);
+        self
+    }
+
+    /// Set the start time from a system time for messages stamped by
+    /// [SystemTimestamped](crate::SystemTimestamped). Times before
+    /// the Unix epoch are clamped to zero.
+    pub fn with_start_system_time(self, start_time: SystemTime) -> Self {
+        self.starting_at(crate::duration_since_epoch(start_time))
+    }
+
     /// Enable staleness detection on an existing config
     pub fn enable_staleness(mut self, staleness_config: StalenessConfig) -> Self {
         self.staleness_config = Some(staleness_config);
         self
     }
+
+    /// Set the windowing mode
+    pub fn with_window_mode(mut self, window_mode: WindowMode) -> Self {
+        self.window_mode = window_mode;
+        self
+    }
+
+    /// Limit the number of frames kept across all input streams
+    pub fn with_max_total_pending(mut self, max_total_pending: usize) -> Self {
+        self.max_total_pending = Some(max_total_pending);
+        self
+    }
+
+    /// Set the minimum number of streams that must remain registered
+    pub fn with_minimum_streams_required(mut self, minimum_streams_required: usize) -> Self {
+        self.minimum_streams_required = minimum_streams_required;
+        self
+    }
+
+    /// Set the callback invoked once the feedback receiver is dropped
+    pub fn with_on_feedback_dropped<F>(mut self, callback: F) -> Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.on_feedback_dropped = Some(FeedbackDroppedCallback::new(callback));
+        self
+    }
+
+    /// Skip groups identical to one of the last `dedup_history`
+    /// emitted groups
+    pub fn with_dedup_output(mut self, dedup_history: usize) -> Self {
+        self.dedup_output = true;
+        self.dedup_history = dedup_history;
+        self
+    }
+
+    /// Send a separate feedback to each stream
+    pub fn with_per_key_feedback(mut self) -> Self {
+        self.per_key_feedback = true;
+        self
+    }
+
+    /// Widen the window by the expected worst-case one-way timing
+    /// variance per stream
+    pub fn with_jitter_tolerance(mut self, jitter_tolerance: Duration) -> Self {
+        self.jitter_tolerance = Some(jitter_tolerance);
+        self
+    }
+
+    /// Set the strategy that picks messages within the window
+    pub fn with_matching_strategy(mut self, matching_strategy: MatchingStrategy) -> Self {
+        self.matching_strategy = matching_strategy;
+        self
+    }
+
+    /// Set the wall-clock time to wait before flushing buffered
+    /// messages
+    pub fn with_flush_timeout(mut self, flush_timeout: Duration) -> Self {
+        self.flush_timeout = Some(flush_timeout);
+        self
+    }
+
+    /// Keep messages of the same stream sharing a timestamp
+    pub fn with_equal_timestamps(mut self) -> Self {
+        self.allow_equal_timestamps = true;
+        self
+    }
+
+    /// Set the policy applied when a buffer reaches its capacity
+    pub fn with_drop_policy(mut self, drop_policy: DropPolicy) -> Self {
+        self.drop_policy = drop_policy;
+        self
+    }
+
+    /// Accept messages arriving up to `out_of_order_tolerance`
+    /// behind the latest message of the same stream
+    pub fn with_out_of_order_tolerance(mut self, out_of_order_tolerance: Duration) -> Self {
+        self.out_of_order_tolerance = out_of_order_tolerance;
+        self
+    }
+
+    /// Set the window size policy
+    pub fn with_window_size_policy(mut self, window_size_policy: WindowSizePolicy) -> Self {
+        self.window_size_policy = window_size_policy;
+        self
+    }
+
+    /// Check the configuration and collect all errors found.
+    ///
+    /// Checks depending on the keys passed to
+    /// [sync](crate::sync()), such as `max_total_pending`, are
+    /// performed when the synchronizer starts.
+    pub fn validate(&self) -> Result<(), Vec<ConfigError>> {
+        let mut errors = vec![];
+
+        if self.window_size == Duration::ZERO {
+            errors.push(ConfigError::WindowSizeZero);
+        }
+        if self.buf_size < 2 {
+            errors.push(ConfigError::BufSizeTooSmall(self.buf_size));
+        }
+        if let Some(start_time) = self.start_time {
+            // A start time far in the future rejects all messages.
+            if start_time > Duration::from_secs(u32::MAX as u64) {
+                errors.push(ConfigError::UnreasonableStartTime(start_time));
+            }
+        }
+        if let WindowMode::Session { gap } = self.window_mode {
+            if gap == Duration::ZERO {
+                errors.push(ConfigError::SessionGapZero);
+            }
+            if self.per_key_feedback {
+                errors.push(ConfigError::UnsupportedInSessionMode("per_key_feedback"));
+            }
+            if self.jitter_tolerance.is_some() {
+                errors.push(ConfigError::UnsupportedInSessionMode("jitter_tolerance"));
+            }
+            if self.flush_timeout.is_some() {
+                errors.push(ConfigError::UnsupportedInSessionMode("flush_timeout"));
+            }
+            if self.allow_equal_timestamps {
+                errors.push(ConfigError::UnsupportedInSessionMode(
+                    "allow_equal_timestamps",
+                ));
+            }
+            if self.drop_policy != DropPolicy::DropOldest {
+                errors.push(ConfigError::UnsupportedInSessionMode("drop_policy"));
+            }
+            if self.out_of_order_tolerance > Duration::ZERO {
+                errors.push(ConfigError::UnsupportedInSessionMode(
+                    "out_of_order_tolerance",
+                ));
+            }
+        }
+        if let WindowSizePolicy::Adaptive {
+            min,
+            max,
+            percentile,
+        } = self.window_size_policy
+        {
+            if min == Duration::ZERO || min > max {
+                errors.push(ConfigError::InvalidAdaptiveRange { min, max });
+            }
+            if !(0.0..=1.0).contains(&percentile) {
+                errors.push(ConfigError::InvalidPercentile(percentile));
+            }
+        }
+        if self.minimum_streams_required == 0 {
+            errors.push(ConfigError::MinimumStreamsRequiredZero);
+        }
+        if self.flush_timeout == Some(Duration::ZERO) {
+            errors.push(ConfigError::FlushTimeoutZero);
+        }
+        if self.dedup_output && self.dedup_history == 0 {
+            errors.push(ConfigError::DedupHistoryZero);
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+impl Default for Config {
+    /// Create a basic Config with a window size of 100 ms and a buffer
+    /// size of 16
+    fn default() -> Self {
+        Self {
+            window_size: Duration::from_millis(100),
+            start_time: None,
+            buf_size: 16,
+            staleness_config: None,
+            window_mode: WindowMode::Sliding,
+            window_size_policy: WindowSizePolicy::Fixed,
+            max_total_pending: None,
+            minimum_streams_required: 1,
+            on_feedback_dropped: None,
+            dedup_output: false,
+            dedup_history: 0,
+            per_key_feedback: false,
+            jitter_tolerance: None,
+            matching_strategy: MatchingStrategy::First,
+            flush_timeout: None,
+            allow_equal_timestamps: false,
+            drop_policy: DropPolicy::DropOldest,
+            out_of_order_tolerance: Duration::ZERO,
+        }
+    }
+}
+
+/// Options assigned to individual streams, which are passed to
+/// [sync_with_options](crate::sync_with_options()) along with the
+/// [Config]. Streams are identified by the same keys as the input.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct KeyOptions<K> {
+    /// Reliability weights for each stream used to compute the window
+    /// anchor (optional). Streams not listed have a weight of 1.0.
+    /// Weighted groups may span up to twice the window size.
+    pub anchor_weights: Option<IndexMap<K, f64>>,
+    /// Signed clock offsets in nanoseconds for each stream
+    /// (optional). The offset is added to message timestamps before
+    /// matching, while emitted messages keep their original
+    /// timestamps. Streams not listed have no offset.
+    pub timestamp_offsets: Option<IndexMap<K, i64>>,
+    /// The keys that must be present in every group (optional). Other
+    /// keys are optional and join a group only when a message falls
+    /// within the window. All keys are required if not set.
+    pub required_keys: Option<Vec<K>>,
+    /// The maximum number of frames kept for each listed input
+    /// stream (optional). Streams not listed keep up to
+    /// [buf_size](Config::buf_size) frames.
+    pub per_key_buf_sizes: Option<IndexMap<K, usize>>,
+}
+
+impl<K> KeyOptions<K>
+where
+    K: Key,
+{
+    /// Set the reliability weights used to compute the window anchor
+    pub fn with_anchor_weights(mut self, anchor_weights: IndexMap<K, f64>) -> Self {
+        self.anchor_weights = Some(anchor_weights);
+        self
+    }
+
+    /// Set the clock offsets in nanoseconds for each stream
+    pub fn with_timestamp_offsets(mut self, timestamp_offsets: IndexMap<K, i64>) -> Self {
+        self.timestamp_offsets = Some(timestamp_offsets);
+        self
+    }
+
+    /// Set the keys that must be present in every group
+    pub fn with_required_keys(mut self, required_keys: Vec<K>) -> Self {
+        self.required_keys = Some(required_keys);
+        self
+    }
+
+    /// Set the buffer sizes of individual streams
+    pub fn with_per_key_buf_sizes(mut self, per_key_buf_sizes: IndexMap<K, usize>) -> Self {
+        self.per_key_buf_sizes = Some(per_key_buf_sizes);
+        self
+    }
+
+    /// Check the options against the config and collect all errors
+    /// found. Keys are identified by their position in the option.
+    ///
+    /// Checks depending on the keys passed to the synchronizer, such
+    /// as whether the required keys are registered, are performed when
+    /// the synchronizer starts.
+    pub fn validate(&self, config: &Config) -> Result<(), Vec<ConfigError>> {
+        let mut errors = vec![];
+
+        if let Some(anchor_weights) = &self.anchor_weights {
+            for (key_index, &weight) in anchor_weights.values().enumerate() {
+                if !weight.is_finite() || weight < 0.0 {
+                    errors.push(ConfigError::InvalidAnchorWeight { key_index, weight });
+                }
+            }
+        }
+        if let Some(per_key_buf_sizes) = &self.per_key_buf_sizes {
+            for (key_index, &buf_size) in per_key_buf_sizes.values().enumerate() {
+                if buf_size < 2 {
+                    errors.push(ConfigError::PerKeyBufSizeTooSmall {
+                        key_index,
+                        buf_size,
+                    });
+                }
+            }
+        }
+        if self
+            .required_keys
+            .as_ref()
+            .is_some_and(|keys| keys.is_empty())
+        {
+            errors.push(ConfigError::RequiredKeysEmpty);
+        }
+        if let WindowMode::Session { .. } = config.window_mode {
+            if self.timestamp_offsets.is_some() {
+                errors.push(ConfigError::UnsupportedInSessionMode("timestamp_offsets"));
+            }
+            if self.required_keys.is_some() {
+                errors.push(ConfigError::UnsupportedInSessionMode("required_keys"));
+            }
+            if self.per_key_buf_sizes.is_some() {
+                errors.push(ConfigError::UnsupportedInSessionMode("per_key_buf_sizes"));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+impl<K> Default for KeyOptions<K> {
+    /// Create options assigning nothing to any stream
+    fn default() -> Self {
+        Self {
+            anchor_weights: None,
+            timestamp_offsets: None,
+            required_keys: None,
+            per_key_buf_sizes: None,
+        }
+    }
+}
+
+/// A builder of [Config] that validates the parameters on
+/// [build](ConfigBuilder::build).
+///
+/// The default window size is 100 ms and the default buffer size is
+/// 16. No start time is set by default.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl ConfigBuilder {
+    /// Set the window size
+    pub fn window_size(mut self, window_size: Duration) -> Self {
+        self.config.window_size = window_size;
+        self
+    }
+
+    /// Set the start time. Frames with timestamps at or before it are
+    /// rejected
+    pub fn start_time(mut self, start_time: Duration) -> Self {
+        self.config.start_time = Some(start_time);
+        self
+    }
+
+    /// Set the maximum number of frames kept for each input stream
+    pub fn buf_size(mut self, buf_size: usize) -> Self {
+        self.config.buf_size = buf_size;
+        self
+    }
+
+    /// Validate the parameters and build the [Config].
+    pub fn build(self) -> Result<Config, SyncError> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
+/// An error found by [Config::validate].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    /// The window size is zero.
+    WindowSizeZero,
+    /// The buffer size is less than 2.
+    BufSizeTooSmall(usize),
+    /// The start time is so far in the future that all messages are
+    /// rejected.
+    UnreasonableStartTime(Duration),
+    /// The anchor weight is negative or not finite.
+    InvalidAnchorWeight { key_index: usize, weight: f64 },
+    /// The session gap is zero.
+    SessionGapZero,
+    /// The named option is set in session window mode, which does not
+    /// support it.
+    UnsupportedInSessionMode(&'static str),
+    /// The adaptive window size range is empty or starts at zero.
+    InvalidAdaptiveRange { min: Duration, max: Duration },
+    /// The percentile is not within 0.0 to 1.0.
+    InvalidPercentile(f64),
+    /// The minimum number of streams required is zero.
+    MinimumStreamsRequiredZero,
+    /// Deduplication is enabled without remembering any group.
+    DedupHistoryZero,
+    /// The list of required keys is empty.
+    RequiredKeysEmpty,
+    /// The buffer size of a stream is less than 2.
+    PerKeyBufSizeTooSmall { key_index: usize, buf_size: usize },
+    /// The flush timeout is zero.
+    FlushTimeoutZero,
+    /// The stream rate given to [Config::from_rate] is not positive.
+    InvalidRate(f64),
+    /// The jitter fraction given to [Config::from_rate] is not
+    /// positive.
+    InvalidJitterFraction(f64),
+    /// The buffered duration given to [Config::from_rate] is not
+    /// positive.
+    InvalidBufferSeconds(f64),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WindowSizeZero => write!(f, "window size must be positive"),
+            Self::BufSizeTooSmall(size) => write!(f, "buffer size {size} is less than 2"),
+            Self::UnreasonableStartTime(start_time) => {
+                write!(f, "unreasonable start time {start_time:?}")
+            }
+            Self::InvalidAnchorWeight { key_index, weight } => {
+                write!(f, "invalid anchor weight {weight} for key {key_index}")
+            }
+            Self::SessionGapZero => write!(f, "session gap must be positive"),
+            Self::UnsupportedInSessionMode(option) => {
+                write!(f, "{option} is not supported in session window mode")
+            }
+            Self::InvalidAdaptiveRange { min, max } => {
+                write!(f, "invalid adaptive window size range {min:?} to {max:?}")
+            }
+            Self::InvalidPercentile(percentile) => {
+                write!(f, "percentile {percentile} is not within 0.0 to 1.0")
+            }
+            Self::MinimumStreamsRequiredZero => {
+                write!(f, "minimum number of streams required must be positive")
+            }
+            Self::DedupHistoryZero => write!(f, "dedup history must be positive"),
+            Self::RequiredKeysEmpty => write!(f, "required keys must not be empty"),
+            Self::PerKeyBufSizeTooSmall {
+                key_index,
+                buf_size,
+            } => write!(
+                f,
+                "buffer size {buf_size} for key {key_index} is less than 2"
+            ),
+            Self::FlushTimeoutZero => write!(f, "flush timeout must be positive"),
+            Self::InvalidRate(rate_hz) => write!(f, "stream rate {rate_hz} Hz is not positive"),
+            Self::InvalidJitterFraction(jitter_fraction) => {
+                write!(f, "jitter fraction {jitter_fraction} is not positive")
+            }
+            Self::InvalidBufferSeconds(buffer_seconds) => {
+                write!(f, "buffered duration {buffer_seconds} s is not positive")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// A [Config] that passes [Config::validate].
+#[derive(Debug, Clone)]
+pub struct ValidatedConfig(Config);
+
+impl ValidatedConfig {
+    /// Unwraps the validated config.
+    pub fn into_inner(self) -> Config {
+        self.0
+    }
+}
+
+impl Deref for ValidatedConfig {
+    type Target = Config;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl TryFrom<Config> for ValidatedConfig {
+    type Error = Vec<ConfigError>;
+
+    fn try_from(config: Config) -> Result<Self, Self::Error> {
+        config.validate()?;
+        Ok(Self(config))
+    }
+}
+
+impl From<Duration> for Config {
+    /// Create a basic Config with the window size and a buffer size of 16
+    fn from(window_size: Duration) -> Self {
+        Self::basic(window_size, None, 16)
+    }
+}
+
+impl From<(Duration, usize)> for Config {
+    /// Create a basic Config with the window size and the buffer size
+    fn from((window_size, buf_size): (Duration, usize)) -> Self {
+        Self::basic(window_size, None, buf_size)
+    }
 }
@@ -0,0 +1,97 @@
+//! Serializes a `Duration` as a floating-point number of milliseconds,
+//! used with `#[serde(with = "crate::duration_millis")]`.
+
+use serde::{de::Error, Deserialize, Deserializer, Serializer};
+use std::time::Duration;
+
+pub fn serialize<S>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_f64(to_millis(*duration))
+}
+
+pub fn deserialize<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let millis = f64::deserialize(deserializer)?;
+    from_millis(millis).map_err(D::Error::custom)
+}
+
+/// The same as the parent module for `Option<Duration>` fields.
+pub mod option {
+    use super::{from_millis, to_millis};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S>(duration: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match duration {
+            Some(duration) => serializer.serialize_some(&to_millis(*duration)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Option::<f64>::deserialize(deserializer)?
+            .map(from_millis)
+            .transpose()
+            .map_err(D::Error::custom)
+    }
+}
+
+/// The same as the parent module for maps of `Option<Duration>`
+/// values.
+pub mod option_map {
+    use super::{from_millis, to_millis};
+    use indexmap::IndexMap;
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+    use std::{hash::Hash, time::Duration};
+
+    pub fn serialize<K, S>(
+        map: &IndexMap<K, Option<Duration>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        K: Serialize,
+        S: Serializer,
+    {
+        serializer.collect_map(
+            map.iter()
+                .map(|(key, duration)| (key, duration.map(to_millis))),
+        )
+    }
+
+    pub fn deserialize<'de, K, D>(
+        deserializer: D,
+    ) -> Result<IndexMap<K, Option<Duration>>, D::Error>
+    where
+        K: Deserialize<'de> + Hash + Eq,
+        D: Deserializer<'de>,
+    {
+        IndexMap::<K, Option<f64>>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(key, millis)| Ok((key, millis.map(from_millis).transpose()?)))
+            .collect::<Result<_, String>>()
+            .map_err(D::Error::custom)
+    }
+}
+
+fn to_millis(duration: Duration) -> f64 {
+    duration.as_nanos() as f64 / 1e6
+}
+
+fn from_millis(millis: f64) -> Result<Duration, String> {
+    if !millis.is_finite() || millis < 0.0 {
+        return Err(format!("invalid duration of {millis} milliseconds"));
+    }
+    // Round to whole nanoseconds so that sub-millisecond durations
+    // survive a round trip.
+    Ok(Duration::from_nanos((millis * 1e6).round() as u64))
+}
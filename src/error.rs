@@ -0,0 +1,163 @@
+use crate::config::ConfigError;
+use std::{convert::Infallible, fmt, time::Duration};
+
+/// An error returned when the synchronizer fails to start, or yielded
+/// by the output stream when a buffer overflows with
+/// [DropPolicy::ReturnError](crate::DropPolicy::ReturnError) or groups
+/// are out of order in [validate_ordering](crate::validate_ordering).
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyncError {
+    /// The window size is zero.
+    InvalidWindowSize,
+    /// The buffer size is less than 2.
+    InsufficientBufferCapacity(usize),
+    /// No keys are passed to the synchronizer.
+    NoKeysProvided,
+    /// The config fails [Config::validate](crate::Config::validate),
+    /// or the options fail
+    /// [KeyOptions::validate](crate::KeyOptions::validate), for other
+    /// reasons.
+    InvalidConfig(Vec<ConfigError>),
+    /// The global limit on pending messages cannot hold two messages
+    /// per stream.
+    MaxTotalPendingTooSmall {
+        max_total_pending: usize,
+        required: usize,
+    },
+    /// Fewer keys are passed than `minimum_streams_required`.
+    TooFewKeys { keys: usize, required: usize },
+    /// A key in [required_keys](crate::KeyOptions::required_keys) is
+    /// not passed to the synchronizer.
+    UnknownRequiredKey,
+    /// Session window mode is not supported by the called API, such
+    /// as the runtime-free synchronizer or
+    /// [sync_with_envelope](crate::sync_with_envelope()).
+    SessionModeUnsupported,
+    /// A key is passed more than once.
+    DuplicateKey,
+    /// The channel size of
+    /// [sync_concurrent](crate::sync_concurrent()) is zero.
+    ChannelSizeZero,
+    /// All buffers are full and no group can be formed. The key is
+    /// identified by its position in the key list, whose buffer holds
+    /// the oldest message.
+    BufferOverflow { key_index: usize },
+    /// The minimum timestamp of a group is before that of the previous
+    /// group.
+    TemporalInversion {
+        timestamp: Duration,
+        previous: Duration,
+    },
+}
+
+impl fmt::Display for SyncError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidWindowSize => write!(f, "window size must be positive"),
+            Self::InsufficientBufferCapacity(size) => {
+                write!(f, "buffer size {size} is less than 2")
+            }
+            Self::NoKeysProvided => write!(f, "no keys are provided"),
+            Self::InvalidConfig(errors) => {
+                write!(f, "invalid config: ")?;
+                for (index, error) in errors.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{error}")?;
+                }
+                Ok(())
+            }
+            Self::MaxTotalPendingTooSmall {
+                max_total_pending,
+                required,
+            } => write!(
+                f,
+                "max total pending {max_total_pending} is less than {required}"
+            ),
+            Self::TooFewKeys { keys, required } => {
+                write!(f, "{keys} keys are provided but {required} are required")
+            }
+            Self::UnknownRequiredKey => write!(f, "a required key is not registered"),
+            Self::SessionModeUnsupported => {
+                write!(f, "session window mode is not supported by this API")
+            }
+            Self::DuplicateKey => write!(f, "a key is passed more than once"),
+            Self::ChannelSizeZero => write!(f, "channel size must be positive"),
+            Self::BufferOverflow { key_index } => {
+                write!(f, "buffer of key {key_index} overflows")
+            }
+            Self::TemporalInversion {
+                timestamp,
+                previous,
+            } => write!(
+                f,
+                "temporal inversion: group at {timestamp:?} is emitted after {previous:?}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SyncError {}
+
+impl From<Vec<ConfigError>> for SyncError {
+    /// Maps the window size and buffer size errors to dedicated
+    /// variants if either is the only error.
+    fn from(errors: Vec<ConfigError>) -> Self {
+        match errors.as_slice() {
+            [ConfigError::WindowSizeZero] => Self::InvalidWindowSize,
+            &[ConfigError::BufSizeTooSmall(size)] => Self::InsufficientBufferCapacity(size),
+            _ => Self::InvalidConfig(errors),
+        }
+    }
+}
+
+impl From<Infallible> for SyncError {
+    fn from(never: Infallible) -> Self {
+        match never {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sync_error_from_config_errors() {
+        assert_eq!(
+            SyncError::from(vec![ConfigError::WindowSizeZero]),
+            SyncError::InvalidWindowSize
+        );
+        assert_eq!(
+            SyncError::from(vec![ConfigError::BufSizeTooSmall(1)]),
+            SyncError::InsufficientBufferCapacity(1)
+        );
+
+        let errors = vec![ConfigError::WindowSizeZero, ConfigError::BufSizeTooSmall(1)];
+        assert_eq!(
+            SyncError::from(errors.clone()),
+            SyncError::InvalidConfig(errors)
+        );
+    }
+
+    #[test]
+    fn test_sync_error_display() {
+        assert_eq!(
+            SyncError::InsufficientBufferCapacity(1).to_string(),
+            "buffer size 1 is less than 2"
+        );
+
+        let error = SyncError::InvalidConfig(vec![
+            ConfigError::WindowSizeZero,
+            ConfigError::BufSizeTooSmall(1),
+        ]);
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "invalid config: {}, {}",
+                ConfigError::WindowSizeZero,
+                ConfigError::BufSizeTooSmall(1)
+            )
+        );
+    }
+}
@@ -0,0 +1,100 @@
+use crate::types::{Key, SyncStats};
+use eyre::{eyre, Result};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::mpsc;
+
+/// The control message sent from [SyncHandle] to a running
+/// synchronizer.
+#[derive(Debug, Clone)]
+pub enum ControlMessage<K>
+where
+    K: Key,
+{
+    /// Do not require the stream to form a group.
+    Pause(K),
+    /// Require the stream to form a group again.
+    Resume(K),
+    /// Drop messages before the timestamp. See
+    /// [State::fast_forward](crate::state::State::fast_forward).
+    FastForward(Duration),
+    /// Register a new stream. See
+    /// [State::add_key](crate::state::State::add_key).
+    AddKey(K),
+    /// Deregister a stream and discard its pending messages. It is
+    /// ignored if fewer than `minimum_streams_required` streams would
+    /// remain.
+    RemoveKey(K),
+}
+
+/// The handle is returned by
+/// [sync_with_handle](crate::sync_with_handle()) to control the
+/// running synchronizer.
+///
+/// Control messages are applied the next time the output stream is
+/// polled. They are ignored in session window mode, where no
+/// statistics are collected either.
+#[derive(Debug, Clone)]
+pub struct SyncHandle<K>
+where
+    K: Key,
+{
+    control_tx: mpsc::UnboundedSender<ControlMessage<K>>,
+    stats: Arc<Mutex<SyncStats<K>>>,
+}
+
+impl<K> SyncHandle<K>
+where
+    K: Key,
+{
+    pub(crate) fn new(
+        control_tx: mpsc::UnboundedSender<ControlMessage<K>>,
+        stats: Arc<Mutex<SyncStats<K>>>,
+    ) -> Self {
+        Self { control_tx, stats }
+    }
+
+    /// Gets a snapshot of the statistics, which are updated each time
+    /// the output stream is polled.
+    pub fn stats(&self) -> SyncStats<K> {
+        self.stats.lock().unwrap().clone()
+    }
+
+    /// Pause the stream identified by the key. Its messages are still
+    /// accepted but the stream is not required to form a group.
+    pub fn pause_key(&self, key: K) -> Result<()> {
+        self.send(ControlMessage::Pause(key))
+    }
+
+    /// Resume a paused stream identified by the key.
+    pub fn resume_key(&self, key: K) -> Result<()> {
+        self.send(ControlMessage::Resume(key))
+    }
+
+    /// Drop buffered messages before the timestamp and reject later
+    /// arriving messages before it.
+    pub fn fast_forward(&self, target_ts: Duration) -> Result<()> {
+        self.send(ControlMessage::FastForward(target_ts))
+    }
+
+    /// Register a new stream identified by the key, such as a sensor
+    /// discovered at runtime. Its messages can be fed to the input
+    /// once the output stream is polled again.
+    pub fn add_key(&self, key: K) -> Result<()> {
+        self.send(ControlMessage::AddKey(key))
+    }
+
+    /// Deregister the stream identified by the key. Its pending
+    /// messages are discarded and later messages are rejected.
+    pub fn remove_key(&self, key: K) -> Result<()> {
+        self.send(ControlMessage::RemoveKey(key))
+    }
+
+    fn send(&self, msg: ControlMessage<K>) -> Result<()> {
+        self.control_tx
+            .send(msg)
+            .map_err(|_| eyre!("the synchronizer is dropped"))
+    }
+}
@@ -8,8 +8,7 @@
 //!     stream,
 //!     stream::{StreamExt, TryStreamExt},
 //! };
-//! use indexmap::IndexMap;
-//! use multi_stream_synchronizer::{sync, Config, WithTimestamp};
+//! use multi_stream_synchronizer::{sync, Config, IndexMap, WithTimestamp};
 //! use std::time::Duration;
 //!
 //! // Define your message type
@@ -45,12 +44,7 @@
 //! .map(|msg| eyre::Ok(msg));
 //!
 //! // Run the synchronization algorithm
-//! let config = Config {
-//!     window_size: Duration::from_millis(500),
-//!     start_time: None,
-//!     buf_size: 16,
-//!     staleness_config: None,
-//! };
+//! let config = Config::basic(Duration::from_millis(500), None, 16);
 //! let (sync_stream, feedback_stream) = sync(join_stream, ["X", "Y"], config)?;
 //!
 //! // Collect the groups
@@ -58,16 +52,137 @@
 //! # Ok(())
 //! # }
 //! ```
+//!
+//! # How It Works
+//!
+//! Each stream has a buffer of messages ordered by timestamp. A group
+//! is formed around the *inf timestamp*, the maximum of the earliest
+//! timestamps of the buffers, which is the earliest time every stream
+//! has reached. The window spans `window_size` before and after it,
+//! and the earliest message of each buffer is picked into the group.
+//! Messages before the window are dropped.
+//!
+//! The *sup timestamp*, the minimum of the latest timestamps, tells
+//! how far every stream has progressed. A group is only formed once
+//! the sup timestamp is one window size past the inf timestamp, so
+//! that no stream can deliver a closer message later.
+//!
+//! The earliest timestamp of the last group becomes the *commit
+//! timestamp*. Messages at or before it are rejected, because groups
+//! are emitted in time order. When all buffers are full but no group
+//! can be formed, or when the input ends, the message with the minimum
+//! timestamp is dropped by `drop_min()` and the matching is retried.
+//!
+//! For example, with a window size of 50 ms, the front messages A@1000,
+//! B@1010 and C@990 give the inf timestamp 1010 and the window
+//! [960, 1060]. Once every stream reaches 1060, the three messages are
+//! grouped and the commit timestamp becomes 990.
+//!
+//! ```text
+//!                  960          1010          1060
+//!                   [-------------|-------------]
+//! Stream A:                   1000                        1100
+//! Stream B:                     1010                        1110
+//! Stream C:              990               1040     1090         1140
+//! Group:     {A: 1000, B: 1010, C: 990}
+//! ```
+//!
+//! See `ALGORITHM.md` in the repository for a step-by-step walkthrough.
+//!
+//! # Timestamp Range
+//!
+//! Timestamps are assumed to be reasonable, i.e. within about 584
+//! years from the epoch, which is the range of nanoseconds in a
+//! `u64`. Timestamps beyond the range are still grouped correctly,
+//! since the arithmetic on them saturates instead of panicking, but
+//! they are clamped to `u64::MAX` nanoseconds when encoded to
+//! Protocol Buffers. Use [utils::duration_safe_from_nanos] to convert
+//! raw nanosecond counts, e.g. from a system clock set far from the
+//! epoch.
+//!
+//! # Merging Streams with `StreamMap`
+//!
+//! [tokio_stream::StreamMap](https://docs.rs/tokio-stream/latest/tokio_stream/struct.StreamMap.html)
+//! merges keyed streams into `(key, message)` pairs, which become the
+//! input of [sync()] once wrapped in `Ok`. The `from_stream_map()`
+//! function does the same with the `tokio-stream` feature enabled.
+//!
+//! ```rust
+//! # use futures::{StreamExt, TryStreamExt};
+//! # use multi_stream_synchronizer::{sync, Config, WithTimestamp};
+//! # use std::time::Duration;
+//! # use tokio_stream::StreamMap;
+//! # #[derive(Clone)]
+//! # struct MyMessage(Duration);
+//! # impl WithTimestamp for MyMessage {
+//! #     fn timestamp(&self) -> Duration {
+//! #         self.0
+//! #     }
+//! # }
+//! # #[tokio::main]
+//! # async fn main() -> eyre::Result<()> {
+//! let mut streams = StreamMap::new();
+//! streams.insert("X", tokio_stream::iter(vec![MyMessage(Duration::from_millis(1001))]));
+//! streams.insert("Y", tokio_stream::iter(vec![MyMessage(Duration::from_millis(998))]));
+//!
+//! let config = Config::basic(Duration::from_millis(500), None, 16);
+//! let (sync_stream, _feedback_stream) = sync(streams.map(eyre::Ok), ["X", "Y"], config)?;
+//! let groups: Vec<_> = sync_stream.try_collect().await?;
+//! # Ok(())
+//! # }
+//! ```
 
 pub mod buffer;
+mod combinators;
 mod config;
+#[cfg(feature = "serde")]
+mod duration_millis;
+mod error;
+mod handle;
+pub mod low_level;
+#[cfg(feature = "proto")]
+pub mod proto;
+#[cfg(feature = "smolstr-key")]
+mod smolstr_key;
 pub mod staleness;
 pub mod state;
 mod sync;
+mod sync_iter;
+mod throttle;
 mod types;
-mod utils;
+pub mod utils;
 
-pub use config::Config;
+pub use combinators::{
+    fill_gaps, group_to_stream, inspect_all, inspect_errors, inspect_groups, validate_ordering,
+    GroupTimestamped,
+};
+pub use config::{
+    Config, ConfigBuilder, ConfigError, DropPolicy, FeedbackDroppedCallback, KeyOptions,
+    MatchingStrategy, ValidatedConfig, WindowMode, WindowSizePolicy,
+};
+pub use error::SyncError;
+pub use handle::{ControlMessage, SyncHandle};
+#[cfg(feature = "derive")]
+pub use multi_stream_synchronizer_derive::WithTimestamp;
+// Re-exported so that users can handle the emitted groups without
+// depending on `indexmap` directly.
+pub use indexmap::{
+    map::{Entry, Iter},
+    IndexMap, IndexSet,
+};
+#[cfg(feature = "smolstr-key")]
+pub use smolstr_key::SmolStrKey;
 pub use staleness::{StalenessConfig, StalenessDetector, StalenessStats};
-pub use sync::sync;
+pub use sync::{
+    sync, sync_any, sync_concurrent, sync_from_streams, sync_with_dead_letters, sync_with_envelope,
+    sync_with_eof, sync_with_handle, sync_with_options, SyncBuilder,
+};
+pub use sync_iter::{drain_to_vec, sync_from_iter, sync_iter, SyncIter};
+pub use throttle::ThrottledSource;
 pub use types::*;
+#[cfg(feature = "tokio-stream")]
+pub use utils::from_stream_map;
+pub use utils::{
+    collect_timeline, compute_window, duration_since_epoch, messages_in_window, timeline_to_vec,
+    validate_group, GroupValidationError, Timeline, TimelineExt,
+};
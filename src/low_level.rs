@@ -0,0 +1,237 @@
+//! Building blocks to drive the synchronizer from a custom polling
+//! loop, for example on an async runtime other than tokio.
+//!
+//! Create the state with [new_state] and call [step] for each input
+//! message. Once the input is exhausted, call [step] without a message
+//! until it returns [StepResult::Done].
+
+pub use crate::{
+    buffer::{Buffer, FrontEntry},
+    state::State,
+};
+use crate::{
+    sync::{init_state, poll, SyncState},
+    types::{FeedbackReceiver, Key, WithTimestamp},
+    KeyOptions, SyncError, ValidatedConfig,
+};
+use eyre::Result;
+use futures::{task::noop_waker_ref, Stream};
+use indexmap::IndexMap;
+use std::{
+    pin::Pin,
+    task::{Context, Poll, Poll::*},
+};
+use tracing::debug;
+
+/// The outcome of a single [step].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StepResult<K, T>
+where
+    K: Key,
+{
+    /// No group can be formed until more messages arrive.
+    Pending,
+    /// A group is formed.
+    Group(IndexMap<K, T>),
+    /// The input is exhausted and no more group can be formed.
+    Done,
+}
+
+/// Validate the configuration and create the state along with the
+/// feedback receiver. Session window mode is not supported.
+pub fn new_state<K, T, I, C>(
+    keys: I,
+    config: C,
+) -> Result<(State<K, T>, FeedbackReceiver<K>), SyncError>
+where
+    K: Key,
+    T: WithTimestamp + Clone,
+    I: IntoIterator<Item = K>,
+    C: TryInto<ValidatedConfig>,
+    C::Error: Into<SyncError>,
+{
+    let (state, feedback_rx) = init_state(keys, config, KeyOptions::default())?;
+    let SyncState::Sliding(state) = state else {
+        return Err(SyncError::SessionModeUnsupported);
+    };
+    Ok((state, feedback_rx))
+}
+
+/// Run a single iteration of the synchronization algorithm.
+///
+/// The `message` is buffered in the state, possibly after a group is
+/// formed from the already buffered messages. Set `input_exhausted`
+/// once no more message will be given, so that the remaining buffered
+/// messages are grouped.
+///
+/// With [DropPolicy::ReturnError](crate::DropPolicy::ReturnError),
+/// [SyncError::BufferOverflow] is returned if all buffers are full and
+/// no group can be formed. The `message` is discarded if it is not
+/// buffered yet.
+pub fn step<K, T>(
+    state: &mut State<K, T>,
+    message: Option<(K, T)>,
+    input_exhausted: bool,
+) -> Result<StepResult<K, T>, SyncError>
+where
+    K: Key,
+    T: WithTimestamp + Clone,
+{
+    let mut input = Some(StepInput {
+        message,
+        input_exhausted,
+    });
+    let mut ctx = Context::from_waker(noop_waker_ref());
+
+    let result = match poll(Pin::new(&mut input), state, &mut ctx) {
+        Ready(Some(Ok(group))) => StepResult::Group(group),
+        Ready(Some(Err(err))) => {
+            // The step input never yields an error, so the error comes
+            // from the synchronizer.
+            return Err(err
+                .downcast()
+                .expect("the step input never yields an error"));
+        }
+        Ready(None) => StepResult::Done,
+        Pending => StepResult::Pending,
+    };
+
+    // A group can be formed from full buffers before the message is
+    // consumed. Buffer the message for the next step.
+    if let Some(StepInput {
+        message: Some((key, item)),
+        ..
+    }) = input
+    {
+        if state.push(key, item).is_err() {
+            debug!("drop a late message");
+        }
+        state.update_feedback();
+    }
+
+    Ok(result)
+}
+
+/// The input stream yielding at most one message per step.
+struct StepInput<K, T> {
+    message: Option<(K, T)>,
+    input_exhausted: bool,
+}
+
+// The message is never pinned.
+impl<K, T> Unpin for StepInput<K, T> {}
+
+impl<K, T> Stream for StepInput<K, T> {
+    type Item = Result<(K, Option<T>)>;
+
+    fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        match this.message.take() {
+            Some((key, item)) => Ready(Some(Ok((key, Some(item))))),
+            None if this.input_exhausted => Ready(None),
+            None => Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{sync_iter, Config, DropPolicy};
+    use std::time::Duration;
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct TestMessage(Duration);
+
+    impl WithTimestamp for TestMessage {
+        fn timestamp(&self) -> Duration {
+            self.0
+        }
+    }
+
+    fn messages() -> Vec<(&'static str, TestMessage)> {
+        [0, 3, 10, 12, 20, 24, 30, 31, 40, 44, 50, 52]
+            .into_iter()
+            .enumerate()
+            .map(|(index, ms)| {
+                let key = if index % 2 == 0 { "A" } else { "B" };
+                (key, TestMessage(Duration::from_millis(ms)))
+            })
+            .collect()
+    }
+
+    fn run_steps(config: Config) -> Vec<IndexMap<&'static str, TestMessage>> {
+        let (mut state, _feedback_rx) = new_state(["A", "B"], config).unwrap();
+        let mut groups = vec![];
+
+        for message in messages() {
+            if let StepResult::Group(group) = step(&mut state, Some(message), false).unwrap() {
+                groups.push(group);
+            }
+        }
+        loop {
+            match step(&mut state, None, true).unwrap() {
+                StepResult::Group(group) => groups.push(group),
+                StepResult::Done => break,
+                StepResult::Pending => panic!("step must not be pending once exhausted"),
+            }
+        }
+
+        groups
+    }
+
+    #[test]
+    fn test_step_matches_sync_iter() {
+        let config = || Config::basic(Duration::from_millis(5), None, 16);
+        let expect: Vec<_> = sync_iter(messages(), ["A", "B"], config())
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+
+        let groups = run_steps(config());
+        assert!(!groups.is_empty());
+        assert_eq!(groups, expect);
+    }
+
+    #[test]
+    fn test_step_pending_without_message() {
+        let (mut state, _feedback_rx) = new_state::<_, TestMessage, _, _>(
+            ["A", "B"],
+            Config::basic(Duration::from_millis(5), None, 2),
+        )
+        .unwrap();
+        assert_eq!(step(&mut state, None, false), Ok(StepResult::Pending));
+        assert_eq!(step(&mut state, None, true), Ok(StepResult::Done));
+    }
+
+    #[test]
+    fn test_step_drop_policy_return_error() {
+        // Both buffers are full while the messages span less than the
+        // window, so no group can be formed.
+        let config = Config::basic(Duration::from_millis(100), None, 2)
+            .with_drop_policy(DropPolicy::ReturnError);
+        let (mut state, _feedback_rx) = new_state(["A", "B"], config).unwrap();
+        let message = |key, ms| Some((key, TestMessage(Duration::from_millis(ms))));
+
+        for (key, ms) in [("A", 10), ("A", 20), ("B", 30)] {
+            assert_eq!(
+                step(&mut state, message(key, ms), false),
+                Ok(StepResult::Pending)
+            );
+        }
+        assert_eq!(
+            step(&mut state, message("B", 40), false),
+            Err(SyncError::BufferOverflow { key_index: 0 })
+        );
+    }
+
+    #[test]
+    fn test_new_state_session_mode_unsupported() {
+        let mut config: Config = Config::basic(Duration::from_millis(5), None, 2);
+        config.window_mode = crate::WindowMode::Session {
+            gap: Duration::from_millis(10),
+        };
+        let result = new_state::<_, TestMessage, _, _>(["A", "B"], config);
+        assert!(matches!(result, Err(SyncError::SessionModeUnsupported)));
+    }
+}
@@ -0,0 +1,90 @@
+//! Protocol Buffer types generated from `proto/sync.proto`.
+//!
+//! Durations are encoded in nanoseconds. Values exceeding `u64` are
+//! saturated.
+
+use crate::types::Key;
+use eyre::ensure;
+use std::{fmt::Display, time::Duration};
+
+include!(concat!(env!("OUT_DIR"), "/multi_stream_synchronizer.rs"));
+
+fn to_nanos(duration: Duration) -> u64 {
+    u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX)
+}
+
+impl From<&crate::Config> for Config {
+    fn from(config: &crate::Config) -> Self {
+        Self {
+            window_size_ns: to_nanos(config.window_size),
+            start_time_ns: config.start_time.map(to_nanos),
+            buf_size: u32::try_from(config.buf_size).unwrap_or(u32::MAX),
+        }
+    }
+}
+
+impl TryFrom<Config> for crate::Config {
+    type Error = eyre::Report;
+
+    fn try_from(config: Config) -> Result<Self, Self::Error> {
+        let Config {
+            window_size_ns,
+            start_time_ns,
+            buf_size,
+        } = config;
+        ensure!(window_size_ns > 0, "window size must be positive");
+
+        Ok(Self::basic(
+            Duration::from_nanos(window_size_ns),
+            start_time_ns.map(Duration::from_nanos),
+            usize::try_from(buf_size)?,
+        ))
+    }
+}
+
+impl<K> From<&crate::Feedback<K>> for Feedback
+where
+    K: Key + Display,
+{
+    fn from(feedback: &crate::Feedback<K>) -> Self {
+        Self {
+            accepted_keys: feedback
+                .accepted_keys
+                .iter()
+                .map(|key| key.to_string())
+                .collect(),
+            commit_timestamp_ns: feedback.commit_timestamp.map(to_nanos),
+            accepted_max_timestamp_ns: feedback.accepted_max_timestamp.map(to_nanos),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_proto_config_round_trip() {
+        let config =
+            crate::Config::basic(Duration::from_millis(100), Some(Duration::from_secs(1)), 8);
+
+        let proto = Config::from(&config);
+        assert_eq!(proto.window_size_ns, 100_000_000);
+        assert_eq!(proto.start_time_ns, Some(1_000_000_000));
+        assert_eq!(proto.buf_size, 8);
+
+        let decoded: crate::Config = proto.try_into().unwrap();
+        assert_eq!(decoded.window_size, config.window_size);
+        assert_eq!(decoded.start_time, config.start_time);
+        assert_eq!(decoded.buf_size, config.buf_size);
+    }
+
+    #[test]
+    fn test_proto_config_zero_window_size() {
+        let proto = Config {
+            buf_size: 8,
+            ..Default::default()
+        };
+        assert!(crate::Config::try_from(proto).is_err());
+    }
+}
@@ -0,0 +1,103 @@
+use smol_str::SmolStr;
+use std::{fmt, ops::Deref};
+
+/// A short string key stored inline, which implements [Copy].
+///
+/// It holds up to [SmolStrKey::CAPACITY] bytes, the same as the
+/// inline capacity of [SmolStr], and converts from and to [SmolStr].
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SmolStrKey {
+    len: u8,
+    bytes: [u8; SmolStrKey::CAPACITY],
+}
+
+impl SmolStrKey {
+    /// The maximum length of the key in bytes.
+    pub const CAPACITY: usize = 23;
+
+    /// Creates a key from the string. Returns `None` if the string is
+    /// longer than [SmolStrKey::CAPACITY] bytes.
+    pub fn new(text: &str) -> Option<Self> {
+        let len = text.len();
+        if len > Self::CAPACITY {
+            return None;
+        }
+
+        let mut bytes = [0; Self::CAPACITY];
+        bytes[..len].copy_from_slice(text.as_bytes());
+        Some(Self {
+            len: len as u8,
+            bytes,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        // The bytes are copied from a valid string in new().
+        std::str::from_utf8(&self.bytes[..self.len as usize]).unwrap()
+    }
+}
+
+impl Deref for SmolStrKey {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        self.as_str()
+    }
+}
+
+impl fmt::Debug for SmolStrKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for SmolStrKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+impl TryFrom<&str> for SmolStrKey {
+    type Error = eyre::Report;
+
+    fn try_from(text: &str) -> Result<Self, Self::Error> {
+        Self::new(text)
+            .ok_or_else(|| eyre::eyre!("the key {text:?} is longer than {} bytes", Self::CAPACITY))
+    }
+}
+
+impl TryFrom<&SmolStr> for SmolStrKey {
+    type Error = eyre::Report;
+
+    fn try_from(text: &SmolStr) -> Result<Self, Self::Error> {
+        Self::try_from(text.as_str())
+    }
+}
+
+impl From<SmolStrKey> for SmolStr {
+    fn from(key: SmolStrKey) -> Self {
+        SmolStr::new(key.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_smolstr_key_round_trip() {
+        let key = SmolStrKey::new("lidar").unwrap();
+        let copied = key;
+        assert_eq!(key, copied);
+        assert_eq!(key.as_str(), "lidar");
+        assert_eq!(SmolStr::from(key), SmolStr::new("lidar"));
+        assert_eq!(SmolStrKey::try_from(&SmolStr::new("lidar")).unwrap(), key);
+    }
+
+    #[test]
+    fn test_smolstr_key_too_long() {
+        let text = "a".repeat(SmolStrKey::CAPACITY + 1);
+        assert!(SmolStrKey::new(&text).is_none());
+        assert!(SmolStrKey::new(&text[..SmolStrKey::CAPACITY]).is_some());
+    }
+}
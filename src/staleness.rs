@@ -5,20 +5,26 @@ use std::{
     time::{Duration, Instant},
 };
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 #[cfg(feature = "tokio")]
 use tokio::sync::mpsc;
 
 /// Configuration for staleness detection
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct StalenessConfig {
     /// Maximum number of entries in the min-heap before delegating to timer wheel
     pub heap_max_size: usize,
     /// Maximum time horizon for heap entries (messages beyond this use timer wheel)
+    #[cfg_attr(feature = "serde", serde(with = "crate::duration_millis"))]
     pub heap_time_horizon: Duration,
     /// Precision gap - minimum time between expiration checks
+    #[cfg_attr(feature = "serde", serde(with = "crate::duration_millis"))]
     pub precision_gap: Duration,
     /// Timer wheel settings for overflow handling
     pub timer_wheel_slots: usize,
+    #[cfg_attr(feature = "serde", serde(with = "crate::duration_millis"))]
     pub timer_wheel_slot_duration: Duration,
     /// Enable immediate expiration (requires tokio feature)
     pub enable_immediate_expiration: bool,
@@ -104,7 +110,7 @@ impl<K, T> Ord for StalenessEntry<K, T> {
 }
 
 /// Constrained min-heap for staleness detection with size, temporal, and precision constraints
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ConstrainedHeap<K, T>
 where
     K: Clone + Hash + Eq,
@@ -241,7 +247,7 @@ where
 }
 
 /// Phase 3: Timer wheel for handling overflow messages
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TimerWheel<K, T>
 where
     K: Clone + Hash + Eq,
@@ -339,6 +345,26 @@ where
     command_tx: Option<mpsc::UnboundedSender<ExpirationCommand>>,
 }
 
+impl<K, T> Clone for StalenessDetector<K, T>
+where
+    K: Clone + Hash + Eq,
+    T: WithTimestamp + Clone,
+{
+    /// Clones the tracked messages. The clone shares the background
+    /// expiration task with the original detector.
+    fn clone(&self) -> Self {
+        Self {
+            constrained_heap: self.constrained_heap.clone(),
+            timer_wheel: self.timer_wheel.clone(),
+            config: self.config.clone(),
+            #[cfg(feature = "tokio")]
+            _expiration_handle: None,
+            #[cfg(feature = "tokio")]
+            command_tx: self.command_tx.clone(),
+        }
+    }
+}
+
 impl<K, T> StalenessDetector<K, T>
 where
     K: Clone + Hash + Eq,
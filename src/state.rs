@@ -1,26 +1,49 @@
 use crate::{
-    buffer::Buffer,
+    buffer::{Buffer, PushError},
+    config::{DropPolicy, FeedbackDroppedCallback, MatchingStrategy, WindowSizePolicy},
     staleness::StalenessDetector,
-    types::{Feedback, Key, WithTimestamp},
+    types::{
+        ClosestTimestamp, DropReason, DroppedMessage, Feedback, Key, MessagePriority, SyncStats,
+        WithTimestamp,
+    },
+    utils::{compute_window, duration_diff},
 };
-use indexmap::IndexMap;
-use std::time::Duration;
-use tokio::sync::watch;
+use eyre::{ensure, Result};
+use indexmap::{IndexMap, IndexSet};
+use rustc_hash::FxHasher;
+use std::{
+    cmp::Reverse,
+    collections::{hash_map::RandomState, VecDeque},
+    fmt,
+    hash::{BuildHasher, Hash, Hasher},
+    iter,
+    time::{Duration, Instant},
+};
+use tokio::sync::{mpsc, watch};
+#[cfg(feature = "tracing")]
+use tracing::{debug, field, trace, trace_span, Span};
 
 /// The internal state maintained by [sync](crate::sync).
-#[derive(Debug)]
-pub struct State<K, T>
+///
+/// The buffers are indexed with the hasher `S`. A faster hasher, such
+/// as `FxBuildHasher` from the `rustc-hash` crate, may be used for
+/// simple integer keys. See [State::with_hasher].
+pub struct State<K, T, S = RandomState>
 where
     K: Key,
     T: WithTimestamp + Clone,
 {
     /// A list of buffers indexed by key K.
-    pub buffers: IndexMap<K, Buffer<T>>,
+    pub buffers: IndexMap<K, Buffer<T>, S>,
 
     /// Marks the timestamp where messages before the time point are
     /// emitted.
     pub commit_ts: Option<Duration>,
 
+    /// The start time from the config, which is the initial commit
+    /// timestamp.
+    pub start_time: Option<Duration>,
+
     /// The maximum size of each buffer for each key.
     pub buf_size: usize,
 
@@ -28,18 +51,284 @@ where
     /// within.
     pub window_size: Duration,
 
-    /// The sender where feedback messages are sent to.
+    /// The sender where feedback messages are sent to. It is not
+    /// cloned along with the state, so a cloned state has `None` and
+    /// sends no feedback.
     pub feedback_tx: Option<watch::Sender<Feedback<K>>>,
 
     /// Optional staleness detector for real-time message expiration
     pub staleness_detector: Option<StalenessDetector<K, T>>,
+
+    /// Optional reliability weights used to compute the window anchor.
+    pub anchor_weights: Option<IndexMap<K, f64>>,
+
+    /// Keys which are temporarily not required to form a group.
+    pub paused_keys: IndexSet<K>,
+
+    /// The policy that decides how the window size evolves.
+    pub window_size_policy: WindowSizePolicy,
+
+    /// Timestamp spreads of recently emitted groups used by the
+    /// adaptive window size policy.
+    pub spread_history: VecDeque<Duration>,
+
+    /// The maximum number of messages kept across all buffers.
+    pub max_total_pending: Option<usize>,
+
+    /// The minimum number of streams that must remain registered.
+    pub minimum_streams_required: usize,
+
+    /// Signed per-key offsets in nanoseconds added to message
+    /// timestamps before matching.
+    pub timestamp_offsets: Option<IndexMap<K, i64>>,
+
+    /// The callback invoked once the feedback receiver is dropped.
+    pub on_feedback_dropped: Option<FeedbackDroppedCallback>,
+
+    /// Fingerprints of recently emitted groups, or `None` if
+    /// deduplication is disabled.
+    pub fingerprint_history: Option<VecDeque<u64>>,
+
+    /// The maximum number of fingerprints kept for deduplication.
+    pub dedup_history: usize,
+
+    /// Whether the buffers or the commit timestamp changed since the
+    /// last feedback was sent. Set it after modifying the buffers
+    /// directly.
+    pub feedback_dirty: bool,
+
+    /// The wall-clock time when the latest message of each key was
+    /// received.
+    pub last_received_wall: IndexMap<K, Instant>,
+
+    /// Breaks ties between candidate messages the matching strategy
+    /// ranks equally (optional). Defaults to [ClosestTimestamp]. See
+    /// [MessagePriority].
+    pub priority: Option<PriorityFn<K, T>>,
+
+    /// The senders of per-key feedback telling whether messages of
+    /// each key are accepted (optional).
+    pub key_feedback_tx: Option<IndexMap<K, watch::Sender<bool>>>,
+
+    /// The sender where dropped messages are reported (optional). It
+    /// is cleared once the receiver is dropped.
+    pub dead_letter_tx: Option<mpsc::UnboundedSender<DroppedMessage<K, T>>>,
+
+    /// The extra tolerance added to the window size, which is kept
+    /// when the window size is adjusted.
+    pub jitter_tolerance: Duration,
+
+    /// Keys which are not required to form a group. Unlike paused
+    /// keys, they are set by the config.
+    pub optional_keys: IndexSet<K>,
+
+    /// Keys missing from the last emitted group.
+    pub absent_keys: Vec<K>,
+
+    /// The maximum size of the buffers of listed keys, overriding
+    /// `buf_size`.
+    pub per_key_buf_size: IndexMap<K, usize>,
+
+    /// The strategy that picks the message of each stream within the
+    /// window.
+    pub matching_strategy: MatchingStrategy,
+
+    /// The time window `(start, end)` of the last emitted group.
+    pub last_window: Option<(Duration, Duration)>,
+
+    /// The wall-clock time to wait for a new message before flushing
+    /// the buffered messages.
+    pub flush_timeout: Option<Duration>,
+
+    /// Whether messages of the same stream sharing a timestamp are
+    /// all kept instead of the last one.
+    pub allow_equal_timestamps: bool,
+
+    /// What to do when a buffer reaches its capacity.
+    pub drop_policy: DropPolicy,
+
+    /// How far behind the latest message of the same stream a message
+    /// may be and still be buffered, which is set on every buffer.
+    pub out_of_order_tolerance: Duration,
+
+    /// The counters of received, emitted and discarded messages.
+    pub(crate) stats: SyncStats<K>,
+}
+
+/// The function ranking candidate messages, usually taken from a
+/// [MessagePriority] implementation.
+pub type PriorityFn<K, T> = fn(&K, &T, Duration) -> i64;
+
+/// The number of recent group spreads kept for adaptive window sizing.
+const SPREAD_HISTORY_LEN: usize = 64;
+
+/// The factor applied to the observed spread percentile to derive the
+/// adaptive window size.
+const SPREAD_SAFETY_FACTOR: f64 = 1.5;
+
+/// Shows timestamps in seconds and summarizes each buffer, e.g.
+/// `"A" => [3 messages, front: 0.100s, back: 0.450s]`.
+impl<K, T, S> fmt::Debug for State<K, T, S>
+where
+    K: Key + fmt::Debug,
+    T: WithTimestamp + Clone,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        struct Secs(Option<Duration>);
+
+        impl fmt::Debug for Secs {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self.0 {
+                    Some(ts) => write!(f, "{:.3}s", ts.as_secs_f64()),
+                    None => write!(f, "none"),
+                }
+            }
+        }
+
+        struct Buffers<'a, K, T, S>(&'a IndexMap<K, Buffer<T>, S>)
+        where
+            T: WithTimestamp;
+
+        impl<K, T, S> fmt::Debug for Buffers<'_, K, T, S>
+        where
+            K: fmt::Debug,
+            T: WithTimestamp,
+        {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{{")?;
+                for (index, (key, buffer)) in self.0.iter().enumerate() {
+                    let sep = if index == 0 { " " } else { ", " };
+                    write!(f, "{sep}{key:?} => [{} messages", buffer.len())?;
+                    if !buffer.is_empty() {
+                        write!(
+                            f,
+                            ", front: {:?}, back: {:?}",
+                            Secs(buffer.front_ts()),
+                            Secs(buffer.back_ts())
+                        )?;
+                    }
+                    write!(f, "]")?;
+                }
+                write!(f, " }}")
+            }
+        }
+
+        f.debug_struct("State")
+            .field("commit", &Secs(self.commit_ts))
+            .field("window_size", &Secs(Some(self.window_size)))
+            .field("buf_size", &self.buf_size)
+            .field("buffers", &Buffers(&self.buffers))
+            .field("paused_keys", &self.paused_keys)
+            .field("optional_keys", &self.optional_keys)
+            .finish_non_exhaustive()
+    }
 }
 
-impl<K, T> State<K, T>
+impl<K, T, S> Clone for State<K, T, S>
 where
     K: Key,
     T: WithTimestamp + Clone,
+    S: Clone,
 {
+    /// Clones the state for offline simulation or recovery. The
+    /// feedback and dead-letter senders are not cloned, so the cloned
+    /// state neither produces feedback nor reports dropped messages.
+    fn clone(&self) -> Self {
+        Self {
+            buffers: self.buffers.clone(),
+            commit_ts: self.commit_ts,
+            start_time: self.start_time,
+            buf_size: self.buf_size,
+            window_size: self.window_size,
+            // A cloned sender would let the clone overwrite the
+            // feedback of the original state.
+            feedback_tx: None,
+            staleness_detector: self.staleness_detector.clone(),
+            anchor_weights: self.anchor_weights.clone(),
+            paused_keys: self.paused_keys.clone(),
+            window_size_policy: self.window_size_policy,
+            spread_history: self.spread_history.clone(),
+            max_total_pending: self.max_total_pending,
+            minimum_streams_required: self.minimum_streams_required,
+            timestamp_offsets: self.timestamp_offsets.clone(),
+            on_feedback_dropped: self.on_feedback_dropped.clone(),
+            fingerprint_history: self.fingerprint_history.clone(),
+            dedup_history: self.dedup_history,
+            feedback_dirty: self.feedback_dirty,
+            last_received_wall: self.last_received_wall.clone(),
+            priority: self.priority,
+            key_feedback_tx: None,
+            dead_letter_tx: None,
+            jitter_tolerance: self.jitter_tolerance,
+            optional_keys: self.optional_keys.clone(),
+            absent_keys: self.absent_keys.clone(),
+            per_key_buf_size: self.per_key_buf_size.clone(),
+            matching_strategy: self.matching_strategy,
+            last_window: self.last_window,
+            flush_timeout: self.flush_timeout,
+            allow_equal_timestamps: self.allow_equal_timestamps,
+            drop_policy: self.drop_policy,
+            out_of_order_tolerance: self.out_of_order_tolerance,
+            stats: self.stats.clone(),
+        }
+    }
+}
+
+impl<K, T, S> State<K, T, S>
+where
+    K: Key,
+    T: WithTimestamp + Clone,
+    S: BuildHasher,
+{
+    /// Creates a state for the keys without optional features, whose
+    /// buffers are indexed with the hasher.
+    pub fn with_hasher<I>(keys: I, buf_size: usize, window_size: Duration, hasher: S) -> Self
+    where
+        I: IntoIterator<Item = K>,
+    {
+        let mut buffers = IndexMap::with_hasher(hasher);
+        buffers.extend(
+            keys.into_iter()
+                .map(|key| (key, Buffer::with_capacity(buf_size))),
+        );
+
+        Self {
+            buffers,
+            commit_ts: None,
+            start_time: None,
+            buf_size,
+            window_size,
+            feedback_tx: None,
+            staleness_detector: None,
+            anchor_weights: None,
+            paused_keys: IndexSet::new(),
+            window_size_policy: WindowSizePolicy::Fixed,
+            spread_history: VecDeque::new(),
+            max_total_pending: None,
+            minimum_streams_required: 1,
+            timestamp_offsets: None,
+            on_feedback_dropped: None,
+            fingerprint_history: None,
+            dedup_history: 0,
+            feedback_dirty: false,
+            last_received_wall: IndexMap::new(),
+            priority: None,
+            key_feedback_tx: None,
+            dead_letter_tx: None,
+            jitter_tolerance: Duration::ZERO,
+            optional_keys: IndexSet::new(),
+            absent_keys: vec![],
+            per_key_buf_size: IndexMap::new(),
+            matching_strategy: MatchingStrategy::First,
+            last_window: None,
+            flush_timeout: None,
+            allow_equal_timestamps: false,
+            drop_policy: DropPolicy::DropOldest,
+            out_of_order_tolerance: Duration::ZERO,
+            stats: SyncStats::default(),
+        }
+    }
+
     // pub fn print_debug_info(&self) {
     //     debug!("buffer sizes");
     //     self.buffers.iter().for_each(|(device, buffer)| {
@@ -47,18 +336,54 @@ where
     //     });
     // }
 
-    /// Generate a feedback message.
+    /// Generate a feedback message if the state changed since the
+    /// last one.
     pub fn update_feedback(&mut self) {
+        if self.feedback_dirty {
+            self.update_key_feedback();
+        }
+
         let Some(feedback_tx) = &self.feedback_tx else {
             return;
         };
 
+        if feedback_tx.is_closed() {
+            if let Some(on_feedback_dropped) = &self.on_feedback_dropped {
+                on_feedback_dropped.call();
+            }
+            self.feedback_tx = None;
+            return;
+        }
+        if !self.feedback_dirty {
+            return;
+        }
+        self.feedback_dirty = false;
+
         let accepted_keys: Vec<K> = self
             .buffers
             .iter()
-            .filter(|&(_key, buffer)| (buffer.len() < self.buf_size))
+            .filter(|&(key, buffer)| buffer.len() < self.key_buf_size(key))
             .map(|(key, _buffer)| key.clone())
             .collect();
+        #[cfg(feature = "tracing")]
+        debug!(
+            accepted_keys = ?self.key_indices(&accepted_keys),
+            "update feedback"
+        );
+        let buffer_usage: IndexMap<K, f64> = self
+            .buffers
+            .iter()
+            .map(|(key, buffer)| {
+                let usage = buffer.len() as f64 / self.key_buf_size(key) as f64;
+                (key.clone(), usage)
+            })
+            .collect();
+        // Keys are labeled by their positions since they are not
+        // required to implement `Debug`.
+        #[cfg(feature = "metrics")]
+        for (index, usage) in buffer_usage.values().enumerate() {
+            metrics::gauge!("sync.buffer_fill", "key" => index.to_string()).set(*usage);
+        }
 
         // Request input sources to deliver messages with ts below thresh_ts
         // let thresh_ts = self
@@ -68,12 +393,23 @@ where
         //     .min();
         // let include_thresh_ts = self.buffers.values().all(|buffer| buffer.buffer.is_empty());
 
+        // Messages beyond two windows ahead of the commit timestamp
+        // are not needed yet.
+        let accepted_max_timestamp = self
+            .commit_ts
+            .map(|commit_ts| commit_ts.saturating_add(self.window_size.saturating_mul(2)));
+
         let msg = Feedback {
             accepted_keys,
             // accepted_max_timestamp: thresh_ts.map(|ts| ts.as_nanos() as u64),
             // inclusive: Some(include_thresh_ts),
-            accepted_max_timestamp: None,
+            accepted_max_timestamp,
             commit_timestamp: self.commit_ts,
+            window_size: Some(self.window_size),
+            absent_keys: self.absent_keys.clone(),
+            buffer_usage,
+            key_lag: self.key_lag_map(),
+            bottleneck_key: self.bottleneck_key(),
         };
 
         // if self.verbose_debug {
@@ -84,76 +420,372 @@ where
         // }
 
         if feedback_tx.send(msg).is_err() {
+            if let Some(on_feedback_dropped) = &self.on_feedback_dropped {
+                on_feedback_dropped.call();
+            }
             self.feedback_tx = None;
         }
     }
 
+    /// Tell each stream whether its messages are accepted. Only
+    /// changes are sent, so that the stream is woken up when it
+    /// switches between keep sending and pause.
+    fn update_key_feedback(&self) {
+        let Some(key_feedback_tx) = &self.key_feedback_tx else {
+            return;
+        };
+
+        for (key, tx) in key_feedback_tx {
+            let Some(buffer) = self.buffers.get(key) else {
+                continue;
+            };
+            let accepted = buffer.len() < self.key_buf_size(key);
+            tx.send_if_modified(|prev| {
+                let modified = *prev != accepted;
+                *prev = accepted;
+                modified
+            });
+        }
+    }
+
     /// Try to group up messages within a time window.
     pub fn try_match(&mut self) -> Option<IndexMap<K, T>> {
-        let inf_ts = loop {
+        self.try_match_checked(true)
+    }
+
+    /// Try to group up messages without waiting for later messages
+    /// that may fit the window better, which is used once no more
+    /// message is expected.
+    pub fn try_match_now(&mut self) -> Option<IndexMap<K, T>> {
+        self.try_match_checked(false)
+    }
+
+    /// Call [try_match](State::try_match) repeatedly and collect all
+    /// groups that can be formed from the buffered messages.
+    pub fn try_match_all(&mut self) -> Vec<IndexMap<K, T>> {
+        iter::from_fn(|| self.try_match()).collect()
+    }
+
+    fn try_match_checked(&mut self, wait: bool) -> Option<IndexMap<K, T>> {
+        #[cfg(feature = "tracing")]
+        let _span = trace_span!(
+            "try_match",
+            wait,
+            window_start = field::Empty,
+            window_end = field::Empty
+        )
+        .entered();
+
+        // Matching removes messages from the front of buffers, which
+        // keeps them ordered.
+        #[cfg(debug_assertions)]
+        self.assert_invariants_with(OrderCheck::Skip);
+
+        let group = self.try_match_inner(wait);
+
+        #[cfg(debug_assertions)]
+        self.assert_invariants_with(OrderCheck::Skip);
+
+        group
+    }
+
+    /// Break ties between candidate messages the matching strategy
+    /// ranks equally by the priority.
+    pub fn set_priority<P>(&mut self)
+    where
+        P: MessagePriority<K, T>,
+    {
+        self.priority = Some(P::priority);
+    }
+
+    /// Record the fingerprint of an emitted group. Returns `true` if
+    /// the group is identical to a recently emitted one, which should
+    /// be skipped.
+    pub fn is_duplicate_group(&mut self, group: &IndexMap<K, T>) -> bool {
+        let Some(history) = &mut self.fingerprint_history else {
+            return false;
+        };
+
+        // The fingerprint covers the message timestamp of each key, so
+        // that a redelivered group matches regardless of the state.
+        let mut hasher = FxHasher::default();
+        for (key, item) in group {
+            key.hash(&mut hasher);
+            item.timestamp().hash(&mut hasher);
+        }
+        let fingerprint = hasher.finish();
+
+        if history.contains(&fingerprint) {
+            return true;
+        }
+        if history.len() >= self.dedup_history {
+            history.pop_front();
+        }
+        history.push_back(fingerprint);
+        false
+    }
+
+    fn try_match_inner(&mut self, wait: bool) -> Option<IndexMap<K, T>> {
+        let (inf_ts, anchor_ts) = loop {
             let (_, inf_ts) = self.inf_timestamp()?;
 
             // Checking all buffers have only one data left.
             // Make sure (sup - inf >= window_size). If not, it needs to
             // wait for more messages.
             let (_, sup_ts) = self.sup_timestamp()?;
-            if !self.all_one() && inf_ts + self.window_size > sup_ts {
+            if wait && !self.all_one() && inf_ts + self.window_size > sup_ts {
                 return None;
             }
 
-            let window_start = inf_ts.saturating_sub(self.window_size);
+            let anchor_ts = self.anchor_timestamp(inf_ts);
+
+            // If the window reaches back to time zero, no message is
+            // before the window. The buffers are still bounded by
+            // `buf_size`.
+            let window_start = if anchor_ts <= self.window_size {
+                Duration::ZERO
+            } else {
+                compute_window(anchor_ts, self.window_size).0
+            };
+
+            // Messages before the commit timestamp are already
+            // matched and must be dropped as well.
+            let drop_ts = window_start.max(self.commit_ts.unwrap_or(Duration::ZERO));
+            if drop_ts == Duration::ZERO {
+                break (inf_ts, anchor_ts);
+            }
 
             // Drop messages before the time window.
-            let dropped = self.buffers.values_mut().any(|buffer| {
-                let count = buffer.drop_before(window_start);
+            let stats = &mut self.stats;
+            let dead_letter_tx = &mut self.dead_letter_tx;
+            let commit_ts = self.commit_ts;
+            let dropped = self.buffers.iter_mut().any(|(key, buffer)| {
+                let count = discard_before(
+                    key,
+                    buffer,
+                    drop_ts,
+                    dead_letter_tx,
+                    DropReason::OutOfWindow,
+                    commit_ts,
+                );
+                stats.record_drops(key, count);
                 count > 0
             });
 
             if !dropped {
-                break inf_ts;
+                break (inf_ts, anchor_ts);
             }
         };
 
+        // A buffer emptied by dropping, e.g. a single message outside
+        // the window, cannot contribute to the group.
+        if self.is_empty() {
+            return None;
+        }
+
         // let window_start = inf_ts.saturating_sub(self.window_size);
-        let window_end = inf_ts.saturating_add(self.window_size);
+        let (_, window_end) = compute_window(inf_ts, self.window_size);
+
+        // The target timestamp that the closest message is picked
+        // against, if the strategy needs one.
+        let matching_strategy = self.matching_strategy;
+        let target_ts = match matching_strategy {
+            MatchingStrategy::Closest => anchor_ts,
+            MatchingStrategy::ClosestToCenter => match self.sup_timestamp() {
+                Some((_, sup_ts)) => inf_ts + sup_ts.saturating_sub(inf_ts) / 2,
+                None => inf_ts,
+            },
+            MatchingStrategy::First | MatchingStrategy::Last => inf_ts,
+        };
 
+        let paused_keys = &self.paused_keys;
+        let optional_keys = &self.optional_keys;
+        let priority = self
+            .priority
+            .unwrap_or(<ClosestTimestamp as MessagePriority<K, T>>::priority);
+        let stats = &mut self.stats;
+        let dead_letter_tx = &mut self.dead_letter_tx;
+        let commit_ts = self.commit_ts;
+        let mut timestamps = vec![];
+        // The key index is only used for tracing.
+        #[cfg_attr(not(feature = "tracing"), allow(clippy::unused_enumerate_index))]
         let items: IndexMap<_, _> = self
             .buffers
             .iter_mut()
-            .map(|(key, buffer)| {
-                // find the first candidate that is within the window
+            .enumerate()
+            .filter_map(|(_key_index, (key, buffer))| {
+                // Paused and optional streams join the group only if a
+                // message is within the window.
+                let ts = buffer.front_ts()?;
+                let is_required = !paused_keys.contains(key) && !optional_keys.contains(key);
+                if !is_required && ts > window_end {
+                    return None;
+                }
+
+                // Pick the candidate preferred by the matching strategy
+                // within the window. Candidates the strategy ranks
+                // equally go to the highest priority, then the earliest
+                // message.
+                let front_ts = ts;
+                let distance = |ts: Duration| match matching_strategy {
+                    MatchingStrategy::First => ts - front_ts,
+                    MatchingStrategy::Last => window_end - ts,
+                    MatchingStrategy::Closest | MatchingStrategy::ClosestToCenter => {
+                        duration_diff(ts, target_ts)
+                    }
+                };
+                let ts = {
+                    let candidates = buffer
+                        .iter_with_timestamps()
+                        .take_while(|&(ts, _)| ts <= window_end);
+
+                    #[cfg(feature = "tracing")]
+                    trace!(
+                        key_index = _key_index,
+                        candidates = candidates.clone().count(),
+                        "consider candidates"
+                    );
+                    candidates
+                        .min_by_key(|&(ts, item)| {
+                            (distance(ts), Reverse(priority(key, item, anchor_ts)), ts)
+                        })
+                        .map(|(ts, _)| ts)
+                        .unwrap()
+                };
+                let count = discard_before(
+                    key,
+                    buffer,
+                    ts,
+                    dead_letter_tx,
+                    DropReason::OutOfWindow,
+                    commit_ts,
+                );
+                stats.record_drops(key, count);
                 let item = buffer.pop_front().unwrap();
-                assert!(item.timestamp() <= window_end);
-                (key.clone(), item)
+                assert!(ts <= window_end);
+                timestamps.push(ts);
+                Some((key.clone(), item))
             })
             .collect();
 
+        let (window_start, _) = compute_window(anchor_ts, self.window_size);
+        self.last_window = Some((window_start, window_end));
+
+        #[cfg(feature = "tracing")]
+        Span::current()
+            .record("window_start", field::debug(window_start))
+            .record("window_end", field::debug(window_end));
+
         // update commit timestamp
-        let new_commit_ts = items.values().map(|item| item.timestamp()).min().unwrap();
+        let new_commit_ts = timestamps.iter().copied().min().unwrap();
         self.commit_ts = Some(new_commit_ts);
+        self.feedback_dirty = true;
+
+        let max_ts = timestamps.iter().copied().max().unwrap();
+        self.update_window_size(max_ts - new_commit_ts);
+
+        self.absent_keys = self
+            .buffers
+            .keys()
+            .filter(|key| !items.contains_key(*key))
+            .cloned()
+            .collect();
+        self.stats.record_group();
 
         Some(items)
     }
 
+    /// Record the timestamp spread of an emitted group and adjust the
+    /// window size if the adaptive policy is enabled.
+    fn update_window_size(&mut self, spread: Duration) {
+        let WindowSizePolicy::Adaptive {
+            min,
+            max,
+            percentile,
+        } = self.window_size_policy
+        else {
+            return;
+        };
+
+        if self.spread_history.len() >= SPREAD_HISTORY_LEN {
+            self.spread_history.pop_front();
+        }
+        self.spread_history.push_back(spread);
+
+        let mut spreads: Vec<_> = self.spread_history.iter().copied().collect();
+        spreads.sort_unstable();
+        let index = ((spreads.len() - 1) as f64 * percentile).round() as usize;
+        let window_size = spreads[index].mul_f64(SPREAD_SAFETY_FACTOR);
+
+        self.window_size = window_size.clamp(min, max) + self.jitter_tolerance;
+    }
+
+    /// Gets the anchor timestamp of the time window. It defaults to
+    /// the inf timestamp unless anchor weights are configured.
+    ///
+    /// The weighted anchor is never placed more than one window
+    /// before `inf_ts` so that all front messages still fit in the
+    /// window.
+    fn anchor_timestamp(&self, inf_ts: Duration) -> Duration {
+        let Some(weights) = &self.anchor_weights else {
+            return inf_ts;
+        };
+
+        match compute_anchor(&self.buffers, weights) {
+            Some(anchor_ts) => anchor_ts.max(inf_ts.saturating_sub(self.window_size)),
+            None => inf_ts,
+        }
+    }
+
+    /// Iterates over buffers of keys which are not paused.
+    fn active_buffers(&self) -> impl Iterator<Item = (&K, &Buffer<T>)> {
+        self.buffers.iter().filter(|(key, _)| {
+            !self.paused_keys.contains(*key) && !self.optional_keys.contains(*key)
+        })
+    }
+
     /// Gets the minimum of the maximum timestamps from each buffer.
     pub fn sup_timestamp(&self) -> Option<(K, Duration)> {
-        self.buffers
-            .iter()
+        self.active_buffers()
             .filter_map(|(key, buffer)| {
                 // Get the latest timestamp
-                let ts = buffer.back()?.timestamp();
+                let ts = buffer.back_ts()?;
                 Some((key.clone(), ts))
             })
             .min_by_key(|(_, ts)| *ts)
     }
 
-    /// Gets the maximum of the minimum timestamps from each buffer.
-    pub fn inf_timestamp(&self) -> Option<(K, Duration)> {
+    /// Gets how far the latest buffered timestamp of each key lags
+    /// behind the maximum one among all keys. The lag is `None` if the
+    /// buffer is empty. A key with a large lag holds back the
+    /// synchronization.
+    pub fn key_lag_map(&self) -> IndexMap<K, Option<Duration>> {
+        let max_ts = self.buffers.values().filter_map(Buffer::back_ts).max();
         self.buffers
             .iter()
+            .map(|(key, buffer)| {
+                let lag = buffer.back_ts().zip(max_ts).map(|(ts, max_ts)| max_ts - ts);
+                (key.clone(), lag)
+            })
+            .collect()
+    }
+
+    /// Gets the key with the highest lag, i.e. the slowest stream.
+    /// See [key_lag_map](State::key_lag_map).
+    pub fn bottleneck_key(&self) -> Option<K> {
+        self.buffers
+            .iter()
+            .filter_map(|(key, buffer)| Some((key, buffer.back_ts()?)))
+            .min_by_key(|(_, ts)| *ts)
+            .map(|(key, _)| key.clone())
+    }
+
+    /// Gets the maximum of the minimum timestamps from each buffer.
+    pub fn inf_timestamp(&self) -> Option<(K, Duration)> {
+        self.active_buffers()
             .filter_map(|(key, buffer)| {
                 // Get the earliest timestamp
-                let ts = buffer.front()?.timestamp();
+                let ts = buffer.front_ts()?;
                 Some((key.clone(), ts))
             })
             .max_by_key(|(_, ts)| *ts)
@@ -165,83 +797,339 @@ where
             .iter()
             .filter_map(|(key, buffer)| {
                 // Get the earliest timestamp
-                let ts = buffer.front()?.timestamp();
+                let ts = buffer.front_ts()?;
                 Some((key.clone(), ts))
             })
             .min_by_key(|(_, ts)| *ts)
     }
 
-    /// Checks if every buffer size reaches the limit.
+    /// Checks if every buffer size reaches its limit, or the total
+    /// number of messages reaches the global limit.
     pub fn is_full(&self) -> bool {
-        self.buffers
-            .values()
-            .all(|buffer| buffer.len() >= self.buf_size)
+        self.exceeds_total_pending()
+            || self
+                .active_buffers()
+                .all(|(key, buffer)| buffer.len() >= self.key_buf_size(key))
+    }
+
+    /// Gets the maximum buffer size of the key, which falls back to
+    /// `buf_size` if no per-key size is set.
+    pub fn key_buf_size(&self, key: &K) -> usize {
+        self.per_key_buf_size
+            .get(key)
+            .copied()
+            .unwrap_or(self.buf_size)
+    }
+
+    /// Gets the total number of messages across all buffers.
+    pub fn total_pending(&self) -> usize {
+        self.buffers.values().map(|buffer| buffer.len()).sum()
+    }
+
+    /// Checks if the total number of messages reaches the global
+    /// limit.
+    pub fn exceeds_total_pending(&self) -> bool {
+        self.max_total_pending
+            .is_some_and(|max_total_pending| self.total_pending() >= max_total_pending)
     }
 
     /// Checks if every buffer receives at least two messages.
     pub fn is_ready(&self) -> bool {
-        self.buffers.values().all(|buffer| buffer.len() >= 2)
+        self.active_buffers().all(|(_, buffer)| buffer.len() >= 2)
     }
 
-    /// Checks if there are buffers which are empty.
+    /// Checks if any active buffer is empty. No group can be formed
+    /// until the empty buffers receive messages.
+    ///
+    /// Use [all_buffers_empty](State::all_buffers_empty) to check if
+    /// no messages are left at all.
     pub fn is_empty(&self) -> bool {
-        // self.buffers.values().all(|buffer| buffer.is_empty())
-        let buffers = self.buffers.iter();
-        for item in buffers {
-            let (_key, buffer) = item;
-            if buffer.is_empty() {
-                return true;
-            } else {
-                continue;
-            }
-        }
-        false
+        self.active_buffers().any(|(_, buffer)| buffer.is_empty())
+    }
+
+    /// Checks if all buffers, including paused ones, are empty.
+    pub fn all_buffers_empty(&self) -> bool {
+        self.buffers.values().all(|buffer| buffer.is_empty())
     }
 
     /// Checks if all buffers have only one data left.
     pub fn all_one(&self) -> bool {
-        self.buffers.values().all(|buffer| buffer.len() == 1)
+        self.active_buffers().all(|(_, buffer)| buffer.len() == 1)
+    }
+
+    /// Pause a stream so that it is not required to form a group. Its
+    /// messages are still accepted and grouped when they fall within
+    /// the window. Returns false if the key is unknown or it is the
+    /// last stream that is not paused.
+    pub fn pause_key(&mut self, key: K) -> bool {
+        if !self.buffers.contains_key(&key) {
+            return false;
+        }
+
+        let active_count = self.active_buffers().count();
+        if active_count <= 1 && !self.paused_keys.contains(&key) {
+            return false;
+        }
+
+        self.paused_keys.insert(key);
+        true
+    }
+
+    /// Resume a paused stream so that it is required to form a
+    /// group again. Returns false if the key is not paused.
+    pub fn resume_key(&mut self, key: &K) -> bool {
+        self.paused_keys.shift_remove(key)
+    }
+
+    /// Register a new stream at runtime. Like the streams given at
+    /// start, it is required to form a group unless paused. Returns
+    /// false if the key is already registered.
+    pub fn add_key(&mut self, key: K) -> bool {
+        if self.buffers.contains_key(&key) {
+            return false;
+        }
+
+        let capacity = self.key_buf_size(&key);
+        let mut buffer = Buffer::with_capacity(capacity);
+        buffer.set_out_of_order_tolerance(self.out_of_order_tolerance);
+        self.buffers.insert(key, buffer);
+        self.feedback_dirty = true;
+        true
+    }
+
+    /// Deregister the stream identified by the key after the stream
+    /// ends. Pending messages of the stream are discarded. Returns
+    /// false if fewer than `minimum_streams_required` streams remain.
+    pub fn remove_key(&mut self, key: &K) -> bool {
+        if let Some(buffer) = self.buffers.shift_remove(key) {
+            self.stats.record_drops(key, buffer.len());
+        }
+        self.paused_keys.shift_remove(key);
+        self.optional_keys.shift_remove(key);
+        self.last_received_wall.shift_remove(key);
+        self.feedback_dirty = true;
+
+        // Paused and optional keys cannot form groups on their own.
+        if self.active_buffers().next().is_none() {
+            self.paused_keys.clear();
+            self.optional_keys.clear();
+        }
+
+        !self.buffers.is_empty() && self.buffers.len() >= self.minimum_streams_required
+    }
+
+    /// Form the next group from the buffered messages without waiting
+    /// for more messages. See [try_match_now](State::try_match_now).
+    /// Expired messages are dropped first, and then messages with the
+    /// minimum timestamp are dropped until a group is formed or any
+    /// active buffer is empty.
+    pub fn flush_next(&mut self) -> Option<IndexMap<K, T>> {
+        loop {
+            // Clean up expired messages using the latest commit timestamp as reference
+            if let Some(commit_ts) = self.commit_ts {
+                let _expired_count = self.drop_expired_messages(commit_ts);
+            }
+
+            // Stop once any buffer is exhausted. The remaining
+            // messages in other buffers can never form a full group.
+            if self.is_empty() {
+                return None;
+            } else if let Some(group) = self.try_match_now() {
+                return Some(group);
+            } else if !self.drop_min_for(DropReason::OutOfWindow) {
+                return None;
+            }
+        }
     }
+
+    /// Emit all groups formable from the buffered messages once the
+    /// input ends, and reset the commit timestamp so that the state
+    /// can take a new input starting from an earlier time.
+    ///
+    /// Optional and paused streams join the groups whenever a message
+    /// falls within the window, so partial groups are emitted as long
+    /// as the required streams have messages.
+    pub fn flush(&mut self) -> Vec<IndexMap<K, T>> {
+        let groups = self.drain_to_vec();
+        self.commit_ts = None;
+        self.feedback_dirty = true;
+        groups
+    }
+
     /// Remove the message with the minimum timestamp among all
     /// buffers. Returns true if a message is dropped.
     pub fn drop_min(&mut self) -> bool {
+        self.drop_min_for(DropReason::BufferFull)
+    }
+
+    fn drop_min_for(&mut self, drop_reason: DropReason) -> bool {
         let Some((_, min_ts)) = self.min_timestamp() else {
             return false;
         };
 
-        self.buffers.values_mut().for_each(|buffer| {
-            if let Some(front) = buffer.front() {
-                if front.timestamp() == min_ts {
-                    buffer.pop_front();
-                }
+        #[cfg(feature = "tracing")]
+        debug!(timestamp = ?min_ts, "drop messages with the minimum timestamp");
+
+        let stats = &mut self.stats;
+        let dead_letter_tx = &mut self.dead_letter_tx;
+        let commit_ts = self.commit_ts;
+        self.buffers.iter_mut().for_each(|(key, buffer)| {
+            if buffer.front_ts() == Some(min_ts) {
+                let item = buffer.pop_front();
+                send_dead_letters(dead_letter_tx, key, item, drop_reason, commit_ts);
+                stats.record_drops(key, 1);
             }
         });
+        self.feedback_dirty = true;
 
         true
     }
 
+    /// Emit all remaining groups until any one of the buffers is
+    /// depleted. Messages that cannot be grouped are dropped.
+    pub fn drain_to_vec(&mut self) -> Vec<IndexMap<K, T>> {
+        iter::from_fn(|| self.flush_next()).collect()
+    }
+
+    /// Seek forward in time. Messages before `target_ts` are dropped
+    /// from all buffers and the commit timestamp is advanced to
+    /// `target_ts` if it is behind.
+    pub fn fast_forward(&mut self, target_ts: Duration) {
+        let stats = &mut self.stats;
+        let dead_letter_tx = &mut self.dead_letter_tx;
+        let commit_ts = self.commit_ts;
+        self.buffers.iter_mut().for_each(|(key, buffer)| {
+            let count = discard_before(
+                key,
+                buffer,
+                target_ts,
+                dead_letter_tx,
+                DropReason::OutOfWindow,
+                commit_ts,
+            );
+            stats.record_drops(key, count);
+        });
+
+        self.commit_ts = Some(match self.commit_ts {
+            Some(commit_ts) => commit_ts.max(target_ts),
+            None => target_ts,
+        });
+        self.feedback_dirty = true;
+    }
+
     /// Drop expired messages from all buffers based on reference timestamp.
     /// Returns the total number of dropped messages.
     pub fn drop_expired_messages(&mut self, reference_timestamp: Duration) -> usize {
-        self.buffers
-            .values_mut()
-            .map(|buffer| buffer.drop_expired(reference_timestamp))
-            .sum()
+        let stats = &mut self.stats;
+        let dead_letter_tx = &mut self.dead_letter_tx;
+        let commit_ts = self.commit_ts;
+        let count: usize = self
+            .buffers
+            .iter_mut()
+            .map(|(key, buffer)| {
+                let count = if dead_letter_tx.is_some() {
+                    let items = buffer.drain_expired(reference_timestamp);
+                    let count = items.len();
+                    send_dead_letters(dead_letter_tx, key, items, DropReason::Expired, commit_ts);
+                    count
+                } else {
+                    buffer.drop_expired(reference_timestamp)
+                };
+                stats.record_drops(key, count);
+                count
+            })
+            .sum();
+        if count > 0 {
+            self.feedback_dirty = true;
+        }
+        count
     }
 
     /// Insert a message to the queue identified by the key. It
     /// returns true if the message is successfully inserted.
     pub fn push(&mut self, key: K, item: T) -> Result<(), T> {
-        let timestamp = item.timestamp();
+        #[cfg(debug_assertions)]
+        let order_check = self.push_order_check(&key);
+        #[cfg(debug_assertions)]
+        self.assert_invariants_with(order_check);
+
+        let timestamp = self.adjusted_timestamp(&key, item.timestamp());
+        let result = self.push_inner(key, timestamp, item);
+
+        #[cfg(debug_assertions)]
+        self.assert_invariants_with(order_check);
+
+        result
+    }
+
+    /// Insert messages in bulk and update the feedback once after all
+    /// insertions. It returns the number of inserted messages.
+    /// Rejected messages are dropped.
+    pub fn extend_counting<I>(&mut self, iter: I) -> usize
+    where
+        I: IntoIterator<Item = (K, T)>,
+    {
+        let mut count = 0;
+        for (key, item) in iter {
+            if self.push(key, item).is_ok() {
+                count += 1;
+            }
+        }
+        self.update_feedback();
+        count
+    }
+
+    /// Insert a message with the timestamp used for matching in place
+    /// of the one carried by the message. Timestamp offsets are not
+    /// applied.
+    pub fn push_with_timestamp(&mut self, key: K, timestamp: Duration, item: T) -> Result<(), T> {
+        #[cfg(debug_assertions)]
+        let order_check = self.push_order_check(&key);
+        #[cfg(debug_assertions)]
+        self.assert_invariants_with(order_check);
+
+        let result = self.push_inner(key, timestamp, item);
+
+        #[cfg(debug_assertions)]
+        self.assert_invariants_with(order_check);
+
+        result
+    }
+
+    fn push_inner(&mut self, key: K, timestamp: Duration, item: T) -> Result<(), T> {
+        self.stats.record_received();
+
+        // Late messages are also counted as received.
+        let is_known = self.buffers.contains_key(&key);
+        if is_known {
+            self.last_received_wall.insert(key.clone(), Instant::now());
+        }
 
         match self.commit_ts {
-            Some(commit_ts) if commit_ts >= timestamp => return Err(item),
+            Some(commit_ts) if commit_ts >= timestamp => {
+                if is_known {
+                    self.stats.record_drops(&key, 1);
+                    let drop_reason = if self.start_time == Some(commit_ts) {
+                        DropReason::BeforeStartTime
+                    } else {
+                        DropReason::LateArrival
+                    };
+                    self.report_dropped(&key, &item, drop_reason);
+                }
+                return Err(item);
+            }
             _ => {}
         }
 
-        let Some(buffer) = self.buffers.get_mut(&key) else {
+        let capacity = self.key_buf_size(&key);
+        let Some(buffer) = self.buffers.get(&key) else {
             return Err(item);
         };
+        if self.drop_policy == DropPolicy::DropNewest && buffer.len() >= capacity {
+            self.stats.record_drops(&key, 1);
+            self.report_dropped(&key, &item, DropReason::BufferFull);
+            return Err(item);
+        }
 
         // Add to staleness detector if configured
         if let Some(ref mut staleness_detector) = self.staleness_detector {
@@ -250,11 +1138,202 @@ where
             staleness_detector.add_message(key.clone(), item.clone(), staleness_timeout);
         }
 
-        buffer.try_push(item)
+        self.feedback_dirty = true;
+        // Buffers of the state have no capacity limit unless set
+        // manually. A message rejected for either reason is returned.
+        let buffer = &mut self.buffers[&key];
+        let result = if self.allow_equal_timestamps {
+            buffer.try_push_equal_with_timestamp(timestamp, item)
+        } else {
+            buffer.try_push_with_timestamp(timestamp, item)
+        };
+        match result {
+            Ok(()) => Ok(()),
+            Err(err) => {
+                let drop_reason = match err {
+                    PushError::OutOfOrder(_) => DropReason::LateArrival,
+                    PushError::BufferFull(_) => DropReason::BufferFull,
+                };
+                let item = err.into_inner();
+                self.stats.record_drops(&key, 1);
+                self.report_dropped(&key, &item, drop_reason);
+                Err(item)
+            }
+        }
     }
 
-    /// Process expired messages from staleness detector and remove them from buffers
-    pub fn process_staleness_expiration(&mut self) -> usize {
+    /// Reports a dropped message to the dead-letter channel if it is
+    /// enabled. The message is only cloned in that case.
+    fn report_dropped(&mut self, key: &K, item: &T, drop_reason: DropReason) {
+        if self.dead_letter_tx.is_some() {
+            let commit_ts = self.commit_ts;
+            send_dead_letters(
+                &mut self.dead_letter_tx,
+                key,
+                [item.clone()],
+                drop_reason,
+                commit_ts,
+            );
+        }
+    }
+
+    /// Gets the position of the key in the key list, which identifies
+    /// the key in trace events since keys are not required to
+    /// implement `Debug`.
+    #[cfg(feature = "tracing")]
+    pub(crate) fn key_index(&self, key: &K) -> Option<usize> {
+        self.buffers.get_index_of(key)
+    }
+
+    #[cfg(feature = "tracing")]
+    fn key_indices(&self, keys: &[K]) -> Vec<Option<usize>> {
+        keys.iter().map(|key| self.key_index(key)).collect()
+    }
+
+    /// Gets the range of committed timestamps `(start, end)`, where no
+    /// message within the range can appear in future groups. The
+    /// range starts from the start time, or zero if not set, and ends
+    /// at the commit timestamp. Returns `None` if no group is
+    /// committed yet.
+    pub fn committed_range(&self) -> Option<(Duration, Duration)> {
+        let commit_ts = self.commit_ts?;
+        if Some(commit_ts) == self.start_time {
+            return None;
+        }
+        Some((self.start_time.unwrap_or(Duration::ZERO), commit_ts))
+    }
+
+    /// Checks if messages at the timestamp are rejected because it is
+    /// at or before the commit timestamp, or the start time if no
+    /// group is committed yet.
+    pub fn is_timestamp_committed(&self, ts: Duration) -> bool {
+        self.commit_ts
+            .or(self.start_time)
+            .is_some_and(|commit_ts| ts <= commit_ts)
+    }
+
+    /// Gets the counters of received, emitted and discarded messages.
+    pub fn stats(&self) -> &SyncStats<K> {
+        &self.stats
+    }
+
+    /// Returns the wall-clock time elapsed since a message of the key
+    /// was last received, or `None` if none was received.
+    pub fn last_received_age(&self, key: &K) -> Option<Duration> {
+        self.last_received_wall
+            .get(key)
+            .map(|received| received.elapsed())
+    }
+
+    /// Returns true if no message of the key was received within
+    /// `threshold`, including the case that none was ever received.
+    pub fn is_stale(&self, key: &K, threshold: Duration) -> bool {
+        match self.last_received_age(key) {
+            Some(age) => age > threshold,
+            None => true,
+        }
+    }
+
+    /// Applies the timestamp offset of the key to the timestamp.
+    pub fn adjusted_timestamp(&self, key: &K, timestamp: Duration) -> Duration {
+        let Some(offset_ns) = self
+            .timestamp_offsets
+            .as_ref()
+            .and_then(|offsets| offsets.get(key).copied())
+        else {
+            return timestamp;
+        };
+
+        let offset = Duration::from_nanos(offset_ns.unsigned_abs());
+        if offset_ns >= 0 {
+            timestamp.saturating_add(offset)
+        } else {
+            timestamp.saturating_sub(offset)
+        }
+    }
+
+    /// Panics if any internal invariant is violated. See
+    /// [is_consistent](State::is_consistent).
+    ///
+    /// In debug builds, the invariants are also checked at the start
+    /// and end of [push](State::push) and [try_match](State::try_match).
+    pub fn assert_invariants(&self) {
+        self.assert_invariants_with(OrderCheck::All);
+    }
+
+    /// Selects the buffer a push to the key can put out of order.
+    #[cfg(debug_assertions)]
+    fn push_order_check(&self, key: &K) -> OrderCheck {
+        self.buffers
+            .get_index_of(key)
+            .map_or(OrderCheck::Skip, OrderCheck::Back)
+    }
+
+    /// Panics if any internal invariant is violated, checking the
+    /// ordering of only the buffers selected by `order_check`.
+    fn assert_invariants_with(&self, order_check: OrderCheck) {
+        if let Some(violation) = self.invariant_violation(order_check) {
+            panic!("State invariant violated: {violation}");
+        }
+    }
+
+    /// Checks the internal invariants:
+    ///
+    /// - Timestamps in each buffer are strictly increasing, or
+    ///   non-decreasing if equal timestamps are allowed.
+    /// - The commit timestamp is not ahead of the back timestamp of
+    ///   any buffer by more than the window size.
+    ///
+    /// Two more conditions do not hold in every valid state and are not
+    /// checked:
+    ///
+    /// - The buffer length may exceed `buf_size`, because messages are
+    ///   still buffered while other streams lag behind.
+    /// - [inf_timestamp](State::inf_timestamp) may be ahead of
+    ///   [sup_timestamp](State::sup_timestamp), e.g. when a lagging
+    ///   stream has a single message older than every message of the
+    ///   other streams. Matching waits for more messages in that case.
+    pub fn is_consistent(&self) -> bool {
+        self.invariant_violation(OrderCheck::All).is_none()
+    }
+
+    fn invariant_violation(&self, order_check: OrderCheck) -> Option<String> {
+        for (index, buffer) in self.buffers.values().enumerate() {
+            let ordered = match order_check {
+                OrderCheck::All if self.allow_equal_timestamps => buffer.is_non_decreasing(),
+                OrderCheck::All => buffer.is_monotonic(),
+                OrderCheck::Back(pushed) if pushed == index => {
+                    if self.allow_equal_timestamps {
+                        buffer.is_back_non_decreasing()
+                    } else {
+                        buffer.is_back_monotonic()
+                    }
+                }
+                _ => true,
+            };
+            if !ordered {
+                return Some(if self.allow_equal_timestamps {
+                    format!("timestamps in buffer {index} are decreasing")
+                } else {
+                    format!("timestamps in buffer {index} are not strictly increasing")
+                });
+            }
+
+            if let (Some(commit_ts), Some(back_ts)) = (self.commit_ts, buffer.back_ts()) {
+                if commit_ts > back_ts.saturating_add(self.window_size) {
+                    return Some(format!(
+                        "commit timestamp {commit_ts:?} is ahead of buffer {index} \
+                         back timestamp {back_ts:?} by more than the window size"
+                    ));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Process expired messages from staleness detector and remove them from buffers
+    pub fn process_staleness_expiration(&mut self) -> usize {
         if let Some(ref mut staleness_detector) = self.staleness_detector {
             let expired_messages = staleness_detector.drain_expired();
             let mut removed_count = 0;
@@ -266,13 +1345,24 @@ where
                     // This is a limitation of the current buffer implementation
                     if let Some(front_msg) = buffer.front() {
                         if front_msg.timestamp() == expired_message.timestamp() {
-                            buffer.pop_front();
+                            let item = buffer.pop_front();
+                            send_dead_letters(
+                                &mut self.dead_letter_tx,
+                                &key,
+                                item,
+                                DropReason::Expired,
+                                self.commit_ts,
+                            );
+                            self.stats.record_drops(&key, 1);
                             removed_count += 1;
                         }
                     }
                 }
             }
 
+            if removed_count > 0 {
+                self.feedback_dirty = true;
+            }
             removed_count
         } else {
             0
@@ -280,6 +1370,343 @@ where
     }
 }
 
+impl<K, T, S> Extend<(K, T)> for State<K, T, S>
+where
+    K: Key,
+    T: WithTimestamp + Clone,
+    S: BuildHasher,
+{
+    /// Insert messages in bulk. See
+    /// [extend_counting](State::extend_counting).
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = (K, T)>,
+    {
+        self.extend_counting(iter);
+    }
+}
+
+impl<K, T, S, E> Extend<Result<(K, T), E>> for State<K, T, S>
+where
+    K: Key,
+    T: WithTimestamp + Clone,
+    S: BuildHasher,
+{
+    /// Insert messages in bulk until the first error. The remaining
+    /// items are not consumed.
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = Result<(K, T), E>>,
+    {
+        let iter = iter.into_iter().map_while(Result::ok);
+        self.extend_counting(iter);
+    }
+}
+
+/// The internal state maintained by [sync](crate::sync) in session
+/// window mode.
+#[derive(Debug)]
+pub struct SessionState<K, T>
+where
+    K: Key,
+    T: WithTimestamp + Clone,
+{
+    /// The latest message of each stream in the current session.
+    pub session: IndexMap<K, Option<T>>,
+
+    /// Closed sessions which are not emitted yet.
+    pub closed: VecDeque<IndexMap<K, T>>,
+
+    /// The inactivity duration that closes a session.
+    pub gap: Duration,
+
+    /// The latest timestamp received across all streams.
+    pub last_ts: Option<Duration>,
+
+    /// Marks the timestamp where messages before the time point are
+    /// emitted.
+    pub commit_ts: Option<Duration>,
+
+    /// The sender where feedback messages are sent to.
+    pub feedback_tx: Option<watch::Sender<Feedback<K>>>,
+
+    /// The callback invoked once the feedback receiver is dropped.
+    pub on_feedback_dropped: Option<FeedbackDroppedCallback>,
+}
+
+impl<K, T> SessionState<K, T>
+where
+    K: Key,
+    T: WithTimestamp + Clone,
+{
+    /// Generate a feedback message.
+    pub fn update_feedback(&mut self) {
+        let Some(feedback_tx) = &self.feedback_tx else {
+            return;
+        };
+
+        if feedback_tx.is_closed() {
+            if let Some(on_feedback_dropped) = &self.on_feedback_dropped {
+                on_feedback_dropped.call();
+            }
+            self.feedback_tx = None;
+            return;
+        }
+
+        let msg = Feedback {
+            commit_timestamp: self.commit_ts,
+            ..Feedback::new(self.session.keys().cloned().collect())
+        };
+
+        if feedback_tx.send(msg).is_err() {
+            if let Some(on_feedback_dropped) = &self.on_feedback_dropped {
+                on_feedback_dropped.call();
+            }
+            self.feedback_tx = None;
+        }
+    }
+
+    /// Insert a message to the session. If the message arrives after
+    /// an inactivity gap, the current session is closed before the
+    /// message starts a new session.
+    pub fn push(&mut self, key: K, item: T) -> Result<(), T> {
+        let timestamp = item.timestamp();
+
+        match self.commit_ts {
+            Some(commit_ts) if commit_ts >= timestamp => return Err(item),
+            _ => {}
+        }
+
+        if !self.session.contains_key(&key) {
+            return Err(item);
+        }
+
+        if let Some(last_ts) = self.last_ts {
+            if timestamp.saturating_sub(last_ts) > self.gap {
+                self.close();
+            }
+        }
+
+        let slot = &mut self.session[&key];
+        if matches!(slot, Some(prev) if prev.timestamp() >= timestamp) {
+            return Err(item);
+        }
+        *slot = Some(item);
+
+        self.last_ts = Some(match self.last_ts {
+            Some(last_ts) => last_ts.max(timestamp),
+            None => timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Close the current session. Returns true if the session has
+    /// any message.
+    pub fn close(&mut self) -> bool {
+        let group: IndexMap<K, T> = self
+            .session
+            .iter_mut()
+            .filter_map(|(key, slot)| Some((key.clone(), slot.take()?)))
+            .collect();
+
+        if group.is_empty() {
+            return false;
+        }
+
+        self.commit_ts = self.last_ts;
+        self.closed.push_back(group);
+        true
+    }
+
+    /// Try to take a closed session.
+    pub fn try_match(&mut self) -> Option<IndexMap<K, T>> {
+        self.closed.pop_front()
+    }
+}
+
+/// A [State] running on compressed time for replaying recorded data
+/// faster than realtime.
+///
+/// Message timestamps are divided by `time_scale` before matching,
+/// so that a window size of 50 ms at a 10x scale covers 500 ms of
+/// message time. Timestamps kept by the inner state, including the
+/// commit timestamp, are on the scaled time line, while emitted
+/// messages keep their original timestamps.
+#[derive(Debug, Clone)]
+pub struct TimedState<K, T>
+where
+    K: Key,
+    T: WithTimestamp + Clone,
+{
+    state: State<K, T>,
+    time_scale: f64,
+}
+
+impl<K, T> TimedState<K, T>
+where
+    K: Key,
+    T: WithTimestamp + Clone,
+{
+    /// Wrap the state running on a time line compressed by
+    /// `time_scale`, which must be positive and finite.
+    pub fn new(state: State<K, T>, time_scale: f64) -> Result<Self> {
+        ensure!(
+            time_scale.is_finite() && time_scale > 0.0,
+            "time_scale must be positive and finite, but get {time_scale}"
+        );
+        Ok(Self { state, time_scale })
+    }
+
+    /// Get the factor that message timestamps are divided by.
+    pub fn time_scale(&self) -> f64 {
+        self.time_scale
+    }
+
+    /// Converts a message timestamp to the scaled time line. It
+    /// saturates at [Duration::MAX] for a very small `time_scale`.
+    pub fn scale(&self, timestamp: Duration) -> Duration {
+        Duration::try_from_secs_f64(timestamp.as_secs_f64() / self.time_scale)
+            .unwrap_or(Duration::MAX)
+    }
+
+    /// Get the inner state, whose timestamps are on the scaled time
+    /// line.
+    pub fn inner(&self) -> &State<K, T> {
+        &self.state
+    }
+
+    /// Unwrap the inner state.
+    pub fn into_inner(self) -> State<K, T> {
+        self.state
+    }
+
+    /// Insert a message to the queue identified by the key. The
+    /// timestamp offset of the key is applied before scaling.
+    pub fn push(&mut self, key: K, item: T) -> Result<(), T> {
+        let timestamp = self.state.adjusted_timestamp(&key, item.timestamp());
+        let timestamp = self.scale(timestamp);
+        self.state.push_with_timestamp(key, timestamp, item)
+    }
+
+    /// Try to group up messages within a time window.
+    pub fn try_match(&mut self) -> Option<IndexMap<K, T>> {
+        self.state.try_match()
+    }
+
+    /// See [State::drain_to_vec].
+    pub fn drain_to_vec(&mut self) -> Vec<IndexMap<K, T>> {
+        self.state.drain_to_vec()
+    }
+}
+
+/// Sends dropped messages to the dead-letter channel. The sender is
+/// cleared once the receiver is closed.
+fn send_dead_letters<K, T>(
+    dead_letter_tx: &mut Option<mpsc::UnboundedSender<DroppedMessage<K, T>>>,
+    key: &K,
+    items: impl IntoIterator<Item = T>,
+    drop_reason: DropReason,
+    dropped_at: Option<Duration>,
+) where
+    K: Key,
+{
+    let Some(tx) = dead_letter_tx else {
+        return;
+    };
+
+    for item in items {
+        let message = DroppedMessage {
+            key: key.clone(),
+            item,
+            drop_reason,
+            dropped_at,
+        };
+        if tx.send(message).is_err() {
+            *dead_letter_tx = None;
+            return;
+        }
+    }
+}
+
+/// Removes messages before `ts` from the buffer and returns the number
+/// of removed messages. The messages are moved to the dead-letter
+/// channel if it is enabled.
+fn discard_before<K, T>(
+    key: &K,
+    buffer: &mut Buffer<T>,
+    ts: Duration,
+    dead_letter_tx: &mut Option<mpsc::UnboundedSender<DroppedMessage<K, T>>>,
+    drop_reason: DropReason,
+    dropped_at: Option<Duration>,
+) -> usize
+where
+    K: Key,
+    T: WithTimestamp,
+{
+    if dead_letter_tx.is_none() {
+        return buffer.drop_before(ts);
+    }
+
+    let items = buffer.drain_before(ts);
+    let count = items.len();
+    send_dead_letters(dead_letter_tx, key, items, drop_reason, dropped_at);
+    count
+}
+
+/// Computes the weighted average of front timestamps of all
+/// buffers. Keys absent in `weights` have a weight of 1.0. It returns
+/// `None` if no buffer has messages or the total weight is not
+/// positive.
+pub fn compute_anchor<K, T, S>(
+    buffers: &IndexMap<K, Buffer<T>, S>,
+    weights: &IndexMap<K, f64>,
+) -> Option<Duration>
+where
+    K: Key,
+    T: WithTimestamp,
+{
+    let (weighted_sum, total_weight) = buffers
+        .iter()
+        .filter_map(|(key, buffer)| {
+            let ts = buffer.front_ts()?;
+            let weight = weights.get(key).copied().unwrap_or(1.0);
+            Some((ts.as_secs_f64() * weight, weight))
+        })
+        .fold((0.0, 0.0), |(sum, total), (value, weight)| {
+            (sum + value, total + weight)
+        });
+
+    if total_weight.is_nan() || total_weight <= 0.0 {
+        return None;
+    }
+
+    let anchor_secs = weighted_sum / total_weight;
+    if !anchor_secs.is_finite() || anchor_secs < 0.0 {
+        return None;
+    }
+
+    Some(Duration::from_secs_f64(anchor_secs))
+}
+
+/// Selects the buffers checked for ordering by the invariant checks.
+///
+/// Scanning every buffer after each push is quadratic in the buffer
+/// length, so the checks in debug builds only look at the messages
+/// that could have broken the ordering.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(not(debug_assertions), allow(dead_code))]
+enum OrderCheck {
+    /// Scan every buffer.
+    All,
+    /// Compare the latest message of the buffer at the index with the
+    /// one before it, which is the only pair a push can put out of
+    /// order.
+    Back(usize),
+    /// Skip the ordering check.
+    Skip,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -320,10 +1747,37 @@ mod tests {
         State {
             buffers,
             commit_ts: Some(Duration::from_millis(1000)),
+            start_time: None,
             buf_size,
             window_size: Duration::from_millis(window_size_ms),
             feedback_tx: None,
             staleness_detector: None,
+            anchor_weights: None,
+            paused_keys: IndexSet::new(),
+            window_size_policy: WindowSizePolicy::Fixed,
+            spread_history: VecDeque::new(),
+            max_total_pending: None,
+            minimum_streams_required: 1,
+            timestamp_offsets: None,
+            on_feedback_dropped: None,
+            fingerprint_history: None,
+            dedup_history: 0,
+            feedback_dirty: true,
+            last_received_wall: IndexMap::new(),
+            priority: None,
+            key_feedback_tx: None,
+            dead_letter_tx: None,
+            jitter_tolerance: Duration::ZERO,
+            optional_keys: IndexSet::new(),
+            absent_keys: vec![],
+            per_key_buf_size: IndexMap::new(),
+            matching_strategy: MatchingStrategy::First,
+            last_window: None,
+            flush_timeout: None,
+            allow_equal_timestamps: false,
+            drop_policy: DropPolicy::DropOldest,
+            out_of_order_tolerance: Duration::ZERO,
+            stats: SyncStats::default(),
         }
     }
 
@@ -378,6 +1832,23 @@ mod tests {
         assert!(state.is_full());
     }
 
+    #[test]
+    fn test_state_is_full_per_key_buf_size() {
+        let mut state = create_test_state(2, 100);
+        state.per_key_buf_size.insert("B", 3);
+        assert_eq!(state.key_buf_size(&"A"), 2);
+        assert_eq!(state.key_buf_size(&"B"), 3);
+
+        state.push("A", create_message(1500)).unwrap();
+        state.push("A", create_message(1600)).unwrap();
+        state.push("B", create_message(1500)).unwrap();
+        state.push("B", create_message(1600)).unwrap();
+        assert!(!state.is_full());
+
+        state.push("B", create_message(1700)).unwrap();
+        assert!(state.is_full());
+    }
+
     #[test]
     fn test_state_is_empty_all_empty() {
         let state = create_test_state(4, 100);
@@ -402,6 +1873,16 @@ mod tests {
         assert!(!state.is_empty());
     }
 
+    #[test]
+    fn test_state_all_buffers_empty() {
+        let mut state = create_test_state(4, 100);
+        assert!(state.all_buffers_empty());
+
+        state.push("A", create_message(1500)).unwrap();
+        assert!(state.is_empty());
+        assert!(!state.all_buffers_empty());
+    }
+
     #[test]
     fn test_state_all_one_false_when_empty() {
         let state = create_test_state(4, 100);
@@ -465,42 +1946,145 @@ mod tests {
     }
 
     #[test]
-    fn test_state_push_buffer_not_found() {
+    fn test_state_push_equal_timestamps() {
         let mut state = create_test_state(4, 100);
+        state.push("A", create_message(1500)).unwrap();
+        state.push("A", create_message(1500)).unwrap();
+        assert_eq!(state.buffers["A"].len(), 1);
 
-        let result = state.push("C", create_message(1500));
-        assert!(result.is_err());
+        state.allow_equal_timestamps = true;
+        state.push("A", create_message(1500)).unwrap();
+        assert_eq!(state.buffers["A"].len(), 2);
+        assert!(state.is_consistent());
     }
 
     #[test]
-    fn test_state_drop_min_no_messages() {
+    fn test_state_push_drop_newest() {
+        let mut state = create_test_state(2, 100);
+        state.drop_policy = DropPolicy::DropNewest;
+
+        state.push("A", create_message(1500)).unwrap();
+        state.push("A", create_message(2000)).unwrap();
+        assert!(state.push("A", create_message(2500)).is_err());
+        assert_eq!(
+            state.buffers["A"].back_ts(),
+            Some(Duration::from_millis(2000))
+        );
+        assert_eq!(state.stats().drop_counts["A"], 1);
+    }
+
+    #[test]
+    fn test_state_push_out_of_order_within_tolerance() {
         let mut state = create_test_state(4, 100);
+        state.push("A", create_message(1500)).unwrap();
+        assert!(state.push("A", create_message(1450)).is_err());
 
-        let dropped = state.drop_min();
-        assert!(!dropped);
+        state
+            .buffers
+            .values_mut()
+            .for_each(|buffer| buffer.set_out_of_order_tolerance(Duration::from_millis(100)));
+        state.push("A", create_message(1450)).unwrap();
+        assert!(state.push("A", create_message(1350)).is_err());
+        assert_eq!(
+            state.buffers["A"].front_ts(),
+            Some(Duration::from_millis(1450))
+        );
+        assert!(state.is_consistent());
     }
 
     #[test]
-    fn test_state_drop_min_multiple_buffers() {
+    fn test_state_committed_range() {
         let mut state = create_test_state(4, 100);
+        state.commit_ts = Some(Duration::from_millis(500));
+        state.start_time = Some(Duration::from_millis(500));
+        assert_eq!(state.committed_range(), None);
+        assert!(state.is_timestamp_committed(Duration::from_millis(500)));
+        assert!(!state.is_timestamp_committed(Duration::from_millis(501)));
 
         state.push("A", create_message(1500)).unwrap();
-        state.push("A", create_message(2000)).unwrap();
-        state.push("B", create_message(1100)).unwrap();
-        state.push("B", create_message(2500)).unwrap();
-
-        let dropped = state.drop_min();
-        assert!(dropped);
+        state.push("B", create_message(1510)).unwrap();
+        state.try_match().unwrap();
+        assert_eq!(
+            state.committed_range(),
+            Some((Duration::from_millis(500), Duration::from_millis(1500)))
+        );
+        assert!(state.is_timestamp_committed(Duration::from_millis(1500)));
+        assert!(!state.is_timestamp_committed(Duration::from_millis(1501)));
 
-        let (_, min_ts) = state.min_timestamp().unwrap();
-        assert_eq!(min_ts, Duration::from_millis(1500));
+        state.flush();
+        assert_eq!(state.committed_range(), None);
+        assert!(state.is_timestamp_committed(Duration::from_millis(500)));
     }
 
     #[test]
-    fn test_state_try_match_no_match_possible() {
+    fn test_state_dead_letters() {
         let mut state = create_test_state(4, 100);
-
-        // Messages too far apart
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        state.dead_letter_tx = Some(tx);
+
+        // The message is before the commit timestamp.
+        assert!(state.push("A", create_message(500)).is_err());
+        let dropped = rx.try_recv().unwrap();
+        assert_eq!(dropped.key, "A");
+        assert_eq!(dropped.item, create_message(500));
+        assert_eq!(dropped.drop_reason, DropReason::LateArrival);
+        assert_eq!(dropped.dropped_at, Some(Duration::from_millis(1000)));
+
+        state.push("A", create_message(1100)).unwrap();
+        state.push("B", create_message(1110)).unwrap();
+        state.push("A", create_message(1200)).unwrap();
+        state.fast_forward(Duration::from_millis(1150));
+        let dropped: Vec<_> = iter::from_fn(|| rx.try_recv().ok()).collect();
+        assert_eq!(dropped.len(), 2);
+        assert!(dropped
+            .iter()
+            .all(|dropped| dropped.drop_reason == DropReason::OutOfWindow));
+        assert_eq!(dropped[0].item, create_message(1100));
+        assert_eq!(dropped[1].item, create_message(1110));
+
+        // The sender is cleared once the receiver is closed.
+        drop(rx);
+        assert!(state.push("B", create_message(900)).is_err());
+        assert!(state.dead_letter_tx.is_none());
+    }
+
+    #[test]
+    fn test_state_push_buffer_not_found() {
+        let mut state = create_test_state(4, 100);
+
+        let result = state.push("C", create_message(1500));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_state_drop_min_no_messages() {
+        let mut state = create_test_state(4, 100);
+
+        let dropped = state.drop_min();
+        assert!(!dropped);
+    }
+
+    #[test]
+    fn test_state_drop_min_multiple_buffers() {
+        let mut state = create_test_state(4, 100);
+
+        state.push("A", create_message(1500)).unwrap();
+        state.push("A", create_message(2000)).unwrap();
+        state.push("B", create_message(1100)).unwrap();
+        state.push("B", create_message(2500)).unwrap();
+
+        let dropped = state.drop_min();
+        assert!(dropped);
+
+        let (_, min_ts) = state.min_timestamp().unwrap();
+        assert_eq!(min_ts, Duration::from_millis(1500));
+    }
+
+    #[test]
+    fn test_state_try_match_no_match_possible() {
+        let mut state = create_test_state(4, 100);
+
+        // Messages too far apart
         state.push("A", create_message(2000)).unwrap();
         state.push("A", create_message(2600)).unwrap();
         state.push("B", create_message(2500)).unwrap();
@@ -510,6 +2094,936 @@ mod tests {
         assert!(result.is_none());
     }
 
+    #[test]
+    fn test_state_feedback_dropped_callback() {
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+
+        let (feedback_tx, feedback_rx) = watch::channel(Feedback::new(vec![]));
+        let count = Arc::new(AtomicUsize::new(0));
+        let mut state = create_test_state(4, 100);
+        state.feedback_tx = Some(feedback_tx);
+        state.on_feedback_dropped = Some(FeedbackDroppedCallback::new({
+            let count = count.clone();
+            move || {
+                count.fetch_add(1, Ordering::SeqCst);
+            }
+        }));
+
+        state.update_feedback();
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+
+        drop(feedback_rx);
+        state.update_feedback();
+        state.update_feedback();
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        assert!(state.feedback_tx.is_none());
+    }
+
+    #[test]
+    fn test_state_update_feedback_only_when_dirty() {
+        let (feedback_tx, mut feedback_rx) = watch::channel(Feedback::new(vec![]));
+        let mut state = create_test_state(4, 100);
+        state.feedback_tx = Some(feedback_tx);
+
+        state.update_feedback();
+        assert!(feedback_rx.has_changed().unwrap());
+        feedback_rx.borrow_and_update();
+
+        // Nothing changed since the last feedback.
+        state.update_feedback();
+        assert!(!feedback_rx.has_changed().unwrap());
+
+        state.push("A", create_message(1500)).unwrap();
+        state.update_feedback();
+        assert!(feedback_rx.has_changed().unwrap());
+        assert!(!state.feedback_dirty);
+    }
+
+    #[test]
+    fn test_state_feedback_buffer_usage() {
+        let (feedback_tx, feedback_rx) = watch::channel(Feedback::new(vec![]));
+        let mut state = create_test_state(4, 100);
+        state.per_key_buf_size.insert("B", 2);
+        state.feedback_tx = Some(feedback_tx);
+
+        state.push("A", create_message(1500)).unwrap();
+        state.push("B", create_message(1500)).unwrap();
+        state.push("B", create_message(1600)).unwrap();
+        state.update_feedback();
+
+        let feedback = feedback_rx.borrow();
+        assert_eq!(feedback.buffer_usage["A"], 0.25);
+        assert_eq!(feedback.buffer_usage["B"], 1.0);
+        assert_eq!(feedback.accepted_keys, vec!["A"]);
+    }
+
+    #[test]
+    fn test_state_key_lag_map() {
+        let (feedback_tx, feedback_rx) = watch::channel(Feedback::new(vec![]));
+        let mut state = create_test_state(4, 100);
+        state.buffers.insert("C", Buffer::with_capacity(4));
+        state.feedback_tx = Some(feedback_tx);
+        assert_eq!(state.bottleneck_key(), None);
+
+        state.push("A", create_message(1500)).unwrap();
+        state.push("A", create_message(1600)).unwrap();
+        state.push("B", create_message(1540)).unwrap();
+        assert_eq!(
+            state.key_lag_map(),
+            IndexMap::from([
+                ("A", Some(Duration::ZERO)),
+                ("B", Some(Duration::from_millis(60))),
+                ("C", None),
+            ])
+        );
+        assert_eq!(state.bottleneck_key(), Some("B"));
+
+        state.update_feedback();
+        let feedback = feedback_rx.borrow();
+        assert_eq!(feedback.key_lag, state.key_lag_map());
+        assert_eq!(feedback.bottleneck_key, Some("B"));
+    }
+
+    #[test]
+    fn test_state_update_key_feedback() {
+        let mut state = create_test_state(2, 100);
+        let (tx_a, mut rx_a) = watch::channel(true);
+        let (tx_b, rx_b) = watch::channel(true);
+        state.key_feedback_tx = Some(IndexMap::from([("A", tx_a), ("B", tx_b)]));
+
+        // Only the stream with a full buffer is paused.
+        state.push("A", create_message(1500)).unwrap();
+        state.push("A", create_message(1600)).unwrap();
+        state.update_feedback();
+        assert!(rx_a.has_changed().unwrap());
+        assert!(!*rx_a.borrow_and_update());
+        assert!(!rx_b.has_changed().unwrap());
+        assert!(*rx_b.borrow());
+
+        state.drop_min();
+        state.update_feedback();
+        assert!(*rx_a.borrow_and_update());
+    }
+
+    #[test]
+    fn test_state_feedback_accepted_max_timestamp() {
+        let (feedback_tx, feedback_rx) = watch::channel(Feedback::new(vec![]));
+        let mut state = create_test_state(64, 100);
+        state.feedback_tx = Some(feedback_tx);
+
+        state.update_feedback();
+        assert_eq!(
+            feedback_rx.borrow().accepted_max_timestamp,
+            Some(Duration::from_millis(1200))
+        );
+
+        // Simulate a fast stream A and a slow stream B whose upstream
+        // sources only deliver messages below the accepted maximum.
+        let mut pending_a = VecDeque::new();
+        let mut pending_b = VecDeque::new();
+
+        for tick in 1..=300 {
+            let ts = 1000 + tick * 10;
+            pending_a.push_back(ts);
+            if tick % 3 == 0 {
+                pending_b.push_back(ts);
+            }
+
+            let max_ts = feedback_rx.borrow().accepted_max_timestamp.unwrap();
+            for (key, pending) in [("A", &mut pending_a), ("B", &mut pending_b)] {
+                while let Some(&ts) = pending.front() {
+                    if Duration::from_millis(ts) > max_ts {
+                        break;
+                    }
+                    pending.pop_front();
+                    let _ = state.push(key, create_message(ts));
+                }
+            }
+
+            while !state.is_empty() && state.try_match().is_some() {}
+            state.update_feedback();
+
+            assert!(state.buffers.values().all(|buffer| buffer.len() <= 21));
+        }
+
+        assert!(state.commit_ts > Some(Duration::from_millis(1000)));
+    }
+
+    #[test]
+    fn test_state_pause_key_not_required() {
+        let mut state = create_test_state(4, 100);
+        assert!(state.pause_key("B"));
+
+        state.push("A", create_message(1500)).unwrap();
+        state.push("A", create_message(1700)).unwrap();
+        assert!(state.is_ready());
+        assert!(!state.is_empty());
+
+        let group = state.try_match().unwrap();
+        assert_eq!(group.len(), 1);
+        assert_eq!(group["A"].timestamp(), Duration::from_millis(1500));
+    }
+
+    #[test]
+    fn test_state_paused_key_joins_group_within_window() {
+        let mut state = create_test_state(4, 100);
+        assert!(state.pause_key("B"));
+
+        state.push("A", create_message(1500)).unwrap();
+        state.push("A", create_message(1700)).unwrap();
+        state.push("B", create_message(1520)).unwrap();
+
+        let group = state.try_match().unwrap();
+        assert_eq!(group["A"].timestamp(), Duration::from_millis(1500));
+        assert_eq!(group["B"].timestamp(), Duration::from_millis(1520));
+    }
+
+    #[test]
+    fn test_state_pause_and_resume_key() {
+        let mut state = create_test_state(4, 100);
+
+        assert!(!state.pause_key("C"));
+        assert!(state.pause_key("A"));
+        assert!(!state.pause_key("B")); // The last active stream
+        assert!(state.resume_key(&"A"));
+        assert!(!state.resume_key(&"A"));
+
+        state.push("A", create_message(1500)).unwrap();
+        assert!(state.is_empty());
+    }
+
+    #[test]
+    fn test_state_is_consistent() {
+        let mut state = create_test_state(4, 100);
+        assert!(state.is_consistent());
+
+        state.push("A", create_message(1500)).unwrap();
+        state.push("A", create_message(1600)).unwrap();
+        state.push("B", create_message(1510)).unwrap();
+        state.push("B", create_message(1610)).unwrap();
+        assert!(state.is_consistent());
+
+        state.try_match();
+        assert!(state.is_consistent());
+    }
+
+    #[test]
+    fn test_state_is_consistent_commit_ts_too_far_ahead() {
+        let mut state = create_test_state(4, 100);
+        state.push("A", create_message(1500)).unwrap();
+
+        state.commit_ts = Some(Duration::from_millis(1700));
+        assert!(!state.is_consistent());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "State invariant violated")]
+    fn test_state_push_checks_invariants_on_entry() {
+        let mut state = create_test_state(4, 100);
+        state.push("A", create_message(1500)).unwrap();
+
+        state.commit_ts = Some(Duration::from_millis(1700));
+        let _ = state.push("B", create_message(1800));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_state_assert_invariants_panics() {
+        let mut state = create_test_state(4, 100);
+        state.push("A", create_message(1500)).unwrap();
+
+        state.commit_ts = Some(Duration::from_millis(1700));
+        state.assert_invariants();
+    }
+
+    #[test]
+    fn test_state_adaptive_window_size() {
+        let mut state = create_test_state(4, 100);
+        state.window_size_policy = WindowSizePolicy::Adaptive {
+            min: Duration::from_millis(10),
+            max: Duration::from_millis(500),
+            percentile: 1.0,
+        };
+
+        state.push("A", create_message(1100)).unwrap();
+        state.push("A", create_message(1300)).unwrap();
+        state.push("B", create_message(1140)).unwrap();
+        state.push("B", create_message(1340)).unwrap();
+
+        // The group spread is 40ms and scaled by the safety factor.
+        state.try_match().unwrap();
+        assert_eq!(state.spread_history.len(), 1);
+        let expected = Duration::from_millis(60);
+        assert!(state.window_size.abs_diff(expected) < Duration::from_micros(1));
+    }
+
+    #[test]
+    fn test_state_adaptive_window_size_clamped() {
+        let mut state = create_test_state(4, 100);
+        state.window_size_policy = WindowSizePolicy::Adaptive {
+            min: Duration::from_millis(20),
+            max: Duration::from_millis(500),
+            percentile: 0.5,
+        };
+
+        state.push("A", create_message(1100)).unwrap();
+        state.push("B", create_message(1100)).unwrap();
+
+        state.try_match().unwrap();
+        assert_eq!(state.window_size, Duration::from_millis(20));
+    }
+
+    #[test]
+    fn test_state_is_full_max_total_pending() {
+        let mut state = create_test_state(8, 100);
+        state.max_total_pending = Some(3);
+
+        state.push("A", create_message(1500)).unwrap();
+        state.push("A", create_message(1600)).unwrap();
+        assert_eq!(state.total_pending(), 2);
+        assert!(!state.is_full());
+
+        state.push("B", create_message(1500)).unwrap();
+        assert!(state.exceeds_total_pending());
+        assert!(state.is_full());
+    }
+
+    #[test]
+    fn test_state_remove_key() {
+        let mut state = create_test_state(4, 100);
+
+        state.push("A", create_message(1500)).unwrap();
+        state.push("B", create_message(1510)).unwrap();
+        assert!(state.pause_key("B"));
+
+        assert!(state.remove_key(&"B"));
+        assert_eq!(state.buffers.len(), 1);
+        assert!(state.paused_keys.is_empty());
+
+        let group = state.try_match().unwrap();
+        assert_eq!(group.len(), 1);
+        assert_eq!(group["A"].timestamp(), Duration::from_millis(1500));
+    }
+
+    #[test]
+    fn test_state_remove_key_below_minimum() {
+        let mut state = create_test_state(4, 100);
+        state.minimum_streams_required = 2;
+
+        assert!(!state.remove_key(&"A"));
+        assert_eq!(state.buffers.len(), 1);
+    }
+
+    #[test]
+    fn test_state_debug_human_readable() {
+        let mut state = create_test_state(4, 50);
+        state.commit_ts = Some(Duration::from_millis(98));
+        state.push("A", create_message(100)).unwrap();
+        state.push("A", create_message(450)).unwrap();
+
+        let debug = format!("{state:?}");
+        assert!(debug.contains("commit: 0.098s"), "{debug}");
+        assert!(debug.contains("window_size: 0.050s"), "{debug}");
+        assert!(
+            debug.contains("\"A\" => [2 messages, front: 0.100s, back: 0.450s]"),
+            "{debug}"
+        );
+        assert!(debug.contains("\"B\" => [0 messages]"), "{debug}");
+    }
+
+    #[test]
+    fn test_state_try_match_window_reaching_time_zero() {
+        let mut state = create_test_state(4, 100);
+        state.commit_ts = None;
+        for ts in [5, 30, 150] {
+            state.push("A", create_message(ts)).unwrap();
+        }
+        for ts in [10, 160] {
+            state.push("B", create_message(ts)).unwrap();
+        }
+
+        let group = state.try_match().unwrap();
+        assert_eq!(group["A"].timestamp(), Duration::from_millis(5));
+        assert_eq!(group["B"].timestamp(), Duration::from_millis(10));
+        assert_eq!(state.buffers["A"].len(), 2);
+    }
+
+    #[test]
+    fn test_state_try_match_drops_before_commit_timestamp() {
+        let mut state = create_test_state(4, 100);
+        for ts in [1640, 1700, 1820] {
+            state.push("A", create_message(ts)).unwrap();
+        }
+        for ts in [1710, 1830] {
+            state.push("B", create_message(ts)).unwrap();
+        }
+        // A@1640 is within the window around B@1710, but before the
+        // commit timestamp.
+        state.commit_ts = Some(Duration::from_millis(1650));
+
+        let group = state.try_match().unwrap();
+        assert_eq!(group["A"].timestamp(), Duration::from_millis(1700));
+        assert_eq!(group["B"].timestamp(), Duration::from_millis(1710));
+    }
+
+    #[test]
+    fn test_state_extend() {
+        let mut state = create_test_state(4, 100);
+        let messages = [
+            ("A", create_message(1500)),
+            ("B", create_message(1510)),
+            ("A", create_message(1400)), // out of order
+            ("C", create_message(1600)), // unknown key
+        ];
+
+        assert_eq!(state.extend_counting(messages.clone()), 2);
+
+        let mut state = create_test_state(4, 100);
+        state.extend(messages);
+        assert_eq!(state.total_pending(), 2);
+    }
+
+    #[test]
+    fn test_state_extend_stops_at_error() {
+        let mut state = create_test_state(4, 100);
+        state.extend([
+            Ok(("A", create_message(1500))),
+            Err("error"),
+            Ok(("B", create_message(1510))),
+        ]);
+
+        assert_eq!(state.total_pending(), 1);
+        assert!(state.buffers["B"].is_empty());
+    }
+
+    #[test]
+    fn test_state_try_match_all_one_outside_window() {
+        let mut state = create_test_state(4, 100);
+        state.push("A", create_message(1500)).unwrap();
+        state.push("B", create_message(1800)).unwrap();
+        assert!(state.all_one());
+
+        // A@1500 is before the window around B@1800 and is dropped,
+        // leaving buffer A empty.
+        assert!(state.try_match().is_none());
+        assert!(state.buffers["A"].is_empty());
+        assert!(state.try_match().is_none());
+
+        state.push("A", create_message(1790)).unwrap();
+        let group = state.try_match().unwrap();
+        assert_eq!(group["A"].timestamp(), Duration::from_millis(1790));
+        assert_eq!(group["B"].timestamp(), Duration::from_millis(1800));
+    }
+
+    #[test]
+    fn test_state_try_match_matching_strategy() {
+        let pick = |matching_strategy| {
+            let mut state = create_test_state(8, 100);
+            state.commit_ts = None;
+            state.matching_strategy = matching_strategy;
+            for ts in [1000, 1040, 1090, 1160] {
+                state.push("A", create_message(ts)).unwrap();
+            }
+            for ts in [1050, 1200] {
+                state.push("B", create_message(ts)).unwrap();
+            }
+
+            // The window is around B@1050, ending at 1150.
+            let group = state.try_match().unwrap();
+            assert_eq!(group["B"].timestamp(), Duration::from_millis(1050));
+            group["A"].timestamp()
+        };
+
+        assert_eq!(pick(MatchingStrategy::First), Duration::from_millis(1000));
+        assert_eq!(pick(MatchingStrategy::Last), Duration::from_millis(1090));
+        assert_eq!(pick(MatchingStrategy::Closest), Duration::from_millis(1040));
+        // The center is between 1050 and the earliest back at 1160.
+        assert_eq!(
+            pick(MatchingStrategy::ClosestToCenter),
+            Duration::from_millis(1090)
+        );
+    }
+
+    #[test]
+    fn test_state_optional_key_joins_group_within_window() {
+        let mut state = create_test_state(4, 100);
+        state.optional_keys.insert("B");
+        for ts in [1500, 1600, 1700] {
+            state.push("A", create_message(ts)).unwrap();
+        }
+        state.push("B", create_message(1505)).unwrap();
+
+        let group = state.try_match().unwrap();
+        assert_eq!(group.len(), 2);
+        assert!(state.absent_keys.is_empty());
+
+        // No message of B is left, which does not block A.
+        let group = state.try_match().unwrap();
+        assert_eq!(group["A"].timestamp(), Duration::from_millis(1600));
+        assert!(!group.contains_key("B"));
+        assert_eq!(state.absent_keys, ["B"]);
+    }
+
+    #[test]
+    fn test_state_try_match_all() {
+        let mut state = create_test_state(8, 100);
+        for ms in [1500, 2000, 2500, 3000] {
+            state.push("A", create_message(ms)).unwrap();
+            state.push("B", create_message(ms + 10)).unwrap();
+        }
+
+        let mut expect = state.clone();
+        let expect: Vec<_> = iter::from_fn(|| expect.try_match()).collect();
+        let groups = state.try_match_all();
+        assert!(groups.len() >= 2);
+        assert_eq!(groups, expect);
+        assert!(state.try_match().is_none());
+    }
+
+    #[test]
+    fn test_state_with_hasher() {
+        use std::hash::BuildHasherDefault;
+
+        let mut state: State<usize, TestMessage, BuildHasherDefault<FxHasher>> =
+            State::with_hasher([0, 1], 4, Duration::from_millis(100), Default::default());
+        state.push(0, create_message(1000)).unwrap();
+        state.push(1, create_message(1010)).unwrap();
+
+        let group = state.try_match().unwrap();
+        assert_eq!(group[&0].timestamp(), Duration::from_millis(1000));
+        assert_eq!(group[&1].timestamp(), Duration::from_millis(1010));
+        assert!(state.is_empty());
+    }
+
+    #[test]
+    fn test_state_try_match_with_priority() {
+        struct Latest;
+
+        impl MessagePriority<&str, TestMessage> for Latest {
+            fn priority(_key: &&str, msg: &TestMessage, _anchor: Duration) -> i64 {
+                msg.timestamp().as_millis() as i64
+            }
+        }
+
+        let mut state = create_test_state(8, 100);
+        for ts in [1100, 1185, 1205, 1400] {
+            state.push("A", create_message(ts)).unwrap();
+        }
+        for ts in [1195, 1400] {
+            state.push("B", create_message(ts)).unwrap();
+        }
+
+        // The priority does not override the matching strategy.
+        let mut first = state.clone();
+        first.set_priority::<Latest>();
+        let group = first.try_match().unwrap();
+        assert_eq!(group["A"].timestamp(), Duration::from_millis(1100));
+
+        // 1185 and 1205 are equally close to the anchor. The default
+        // priority ranks them equally, so the earlier one wins.
+        state.matching_strategy = MatchingStrategy::Closest;
+        let group = state.clone().try_match().unwrap();
+        assert_eq!(group["A"].timestamp(), Duration::from_millis(1185));
+
+        state.set_priority::<Latest>();
+        let group = state.try_match().unwrap();
+        assert_eq!(group["A"].timestamp(), Duration::from_millis(1205));
+        assert_eq!(group["B"].timestamp(), Duration::from_millis(1195));
+        assert_eq!(state.buffers["A"].len(), 1);
+        assert_eq!(state.commit_ts, Some(Duration::from_millis(1195)));
+    }
+
+    #[test]
+    fn test_state_is_stale() {
+        let mut state = create_test_state(4, 100);
+        let threshold = Duration::from_secs(60);
+
+        assert!(state.is_stale(&"A", threshold));
+        assert!(state.last_received_age(&"A").is_none());
+
+        state.push("A", create_message(1500)).unwrap();
+        assert!(!state.is_stale(&"A", threshold));
+        assert!(state.is_stale(&"B", threshold));
+
+        // Late messages are counted as received.
+        state.commit_ts = Some(Duration::from_millis(1550));
+        assert!(state.push("B", create_message(1500)).is_err());
+        assert!(!state.is_stale(&"B", threshold));
+    }
+
+    #[test]
+    fn test_state_timestamp_offsets() {
+        let mut state = create_test_state(4, 100);
+        state.timestamp_offsets = Some(IndexMap::from([("B", -300_000_000)]));
+
+        state.push("A", create_message(1500)).unwrap();
+        state.push("A", create_message(1700)).unwrap();
+        state.push("B", create_message(1810)).unwrap();
+        state.push("B", create_message(2010)).unwrap();
+        assert_eq!(
+            state.buffers["B"].front_ts(),
+            Some(Duration::from_millis(1510))
+        );
+
+        // Matching uses adjusted timestamps while the group keeps the
+        // original ones.
+        let group = state.try_match().unwrap();
+        assert_eq!(group["A"].timestamp(), Duration::from_millis(1500));
+        assert_eq!(group["B"].timestamp(), Duration::from_millis(1810));
+        assert_eq!(state.commit_ts, Some(Duration::from_millis(1500)));
+
+        // A message whose adjusted timestamp is before the commit
+        // timestamp is rejected.
+        assert!(state.push("B", create_message(1790)).is_err());
+    }
+
+    #[test]
+    fn test_state_is_duplicate_group() {
+        let mut state = create_test_state(4, 100);
+        let group =
+            |ts: u64| IndexMap::from([("A", create_message(ts)), ("B", create_message(ts + 10))]);
+
+        // Deduplication is disabled by default.
+        assert!(!state.is_duplicate_group(&group(1500)));
+        assert!(!state.is_duplicate_group(&group(1500)));
+
+        state.fingerprint_history = Some(VecDeque::new());
+        state.dedup_history = 2;
+        assert!(!state.is_duplicate_group(&group(1500)));
+        assert!(state.is_duplicate_group(&group(1500)));
+
+        // The oldest fingerprint is forgotten once the history is
+        // full.
+        assert!(!state.is_duplicate_group(&group(1600)));
+        assert!(!state.is_duplicate_group(&group(1700)));
+        assert!(!state.is_duplicate_group(&group(1500)));
+    }
+
+    #[test]
+    fn test_state_skips_redelivered_group() {
+        let mut state = create_test_state(4, 100);
+        state.fingerprint_history = Some(VecDeque::new());
+        state.dedup_history = 4;
+        for ts in [1500, 1700, 1900] {
+            state.push("A", create_message(ts)).unwrap();
+            state.push("B", create_message(ts + 10)).unwrap();
+        }
+
+        let group = state.try_match().unwrap();
+        assert!(!state.is_duplicate_group(&group));
+        let next_group = state.try_match().unwrap();
+        assert!(!state.is_duplicate_group(&next_group));
+
+        // A redelivered group is skipped although the commit timestamp
+        // has moved on since it was emitted.
+        assert_ne!(state.commit_ts, Some(group["A"].timestamp()));
+        assert!(state.is_duplicate_group(&group));
+    }
+
+    #[test]
+    fn test_timed_state_matches_realtime_groups() {
+        let data = [
+            ("A", 1000),
+            ("B", 1020),
+            ("A", 1100),
+            ("B", 1130),
+            ("A", 1200),
+            ("B", 1260),
+        ];
+
+        // The same data is grouped at 1x with a 50 ms window and at
+        // 10x with a 5 ms window.
+        let mut realtime = create_test_state(4, 50);
+        realtime.commit_ts = None;
+        let mut timed_state = create_test_state(4, 5);
+        timed_state.commit_ts = None;
+        let mut timed = TimedState::new(timed_state, 10.0).unwrap();
+        for (key, ts) in data {
+            realtime.push(key, create_message(ts)).unwrap();
+            timed.push(key, create_message(ts)).unwrap();
+        }
+
+        let expected = realtime.drain_to_vec();
+        assert!(!expected.is_empty());
+        assert_eq!(timed.drain_to_vec(), expected);
+    }
+
+    #[test]
+    fn test_timed_state_rejects_invalid_scale() {
+        assert!(TimedState::new(create_test_state(4, 50), 0.0).is_err());
+        assert!(TimedState::new(create_test_state(4, 50), f64::NAN).is_err());
+    }
+
+    #[test]
+    fn test_timed_state_scale_saturates() {
+        let timed = TimedState::new(create_test_state(4, 50), f64::MIN_POSITIVE).unwrap();
+        assert_eq!(timed.scale(Duration::from_secs(1)), Duration::MAX);
+        assert_eq!(timed.scale(Duration::ZERO), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_state_clone_restores_speculative_matching() {
+        let (feedback_tx, _feedback_rx) = watch::channel(Feedback::new(vec![]));
+        let mut state = create_test_state(4, 100);
+        state.feedback_tx = Some(feedback_tx);
+        state.push("A", create_message(1500)).unwrap();
+        state.push("B", create_message(1510)).unwrap();
+
+        let mut fork = state.clone();
+        assert!(fork.feedback_tx.is_none());
+        assert!(fork.try_match().is_some());
+        assert!(fork.buffers.values().all(|buffer| buffer.is_empty()));
+
+        // The original state is untouched.
+        assert_eq!(state.total_pending(), 2);
+        assert_eq!(state.commit_ts, Some(Duration::from_millis(1000)));
+        assert!(state.feedback_tx.is_some());
+    }
+
+    #[test]
+    fn test_state_clone_mid_sync_is_independent() {
+        let mut state = create_test_state(4, 100);
+        for ts in [1100, 1200, 1300] {
+            state.push("A", create_message(ts)).unwrap();
+            state.push("B", create_message(ts + 10)).unwrap();
+        }
+        let group = state.try_match().unwrap();
+        assert_eq!(group["A"].timestamp(), Duration::from_millis(1100));
+
+        let mut snapshot = state.clone();
+        assert!(snapshot.feedback_tx.is_none());
+        snapshot.push("A", create_message(1400)).unwrap();
+        snapshot.push("B", create_message(1410)).unwrap();
+        assert_eq!(snapshot.drain_to_vec().len(), 3);
+        assert_eq!(snapshot.total_pending(), 0);
+
+        // The original state keeps its buffers and commit timestamp.
+        assert_eq!(state.total_pending(), 4);
+        assert_eq!(state.commit_ts, Some(Duration::from_millis(1100)));
+        let groups = state.flush();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0]["A"].timestamp(), Duration::from_millis(1200));
+    }
+
+    #[test]
+    fn test_state_flush_emits_remaining_groups() {
+        let mut state = create_test_state(4, 100);
+
+        state.push("A", create_message(1500)).unwrap();
+        state.push("A", create_message(2000)).unwrap();
+        state.push("A", create_message(2500)).unwrap();
+        state.push("B", create_message(1510)).unwrap();
+        state.push("B", create_message(2010)).unwrap();
+
+        let groups = state.flush();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[1]["A"].timestamp(), Duration::from_millis(2000));
+        assert_eq!(groups[1]["B"].timestamp(), Duration::from_millis(2010));
+        assert_eq!(state.stats().total_groups_emitted, 2);
+
+        // The unmatched message is kept and the commit timestamp is
+        // reset.
+        assert_eq!(state.buffers["A"].len(), 1);
+        assert_eq!(state.commit_ts, None);
+        assert!(state.flush().is_empty());
+    }
+
+    #[test]
+    fn test_state_fast_forward_drops_old_messages() {
+        let mut state = create_test_state(4, 100);
+
+        state.push("A", create_message(1500)).unwrap();
+        state.push("A", create_message(2000)).unwrap();
+        state.push("B", create_message(1510)).unwrap();
+        state.push("B", create_message(2010)).unwrap();
+
+        state.fast_forward(Duration::from_millis(1800));
+        assert_eq!(state.commit_ts, Some(Duration::from_millis(1800)));
+        assert_eq!(state.buffers["A"].len(), 1);
+        assert_eq!(state.buffers["B"].len(), 1);
+
+        let group = state.try_match().unwrap();
+        assert!(group
+            .values()
+            .all(|msg| msg.timestamp() >= Duration::from_millis(1800)));
+    }
+
+    #[test]
+    fn test_state_stats_count_matched_groups() {
+        let mut state = create_test_state(8, 100);
+        for ts in [1100, 1300, 1500, 1700] {
+            state.push("A", create_message(ts)).unwrap();
+            state.push("B", create_message(ts + 10)).unwrap();
+        }
+
+        assert!(state.try_match().is_some());
+        assert_eq!(state.stats().total_groups_emitted, 1);
+        let groups = state.drain_to_vec();
+        assert!(!groups.is_empty());
+        assert_eq!(state.stats().total_groups_emitted, 1 + groups.len() as u64);
+    }
+
+    #[test]
+    fn test_state_stats_count_drops() {
+        let mut state = create_test_state(4, 100);
+
+        // Late and unknown messages are counted as received.
+        assert!(state.push("A", create_message(500)).is_err());
+        assert!(state.push("C", create_message(1500)).is_err());
+        state.push("A", create_message(1500)).unwrap();
+        state.push("A", create_message(2000)).unwrap();
+        state.push("B", create_message(1100)).unwrap();
+        state.push("B", create_message(2010)).unwrap();
+
+        // Drop B at 1100 and then A at 1500.
+        assert!(state.drop_min());
+        state.fast_forward(Duration::from_millis(1800));
+
+        let stats = state.stats();
+        assert_eq!(stats.total_messages_received, 6);
+        assert_eq!(stats.total_groups_emitted, 0);
+        assert_eq!(stats.drop_counts["A"], 2);
+        assert_eq!(stats.drop_counts["B"], 1);
+        assert!(!stats.drop_counts.contains_key("C"));
+        assert_eq!(stats.total_drops(), 3);
+    }
+
+    #[test]
+    fn test_state_fast_forward_rejects_earlier_messages() {
+        let mut state = create_test_state(4, 100);
+
+        state.fast_forward(Duration::from_millis(3000));
+        assert!(state.push("A", create_message(2500)).is_err());
+        assert!(state.push("A", create_message(3500)).is_ok());
+    }
+
+    #[test]
+    fn test_state_fast_forward_does_not_rewind() {
+        let mut state = create_test_state(4, 100);
+
+        state.fast_forward(Duration::from_millis(500));
+        assert_eq!(state.commit_ts, Some(Duration::from_millis(1000)));
+    }
+
+    #[test]
+    fn test_compute_anchor_weighted_average() {
+        let mut state = create_test_state(4, 100);
+
+        state.push("A", create_message(1100)).unwrap();
+        state.push("B", create_message(1500)).unwrap();
+
+        let weights: IndexMap<_, _> = [("A", 3.0), ("B", 1.0)].into_iter().collect();
+        let anchor = compute_anchor(&state.buffers, &weights).unwrap();
+        assert!((anchor.as_secs_f64() - 1.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_compute_anchor_empty_buffers() {
+        let state = create_test_state(4, 100);
+        let weights: IndexMap<_, _> = [("A", 1.0)].into_iter().collect();
+        assert!(compute_anchor(&state.buffers, &weights).is_none());
+    }
+
+    #[test]
+    fn test_state_try_match_with_anchor_weights() {
+        let mut state = create_test_state(4, 100);
+        state.anchor_weights = Some([("A", 9.0), ("B", 1.0)].into_iter().collect());
+
+        state.push("A", create_message(1100)).unwrap();
+        state.push("A", create_message(1400)).unwrap();
+        state.push("B", create_message(1250)).unwrap();
+        state.push("B", create_message(1500)).unwrap();
+
+        // The anchor leans to stream A so that its front message is kept.
+        let group = state.try_match().unwrap();
+        assert_eq!(group["A"].timestamp(), Duration::from_millis(1100));
+        assert_eq!(group["B"].timestamp(), Duration::from_millis(1250));
+    }
+
+    fn create_session_state(gap_ms: u64) -> SessionState<&'static str, TestMessage> {
+        SessionState {
+            session: [("A", None), ("B", None)].into_iter().collect(),
+            closed: VecDeque::new(),
+            gap: Duration::from_millis(gap_ms),
+            last_ts: None,
+            commit_ts: None,
+            feedback_tx: None,
+            on_feedback_dropped: None,
+        }
+    }
+
+    #[test]
+    fn test_session_state_closes_on_gap() {
+        let mut state = create_session_state(100);
+
+        state.push("A", create_message(1000)).unwrap();
+        state.push("B", create_message(1050)).unwrap();
+        state.push("A", create_message(1080)).unwrap();
+        assert!(state.try_match().is_none());
+
+        // The gap exceeds 100ms and closes the previous session.
+        state.push("B", create_message(1300)).unwrap();
+        let group = state.try_match().unwrap();
+        assert_eq!(group["A"].timestamp(), Duration::from_millis(1080));
+        assert_eq!(group["B"].timestamp(), Duration::from_millis(1050));
+        assert_eq!(state.commit_ts, Some(Duration::from_millis(1080)));
+    }
+
+    #[test]
+    fn test_session_state_close_partial_session() {
+        let mut state = create_session_state(100);
+
+        state.push("A", create_message(1000)).unwrap();
+        assert!(state.close());
+
+        let group = state.try_match().unwrap();
+        assert_eq!(group.len(), 1);
+        assert!(group.contains_key("A"));
+        assert!(!state.close());
+    }
+
+    #[test]
+    fn test_session_state_rejects_late_and_unknown_messages() {
+        let mut state = create_session_state(100);
+
+        state.push("A", create_message(1000)).unwrap();
+        state.push("A", create_message(1300)).unwrap();
+        assert!(state.push("B", create_message(900)).is_err());
+        assert!(state.push("C", create_message(1310)).is_err());
+    }
+
+    #[test]
+    fn test_session_state_feedback_dropped_callback() {
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+
+        let (feedback_tx, feedback_rx) = watch::channel(Feedback::new(vec![]));
+        let count = Arc::new(AtomicUsize::new(0));
+        let mut state = create_session_state(100);
+        state.feedback_tx = Some(feedback_tx);
+        state.on_feedback_dropped = Some(FeedbackDroppedCallback::new({
+            let count = count.clone();
+            move || {
+                count.fetch_add(1, Ordering::SeqCst);
+            }
+        }));
+
+        state.update_feedback();
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+
+        drop(feedback_rx);
+        state.update_feedback();
+        state.update_feedback();
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        assert!(state.feedback_tx.is_none());
+    }
+
     #[derive(Debug, Clone, PartialEq, Eq)]
     struct TestMessageWithTimeout {
         timestamp: Duration,
@@ -548,10 +3062,37 @@ mod tests {
         State {
             buffers,
             commit_ts: Some(Duration::from_millis(1000)),
+            start_time: None,
             buf_size,
             window_size: Duration::from_millis(window_size_ms),
             feedback_tx: None,
             staleness_detector: None,
+            anchor_weights: None,
+            paused_keys: IndexSet::new(),
+            window_size_policy: WindowSizePolicy::Fixed,
+            spread_history: VecDeque::new(),
+            max_total_pending: None,
+            minimum_streams_required: 1,
+            timestamp_offsets: None,
+            on_feedback_dropped: None,
+            fingerprint_history: None,
+            dedup_history: 0,
+            feedback_dirty: true,
+            last_received_wall: IndexMap::new(),
+            priority: None,
+            key_feedback_tx: None,
+            dead_letter_tx: None,
+            jitter_tolerance: Duration::ZERO,
+            optional_keys: IndexSet::new(),
+            absent_keys: vec![],
+            per_key_buf_size: IndexMap::new(),
+            matching_strategy: MatchingStrategy::First,
+            last_window: None,
+            flush_timeout: None,
+            allow_equal_timestamps: false,
+            drop_policy: DropPolicy::DropOldest,
+            out_of_order_tolerance: Duration::ZERO,
+            stats: SyncStats::default(),
         }
     }
 
@@ -1,23 +1,34 @@
 use crate::{
     buffer::Buffer,
+    handle::{ControlMessage, SyncHandle},
     staleness::StalenessDetector,
-    state::State,
-    types::{FeedbackReceiver, Key, OutputStream, WithTimestamp},
-    Config, Feedback,
+    state::{SessionState, State},
+    types::{
+        AnyMessage, DroppedMessage, FeedbackReceiver, GroupAny, GroupEnvelope, Key, OutputStream,
+        SyncStats, WithTimestamp,
+    },
+    Config, DropPolicy, Feedback, KeyOptions, SyncError, ValidatedConfig, WindowMode,
 };
-use eyre::{ensure, Result};
+use eyre::Result;
 use futures::{
-    self,
-    stream::{self, Stream},
-    StreamExt,
+    self, ready,
+    stream::{self, BoxStream, Stream},
+    StreamExt, TryStreamExt,
 };
-use indexmap::IndexMap;
+use indexmap::{IndexMap, IndexSet};
 use std::{
+    collections::VecDeque,
+    future::Future,
+    marker::PhantomData,
     pin::Pin,
+    sync::{Arc, Mutex},
     task::{Context, Poll, Poll::*},
-    time::Duration,
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::{mpsc, watch},
+    time::{self, Sleep},
 };
-use tokio::sync::watch;
 use tracing::{debug, warn};
 
 /// Consume a stream of messages, each identified by a key, and group
@@ -26,83 +37,876 @@ use tracing::{debug, warn};
 /// The function returns an output stream and a feedback stream. The
 /// output stream emits batches of grouped messages. The feedback
 /// stream emits feedback messages to control the input stream.
-pub fn sync<'a, K, T, S, I>(
+///
+/// A [SyncError] is returned if the config is invalid or does not
+/// fit the given keys.
+pub fn sync<'a, K, T, S, I, C>(
+    stream: S,
+    keys: I,
+    config: C,
+) -> Result<(OutputStream<'a, K, T>, FeedbackReceiver<K>), SyncError>
+where
+    K: Key + 'a,
+    T: WithTimestamp + Clone + 'a,
+    S: Stream<Item = Result<(K, T)>> + Unpin + Send + 'a,
+    I: IntoIterator<Item = K>,
+    C: TryInto<ValidatedConfig>,
+    C::Error: Into<SyncError>,
+{
+    let (output_stream, feedback_rx, _handle) = sync_with_handle(stream, keys, config)?;
+    Ok((output_stream, feedback_rx))
+}
+
+/// The same as [sync](crate::sync()), but the input stream signals
+/// the end of the stream identified by a key with a `None` message.
+///
+/// The ended stream is deregistered so that remaining streams can
+/// continue producing groups without it. If fewer than
+/// `minimum_streams_required` streams remain, the input is treated as
+/// depleted.
+pub fn sync_with_eof<'a, K, T, S, I, C>(
+    stream: S,
+    keys: I,
+    config: C,
+) -> Result<(OutputStream<'a, K, T>, FeedbackReceiver<K>), SyncError>
+where
+    K: Key + 'a,
+    T: WithTimestamp + Clone + 'a,
+    S: Stream<Item = Result<(K, Option<T>)>> + Unpin + Send + 'a,
+    I: IntoIterator<Item = K>,
+    C: TryInto<ValidatedConfig>,
+    C::Error: Into<SyncError>,
+{
+    let (output_stream, feedback_rx, _handle) =
+        sync_impl(stream, keys, config, KeyOptions::default(), None)?;
+    Ok((output_stream, feedback_rx))
+}
+
+/// The same as [sync](crate::sync()), but also returns a
+/// [SyncHandle] to control the running synchronizer.
+#[allow(clippy::type_complexity)]
+pub fn sync_with_handle<'a, K, T, S, I, C>(
     stream: S,
     keys: I,
+    config: C,
+) -> Result<(OutputStream<'a, K, T>, FeedbackReceiver<K>, SyncHandle<K>), SyncError>
+where
+    K: Key + 'a,
+    T: WithTimestamp + Clone + 'a,
+    S: Stream<Item = Result<(K, T)>> + Unpin + Send + 'a,
+    I: IntoIterator<Item = K>,
+    C: TryInto<ValidatedConfig>,
+    C::Error: Into<SyncError>,
+{
+    let stream = stream.map_ok(|(key, item)| (key, Some(item)));
+    sync_impl(stream, keys, config, KeyOptions::default(), None)
+}
+
+/// The same as [sync](crate::sync()), but also takes the
+/// [KeyOptions] assigned to individual streams.
+///
+/// The options are validated along with the config. A
+/// [SyncError::UnknownRequiredKey] is returned if a required key is
+/// not among `keys`.
+pub fn sync_with_options<'a, K, T, S, I, C>(
+    stream: S,
+    keys: I,
+    config: C,
+    options: KeyOptions<K>,
+) -> Result<(OutputStream<'a, K, T>, FeedbackReceiver<K>), SyncError>
+where
+    K: Key + 'a,
+    T: WithTimestamp + Clone + 'a,
+    S: Stream<Item = Result<(K, T)>> + Unpin + Send + 'a,
+    I: IntoIterator<Item = K>,
+    C: TryInto<ValidatedConfig>,
+    C::Error: Into<SyncError>,
+{
+    let stream = stream.map_ok(|(key, item)| (key, Some(item)));
+    let (output_stream, feedback_rx, _handle) = sync_impl(stream, keys, config, options, None)?;
+    Ok((output_stream, feedback_rx))
+}
+
+/// The same as [sync](crate::sync()), but also returns a receiver of
+/// messages dropped by the synchronizer.
+///
+/// Each [DroppedMessage] carries the key, the message, the
+/// [DropReason](crate::DropReason) and the commit timestamp at the
+/// time of dropping. Messages of removed streams are not reported.
+/// Dead letters are only reported in the sliding window mode.
+///
+/// The channel is unbounded, so the receiver should be consumed or
+/// dropped. Once the receiver is dropped, messages are no longer
+/// cloned for reporting.
+#[allow(clippy::type_complexity)]
+pub fn sync_with_dead_letters<'a, K, T, S, I, C>(
+    stream: S,
+    keys: I,
+    config: C,
+) -> Result<
+    (
+        OutputStream<'a, K, T>,
+        FeedbackReceiver<K>,
+        mpsc::UnboundedReceiver<DroppedMessage<K, T>>,
+    ),
+    SyncError,
+>
+where
+    K: Key + 'a,
+    T: WithTimestamp + Clone + 'a,
+    S: Stream<Item = Result<(K, T)>> + Unpin + Send + 'a,
+    I: IntoIterator<Item = K>,
+    C: TryInto<ValidatedConfig>,
+    C::Error: Into<SyncError>,
+{
+    let (dead_letter_tx, dead_letter_rx) = mpsc::unbounded_channel();
+    let stream = stream.map_ok(|(key, item)| (key, Some(item)));
+    let (output_stream, feedback_rx, _handle) = sync_impl(
+        stream,
+        keys,
+        config,
+        KeyOptions::default(),
+        Some(dead_letter_tx),
+    )?;
+    Ok((output_stream, feedback_rx, dead_letter_rx))
+}
+
+/// A builder that collects keys one at a time before starting the
+/// synchronizer, which is handy when keys are computed in a loop.
+///
+/// ```rust
+/// # use multi_stream_synchronizer::{Config, SyncBuilder, WithTimestamp};
+/// # use futures::stream;
+/// # use std::time::Duration;
+/// # #[derive(Clone)]
+/// # struct Message(Duration);
+/// # impl WithTimestamp for Message {
+/// #     fn timestamp(&self) -> Duration { self.0 }
+/// # }
+/// # fn main() -> Result<(), multi_stream_synchronizer::SyncError> {
+/// let mut builder = SyncBuilder::new(Config::basic(Duration::from_millis(50), None, 16));
+/// for index in 0..3 {
+///     builder = builder.add_key(format!("camera_{index}"));
+/// }
+/// let input = stream::empty::<eyre::Result<(String, Message)>>();
+/// let (_output, _feedback_rx) = builder.build(input)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct SyncBuilder<K, T> {
     config: Config,
-) -> Result<(OutputStream<'a, K, T>, FeedbackReceiver<K>)>
+    options: KeyOptions<K>,
+    keys: Vec<K>,
+    _phantom: PhantomData<fn() -> T>,
+}
+
+impl<K, T> SyncBuilder<K, T>
+where
+    K: Key,
+    T: WithTimestamp + Clone,
+{
+    /// Create a builder without keys.
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            options: KeyOptions::default(),
+            keys: vec![],
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Add a key identifying an input stream.
+    pub fn add_key(mut self, key: K) -> Self {
+        self.keys.push(key);
+        self
+    }
+
+    /// Set the options assigned to individual streams. See
+    /// [sync_with_options](crate::sync_with_options()).
+    pub fn key_options(mut self, options: KeyOptions<K>) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Start the synchronizer on the input stream. See
+    /// [sync](crate::sync()).
+    ///
+    /// [SyncError::NoKeysProvided] is returned if no key is added.
+    pub fn build<'a, S>(
+        self,
+        stream: S,
+    ) -> Result<(OutputStream<'a, K, T>, FeedbackReceiver<K>), SyncError>
+    where
+        K: 'a,
+        T: 'a,
+        S: Stream<Item = Result<(K, T)>> + Unpin + Send + 'a,
+    {
+        if self.keys.is_empty() {
+            return Err(SyncError::NoKeysProvided);
+        }
+        sync_with_options(stream, self.keys, self.config, self.options)
+    }
+}
+
+/// The same as [sync](crate::sync()), but creates a channel for each
+/// key instead of consuming a single input stream.
+///
+/// The returned senders can be moved to concurrent producers, e.g.
+/// one task per sensor. The receivers are merged into the input of
+/// the synchronizer, which ends once all senders are dropped. Each
+/// channel buffers up to `channel_size` messages.
+#[allow(clippy::type_complexity)]
+pub fn sync_concurrent<'a, K, T, I, C>(
+    keys: I,
+    channel_size: usize,
+    config: C,
+) -> Result<
+    (
+        IndexMap<K, mpsc::Sender<T>>,
+        OutputStream<'a, K, T>,
+        FeedbackReceiver<K>,
+    ),
+    SyncError,
+>
+where
+    K: Key + 'a,
+    T: WithTimestamp + Clone + 'a,
+    I: IntoIterator<Item = K>,
+    C: TryInto<ValidatedConfig>,
+    C::Error: Into<SyncError>,
+{
+    if channel_size == 0 {
+        return Err(SyncError::ChannelSizeZero);
+    }
+
+    let keys: IndexSet<K> = keys.into_iter().collect();
+    let (senders, receivers): (IndexMap<_, _>, Vec<_>) = keys
+        .iter()
+        .map(|key| {
+            let (tx, mut rx) = mpsc::channel(channel_size);
+            let rx_key = key.clone();
+            let rx = stream::poll_fn(move |ctx| rx.poll_recv(ctx))
+                .map(move |item| Ok((rx_key.clone(), item)));
+            ((key.clone(), tx), rx)
+        })
+        .unzip();
+
+    let (output_stream, feedback_rx) = sync(stream::select_all(receivers), keys, config)?;
+    Ok((senders, output_stream, feedback_rx))
+}
+
+/// The same as [sync](crate::sync()), but consumes a separate
+/// stream for each key instead of a single stream of keyed messages.
+///
+/// The streams are merged and each message is tagged with the key of
+/// its stream. [SyncError::DuplicateKey] is returned if a key is
+/// passed more than once.
+pub fn sync_from_streams<'a, K, T, S, I, C>(
+    streams: I,
+    config: C,
+) -> Result<(OutputStream<'a, K, T>, FeedbackReceiver<K>), SyncError>
+where
+    K: Key + 'a,
+    T: WithTimestamp + Clone + 'a,
+    S: Stream<Item = Result<T>> + Unpin + Send + 'a,
+    I: IntoIterator<Item = (K, S)>,
+    C: TryInto<ValidatedConfig>,
+    C::Error: Into<SyncError>,
+{
+    let mut keys = IndexSet::new();
+    let mut tagged_streams = vec![];
+    for (key, stream) in streams {
+        if !keys.insert(key.clone()) {
+            return Err(SyncError::DuplicateKey);
+        }
+        let tagged = stream.map(move |item| item.map(|item| (key.clone(), item)));
+        tagged_streams.push(tagged);
+    }
+
+    sync(stream::select_all(tagged_streams), keys, config)
+}
+
+/// The same as [sync](crate::sync()), but accepts streams of
+/// different message types wrapped in [AnyMessage]. Each emitted
+/// [GroupAny] gives the messages back by
+/// [get_as](GroupAny::get_as).
+///
+/// The generic version is preferred if all streams share a message
+/// type, or if the types can be put in an enum. It checks the types at
+/// compile time, while here a wrong type is only found at runtime when
+/// `get_as` returns `None`. Each message is also allocated on the heap
+/// here.
+#[allow(clippy::type_complexity)]
+pub fn sync_any<'a, K, S, I, C>(
+    stream: S,
+    keys: I,
+    config: C,
+) -> Result<(BoxStream<'a, Result<GroupAny<K>>>, FeedbackReceiver<K>), SyncError>
+where
+    K: Key + 'a,
+    S: Stream<Item = Result<(K, AnyMessage)>> + Unpin + Send + 'a,
+    I: IntoIterator<Item = K>,
+    C: TryInto<ValidatedConfig>,
+    C::Error: Into<SyncError>,
+{
+    let (output_stream, feedback_rx) = sync(stream, keys, config)?;
+    let output_stream = output_stream
+        .map_ok(|messages| GroupAny { messages })
+        .boxed();
+    Ok((output_stream, feedback_rx))
+}
+
+/// The same as [sync](crate::sync()), but wraps each emitted group
+/// in a [GroupEnvelope] telling its index and time window.
+///
+/// Session window mode is not supported.
+#[allow(clippy::type_complexity)]
+pub fn sync_with_envelope<'a, K, T, S, I, C>(
+    stream: S,
+    keys: I,
+    config: C,
+) -> Result<
+    (
+        BoxStream<'a, Result<GroupEnvelope<K, T>>>,
+        FeedbackReceiver<K>,
+    ),
+    SyncError,
+>
 where
     K: Key + 'a,
     T: WithTimestamp + Clone + 'a,
     S: Stream<Item = Result<(K, T)>> + Unpin + Send + 'a,
     I: IntoIterator<Item = K>,
+    C: TryInto<ValidatedConfig>,
+    C::Error: Into<SyncError>,
+{
+    let (state, feedback_rx) = init_state(keys, config, KeyOptions::default())?;
+    let SyncState::Sliding(mut state) = state else {
+        return Err(SyncError::SessionModeUnsupported);
+    };
+
+    let mut stream = Some(stream.map(|item| item.map(|(key, item)| (key, Some(item)))));
+    let mut flush_timer = state.flush_timeout.map(FlushTimer::new);
+    let mut group_index = 0;
+    let output_stream = stream::poll_fn(move |ctx| {
+        let (item, flushed) = match poll(Pin::new(&mut stream), &mut state, ctx) {
+            Ready(item) => (item, false),
+            Pending => match &mut flush_timer {
+                Some(timer) => (Some(Ok(ready!(timer.poll_flush(&mut state, ctx)))), true),
+                None => return Pending,
+            },
+        };
+        let item = item.map(|result| {
+            result.map(|messages| {
+                // Every group is formed by try_match(), which records
+                // the window and the commit timestamp.
+                let (window_start, window_end) = state.last_window.unwrap();
+                let envelope = GroupEnvelope {
+                    group_index,
+                    window_start,
+                    window_end,
+                    commit_ts: state.commit_ts.unwrap(),
+                    flushed,
+                    messages,
+                };
+                group_index += 1;
+                envelope
+            })
+        });
+        Ready(item)
+    })
+    .boxed();
+
+    Ok((output_stream, feedback_rx))
+}
+
+#[allow(clippy::type_complexity)]
+fn sync_impl<'a, K, T, S, I, C>(
+    stream: S,
+    keys: I,
+    config: C,
+    options: KeyOptions<K>,
+    dead_letter_tx: Option<mpsc::UnboundedSender<DroppedMessage<K, T>>>,
+) -> Result<(OutputStream<'a, K, T>, FeedbackReceiver<K>, SyncHandle<K>), SyncError>
+where
+    K: Key + 'a,
+    T: WithTimestamp + Clone + 'a,
+    S: Stream<Item = Result<(K, Option<T>)>> + Unpin + Send + 'a,
+    I: IntoIterator<Item = K>,
+    C: TryInto<ValidatedConfig>,
+    C::Error: Into<SyncError>,
+{
+    let (state, feedback_rx) = init_state(keys, config, options)?;
+    let (control_tx, mut control_rx) = mpsc::unbounded_channel();
+    let stats = Arc::new(Mutex::new(SyncStats::default()));
+
+    // Construct output stream.
+    let output_stream = match state {
+        SyncState::Sliding(mut state) => {
+            state.dead_letter_tx = dead_letter_tx;
+            let mut stream = Some(stream);
+            let mut flush_timer = state.flush_timeout.map(FlushTimer::new);
+            let stats = stats.clone();
+            stream::poll_fn(move |ctx| {
+                poll_control(&mut control_rx, &mut state, ctx);
+                let output = match poll(Pin::new(&mut stream), &mut state, ctx) {
+                    Pending => match &mut flush_timer {
+                        Some(timer) => timer
+                            .poll_flush(&mut state, ctx)
+                            .map(|group| Some(Ok(group))),
+                        None => Pending,
+                    },
+                    output => output,
+                };
+                *stats.lock().unwrap() = state.stats().clone();
+                output
+            })
+            .boxed()
+        }
+        SyncState::Session(mut state) => {
+            let mut stream = Some(stream);
+            stream::poll_fn(move |ctx| {
+                // Control messages are not supported in session mode.
+                while let Ready(Some(_)) = control_rx.poll_recv(ctx) {}
+                poll_session(Pin::new(&mut stream), &mut state, ctx)
+            })
+            .boxed()
+        }
+    };
+
+    Ok((
+        OutputStream::new(output_stream),
+        feedback_rx,
+        SyncHandle::new(control_tx, stats),
+    ))
+}
+
+/// Flushes the buffered messages once no message is received within
+/// the flush timeout. The timer restarts whenever a message is
+/// received or a flush happens.
+struct FlushTimer {
+    timeout: Duration,
+    last_flush: Instant,
+    // The sleep is created on the first poll, which runs within the
+    // tokio runtime.
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl FlushTimer {
+    fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            last_flush: Instant::now(),
+            sleep: None,
+        }
+    }
+
+    /// Polls the timer after the input stream returns `Pending`.
+    fn poll_flush<K, T>(
+        &mut self,
+        state: &mut State<K, T>,
+        ctx: &mut Context<'_>,
+    ) -> Poll<IndexMap<K, T>>
+    where
+        K: Key,
+        T: WithTimestamp + Clone,
+    {
+        loop {
+            let last_active = state
+                .last_received_wall
+                .values()
+                .copied()
+                .fold(self.last_flush, Instant::max);
+            let deadline = time::Instant::from_std(last_active + self.timeout);
+            let sleep = self
+                .sleep
+                .get_or_insert_with(|| Box::pin(time::sleep_until(deadline)));
+            sleep.as_mut().reset(deadline);
+            ready!(sleep.as_mut().poll(ctx));
+
+            // No message arrives in time. Form a group from whatever
+            // is buffered.
+            self.last_flush = Instant::now();
+            let group = state.flush_next();
+            state.update_feedback();
+            match group {
+                Some(group) if !state.is_duplicate_group(&group) => {
+                    debug!("flush a group after the timeout");
+                    #[cfg(feature = "metrics")]
+                    record_group_latency(&group);
+                    return Ready(group);
+                }
+                _ => continue,
+            }
+        }
+    }
+}
+
+/// Apply control messages sent from [SyncHandle] to the state.
+fn poll_control<K, T>(
+    control_rx: &mut mpsc::UnboundedReceiver<ControlMessage<K>>,
+    state: &mut State<K, T>,
+    ctx: &mut Context<'_>,
+) where
+    K: Key,
+    T: WithTimestamp + Clone,
+{
+    while let Ready(Some(msg)) = control_rx.poll_recv(ctx) {
+        match msg {
+            ControlMessage::Pause(key) => {
+                if !state.pause_key(key) {
+                    warn!("Unable to pause the stream.");
+                }
+            }
+            ControlMessage::Resume(key) => {
+                state.resume_key(&key);
+            }
+            ControlMessage::FastForward(target_ts) => {
+                state.fast_forward(target_ts);
+            }
+            ControlMessage::AddKey(key) => {
+                if !state.add_key(key) {
+                    warn!("Unable to add a registered stream.");
+                }
+            }
+            ControlMessage::RemoveKey(key) => {
+                let remaining = state.buffers.len().saturating_sub(1);
+                if !state.buffers.contains_key(&key)
+                    || remaining == 0
+                    || remaining < state.minimum_streams_required
+                {
+                    warn!("Unable to remove the stream.");
+                } else {
+                    state.remove_key(&key);
+                }
+            }
+        }
+        state.update_feedback();
+    }
+}
+
+/// The internal state selected by the window mode.
+#[allow(clippy::large_enum_variant)]
+pub(crate) enum SyncState<K, T>
+where
+    K: Key,
+    T: WithTimestamp + Clone,
+{
+    Sliding(State<K, T>),
+    Session(SessionState<K, T>),
+}
+
+/// Validate the configuration and the options, and initialize the
+/// internal state along with the feedback channel.
+#[allow(clippy::type_complexity)]
+pub(crate) fn init_state<K, T, I, C>(
+    keys: I,
+    config: C,
+    options: KeyOptions<K>,
+) -> Result<(SyncState<K, T>, FeedbackReceiver<K>), SyncError>
+where
+    K: Key,
+    T: WithTimestamp + Clone,
+    I: IntoIterator<Item = K>,
+    C: TryInto<ValidatedConfig>,
+    C::Error: Into<SyncError>,
 {
     // let keys: Vec<_> = keys.into_iter().collect();
 
+    let config = config.try_into().map_err(Into::<SyncError>::into)?;
+    options.validate(&config)?;
     let Config {
         window_size,
         start_time,
         buf_size,
         staleness_config,
-    } = config;
+        window_mode,
+        window_size_policy,
+        max_total_pending,
+        minimum_streams_required,
+        on_feedback_dropped,
+        dedup_output,
+        dedup_history,
+        per_key_feedback,
+        jitter_tolerance,
+        matching_strategy,
+        flush_timeout,
+        allow_equal_timestamps,
+        drop_policy,
+        out_of_order_tolerance,
+    } = config.into_inner();
+    let KeyOptions {
+        anchor_weights,
+        timestamp_offsets,
+        required_keys,
+        per_key_buf_sizes,
+    } = options;
 
-    // Sanity check
-    ensure!(buf_size >= 2);
-    ensure!(window_size > Duration::ZERO);
+    // The jitter tolerance widens both window boundaries.
+    let jitter_tolerance = jitter_tolerance.unwrap_or(Duration::ZERO);
+    let window_size = window_size + jitter_tolerance;
 
     // Initialize buffers for respective keys.
+    let per_key_buf_size = per_key_buf_sizes.unwrap_or_default();
     let buffers: IndexMap<_, _> = keys
         .into_iter()
         .map(|key| {
-            let buffer = Buffer::with_capacity(buf_size);
+            let capacity = per_key_buf_size.get(&key).copied().unwrap_or(buf_size);
+            let mut buffer = Buffer::with_capacity(capacity);
+            buffer.set_out_of_order_tolerance(out_of_order_tolerance);
             (key, buffer)
         })
         .collect();
-    ensure!(!buffers.is_empty());
+    if buffers.is_empty() {
+        return Err(SyncError::NoKeysProvided);
+    }
+    if let Some(max_total_pending) = max_total_pending {
+        // Every buffer needs at least two messages to form a group.
+        let required = buffers.len() * 2;
+        if max_total_pending < required {
+            return Err(SyncError::MaxTotalPendingTooSmall {
+                max_total_pending,
+                required,
+            });
+        }
+    }
+    if minimum_streams_required > buffers.len() {
+        return Err(SyncError::TooFewKeys {
+            keys: buffers.len(),
+            required: minimum_streams_required,
+        });
+    }
+
+    // Keys not listed in required_keys are optional.
+    let optional_keys: IndexSet<K> = match required_keys {
+        Some(required_keys) => {
+            if required_keys.iter().any(|key| !buffers.contains_key(key)) {
+                return Err(SyncError::UnknownRequiredKey);
+            }
+            buffers
+                .keys()
+                .filter(|key| !required_keys.contains(key))
+                .cloned()
+                .collect()
+        }
+        None => IndexSet::new(),
+    };
     // println!("the buffer is shown as below \n {buffers:#?}");
 
     // Create the queue that pipes generated feedback messages.
     let (feedback_tx, feedback_rx) = {
         let init_feedback = Feedback {
-            accepted_max_timestamp: None,
-            commit_timestamp: None,
-            accepted_keys: buffers.keys().cloned().collect(),
+            window_size: match window_mode {
+                WindowMode::Sliding => Some(window_size),
+                WindowMode::Session { .. } => None,
+            },
+            buffer_usage: match window_mode {
+                WindowMode::Sliding => buffers.keys().map(|key| (key.clone(), 0.0)).collect(),
+                WindowMode::Session { .. } => IndexMap::new(),
+            },
+            ..Feedback::new(buffers.keys().cloned().collect())
         };
         watch::channel(init_feedback)
     };
+    let mut feedback_rx = FeedbackReceiver::new(feedback_rx);
+
+    // Create the per-key feedback channels. Every key is accepted
+    // initially.
+    let key_feedback_tx = if per_key_feedback {
+        let (key_feedback_tx, key_feedback_rx) = buffers
+            .keys()
+            .map(|key| {
+                let (tx, rx) = watch::channel(true);
+                ((key.clone(), tx), (key.clone(), rx))
+            })
+            .unzip();
+        feedback_rx = feedback_rx.with_key_receivers(key_feedback_rx);
+        Some(key_feedback_tx)
+    } else {
+        None
+    };
+
+    // Session windows are handled by a dedicated state machine.
+    if let WindowMode::Session { gap } = window_mode {
+        let state = SessionState {
+            session: buffers.into_keys().map(|key| (key, None)).collect(),
+            closed: VecDeque::new(),
+            gap,
+            last_ts: None,
+            commit_ts: start_time,
+            feedback_tx: Some(feedback_tx),
+            on_feedback_dropped,
+        };
+        return Ok((SyncState::Session(state), feedback_rx));
+    }
 
     // Initialize staleness detector if configured
     let staleness_detector = staleness_config.map(StalenessDetector::new);
 
     // Initialize the internal state.
-    let mut state = State {
+    let state = State {
         feedback_tx: Some(feedback_tx),
         buffers,
         commit_ts: start_time,
+        start_time,
         buf_size,
         window_size,
         staleness_detector,
+        anchor_weights,
+        paused_keys: IndexSet::new(),
+        window_size_policy,
+        spread_history: VecDeque::new(),
+        max_total_pending,
+        minimum_streams_required,
+        timestamp_offsets,
+        on_feedback_dropped,
+        fingerprint_history: dedup_output.then(VecDeque::new),
+        dedup_history,
+        feedback_dirty: false,
+        last_received_wall: IndexMap::new(),
+        priority: None,
+        key_feedback_tx,
+        dead_letter_tx: None,
+        jitter_tolerance,
+        optional_keys,
+        absent_keys: vec![],
+        per_key_buf_size,
+        matching_strategy,
+        last_window: None,
+        flush_timeout,
+        allow_equal_timestamps,
+        drop_policy,
+        out_of_order_tolerance,
+        stats: SyncStats::default(),
     };
 
-    // Construct output stream.
-    let output_stream = {
-        let mut stream = Some(stream);
-        stream::poll_fn(move |ctx| poll(Pin::new(&mut stream), &mut state, ctx))
-    };
+    Ok((SyncState::Sliding(state), feedback_rx))
+}
 
-    Ok((output_stream.boxed(), feedback_rx))
+/// The polling function for session window mode. A group is emitted
+/// whenever a session is closed.
+fn poll_session<K, T, S>(
+    mut input_stream: Pin<&mut Option<S>>,
+    state: &mut SessionState<K, T>,
+    ctx: &mut Context<'_>,
+) -> Poll<Option<Result<IndexMap<K, T>>>>
+where
+    K: Key,
+    S: Stream<Item = Result<(K, Option<T>)>> + Unpin + Send,
+    T: WithTimestamp + Clone + Send,
+{
+    loop {
+        if let Some(group) = state.try_match() {
+            state.update_feedback();
+            return Ready(Some(Ok(group)));
+        }
+
+        let Some(input_stream_mut) = input_stream.as_mut().as_pin_mut() else {
+            return Ready(None);
+        };
+
+        match input_stream_mut.poll_next(ctx) {
+            Ready(Some(Ok((key, Some(item))))) => {
+                if state.push(key, item).is_err() {
+                    debug!("drop a late message");
+                }
+            }
+            Ready(Some(Ok((_key, None)))) => {
+                // Sessions do not require every stream.
+            }
+            Ready(Some(Err(err))) => {
+                input_stream.set(None);
+                return Ready(Some(Err(err)));
+            }
+            Ready(None) => {
+                // The input stream is depleted. Close the last session.
+                input_stream.set(None);
+                state.close();
+            }
+            Pending => return Pending,
+        }
+    }
 }
 
 /// The polling function is repeated called to generated batched
 /// messages.
-fn poll<K, T, S>(
+pub(crate) fn poll<K, T, S>(
     mut input_stream: Pin<&mut Option<S>>,
     state: &mut State<K, T>,
     ctx: &mut Context<'_>,
 ) -> Poll<Option<Result<IndexMap<K, T>>>>
 where
     K: Key,
-    S: Stream<Item = Result<(K, T)>> + Unpin + Send,
+    S: Stream<Item = Result<(K, Option<T>)>> + Unpin + Send,
+    T: WithTimestamp + Clone + Send,
+{
+    loop {
+        match poll_group(input_stream.as_mut(), state, ctx) {
+            Ready(Some(Ok(group))) if state.is_duplicate_group(&group) => {
+                debug!("skip a duplicated group");
+            }
+            poll => {
+                if let Ready(Some(Ok(_group))) = &poll {
+                    #[cfg(debug_assertions)]
+                    assert_group_valid(_group, state);
+                    #[cfg(feature = "metrics")]
+                    record_group_latency(_group);
+                    #[cfg(feature = "tracing")]
+                    debug!(
+                        commit_ts = ?state.commit_ts,
+                        group_size = _group.len(),
+                        "emit a group"
+                    );
+                }
+                return poll;
+            }
+        }
+    }
+}
+
+/// Panics if the group does not fit the window. Groups are only
+/// guaranteed to fit with a fixed window size and without anchor
+/// weights and timestamp offsets.
+#[cfg(debug_assertions)]
+fn assert_group_valid<K, T>(group: &IndexMap<K, T>, state: &State<K, T>)
+where
+    K: Key,
+    T: WithTimestamp + Clone,
+{
+    let is_plain = state.anchor_weights.is_none()
+        && state.timestamp_offsets.is_none()
+        && state.window_size_policy == crate::WindowSizePolicy::Fixed;
+    if !is_plain {
+        return;
+    }
+
+    if let Err(err) = crate::validate_group(group, state.window_size) {
+        panic!("invalid group: {err}");
+    }
+}
+
+/// Records the span from the oldest to the newest timestamp in the
+/// group.
+#[cfg(feature = "metrics")]
+fn record_group_latency<K, T>(group: &IndexMap<K, T>)
+where
+    T: WithTimestamp,
+{
+    let timestamps = group.values().map(|item| item.timestamp());
+    if let (Some(min), Some(max)) = (timestamps.clone().min(), timestamps.max()) {
+        metrics::histogram!("sync.group_latency_ms").record((max - min).as_secs_f64() * 1e3);
+    }
+}
+
+/// Poll for the next group regardless of deduplication.
+fn poll_group<K, T, S>(
+    mut input_stream: Pin<&mut Option<S>>,
+    state: &mut State<K, T>,
+    ctx: &mut Context<'_>,
+) -> Poll<Option<Result<IndexMap<K, T>>>>
+where
+    K: Key,
+    S: Stream<Item = Result<(K, Option<T>)>> + Unpin + Send,
     T: WithTimestamp + Clone + Send,
 {
     let group = if let Some(mut input_stream_mut) = input_stream.as_mut().as_pin_mut() {
@@ -124,17 +928,38 @@ where
                 // Case: Any one of the buffer has one or zero
                 // message.
 
+                // Drop the oldest message if the global limit is
+                // reached while waiting for lagging streams.
+                if state.exceeds_total_pending() {
+                    state.drop_min();
+                    state.update_feedback();
+                }
+
                 // Consume one message from the input stream.
                 let item = input_stream_mut.as_mut().poll_next(ctx);
                 // println!("............\n{:#?}\n",state);
                 match item {
-                    Ready(Some(Ok(item))) => {
-                        let (key, item) = item;
-                        let ok = state.push(key, item).is_ok();
-                        if !ok {
+                    Ready(Some(Ok((key, Some(item))))) => {
+                        #[cfg(feature = "tracing")]
+                        debug!(
+                            key_index = ?state.key_index(&key),
+                            timestamp = ?item.timestamp(),
+                            "receive a message"
+                        );
+                        if state.push(key, item).is_err() {
                             debug!("drop a late message")
                         }
                     } // A message is returned
+                    Ready(Some(Ok((key, None)))) => {
+                        // The stream identified by the key ends.
+                        #[cfg(feature = "tracing")]
+                        debug!(key_index = ?state.key_index(&key), "a stream ends");
+                        if !state.remove_key(&key) {
+                            input_stream.set(None);
+                            break poll_depleted(state);
+                        }
+                        state.update_feedback();
+                    }
                     Ready(Some(Err(err))) => {
                         // An error is returned
                         input_stream.set(None);
@@ -142,43 +967,16 @@ where
                     }
                     Ready(None) => {
                         // The input stream is depleted.
-                        // input_stream.set(None);
-                        // break None;
-                        // println!("........\n{:#?}\n........",state);
-                        if !state.is_empty() {
-                            // println!("checking the buffers still have datas");
-                            if let Some(matching) = state.try_match() {
-                                state.update_feedback();
-                                // println!("when input stream is depeleted and there are still matching");
-                                input_stream.set(None);
-                                break Some(Ok(matching));
-                            } else {
-                                // println!("there are still datas, but matching has failed");
-                                // input_stream.set(None);
-                                // break None;
-                                state.drop_min();
-                                continue;
-                            }
-                        } else {
-                            input_stream.set(None);
-                            break None;
-                        }
+                        #[cfg(feature = "tracing")]
+                        debug!("the input stream ends");
+                        input_stream.set(None);
+                        break poll_depleted(state);
                     }
                     Pending => {
                         // The input stream is not ready.
                         return Pending;
                     }
                 };
-
-                // Try to insert the message.
-                // let ok = state.push(key, item).is_ok();
-                // state.update_feedback();
-
-                // If failed, tell the input stream to catch up and
-                // retry.
-                // if !ok {
-                //     debug!("drop a late message");
-                // }
             } else if state.is_full() {
                 // eprintln!("full");
                 // Case: All buffers are full.
@@ -189,6 +987,13 @@ where
                 if let Some(matching) = state.try_match() {
                     state.update_feedback();
                     break Some(Ok(matching));
+                } else if state.drop_policy == DropPolicy::ReturnError {
+                    let key_index = state
+                        .min_timestamp()
+                        .and_then(|(key, _)| state.buffers.get_index_of(&key))
+                        .unwrap_or_default();
+                    input_stream.set(None);
+                    break Some(Err(SyncError::BufferOverflow { key_index }.into()));
                 } else {
                     warn!(
                         "Unable to find a new matching while all buffers are full.\
@@ -206,46 +1011,45 @@ where
                 let item = input_stream_mut.as_mut().poll_next(ctx);
 
                 match item {
-                    Ready(Some(Ok(item))) => {
-                        let (_key, _item) = item;
-                        if state.push(_key, _item).is_err() {
+                    Ready(Some(Ok((key, Some(item))))) => {
+                        #[cfg(feature = "tracing")]
+                        debug!(
+                            key_index = ?state.key_index(&key),
+                            timestamp = ?item.timestamp(),
+                            "receive a message"
+                        );
+                        if state.push(key, item).is_err() {
                             state.update_feedback();
                             continue;
                         }
                     }
+                    Ready(Some(Ok((key, None)))) => {
+                        // The stream identified by the key ends.
+                        #[cfg(feature = "tracing")]
+                        debug!(key_index = ?state.key_index(&key), "a stream ends");
+                        if !state.remove_key(&key) {
+                            input_stream.set(None);
+                            break poll_depleted(state);
+                        }
+                        state.update_feedback();
+                        continue;
+                    }
                     Ready(Some(Err(err))) => {
                         input_stream.set(None);
                         break Some(Err(err));
                     }
                     Ready(None) => {
-                        // println!("input stream has been depleted.............");
-                        // input_stream.set(None);
-                        // break None; // TODO
-                        if let Some(matching) = state.try_match() {
-                            state.update_feedback();
-                            input_stream.set(None);
-                            break Some(Ok(matching));
-                        } else {
-                            state.drop_min();
-                            continue;
-                            // input_stream.set(None);
-                            // break None;
-                        }
+                        // The input stream is depleted.
+                        #[cfg(feature = "tracing")]
+                        debug!("the input stream ends");
+                        input_stream.set(None);
+                        break poll_depleted(state);
                     }
                     Pending => {
                         return Pending;
                     }
                 };
 
-                // Try to insert the message to one of the buffer.  If
-                // not successful, emit a feedback to tell the input
-                // stream to catch up.
-                // if state.push(key, item).is_err() {
-                //     // debug!("drop a late message for device {:?}", device);
-                //     state.update_feedback();
-                //     continue;
-                // }
-
                 // Try to group up messages.
                 let matching = state.try_match();
 
@@ -261,33 +1065,30 @@ where
     } else {
         // eprintln!("depleted");
         // Case: the input stream is depleted.
-        // Loop until a valid group is found.
-        loop {
-            // Clean up expired messages using the latest commit timestamp as reference
-            if let Some(commit_ts) = state.commit_ts {
-                let _expired_count = state.drop_expired_messages(commit_ts);
-            }
-
-            if state.is_empty() {
-                break None;
-            } else if let Some(matching) = state.try_match() {
-                break Some(Ok(matching));
-            } else {
-                // println!("......\n{state:#?}\n......");
-                state.drop_min();
-            }
-        }
+        poll_depleted(state)
     };
 
     Ready(group)
 }
 
+/// Emit the next group after the input stream is depleted. The
+/// groups are formed one per poll rather than collected at once by
+/// [State::flush].
+fn poll_depleted<K, T>(state: &mut State<K, T>) -> Option<Result<IndexMap<K, T>>>
+where
+    K: Key,
+    T: WithTimestamp + Clone,
+{
+    let group = state.flush_next();
+    state.update_feedback();
+    group.map(Ok)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{Config, WithTimestamp};
+    use crate::{Config, ConfigError, DropReason, WindowMode, WindowSizePolicy, WithTimestamp};
     use futures::stream;
-    use std::time::Duration;
 
     #[derive(Debug, Clone, PartialEq, Eq)]
     struct TestMessage {
@@ -301,13 +1102,20 @@ mod tests {
         }
     }
 
+    fn create_message(timestamp_ms: u64) -> TestMessage {
+        TestMessage {
+            timestamp: Duration::from_millis(timestamp_ms),
+            data: format!("msg_{timestamp_ms}"),
+        }
+    }
+
     #[tokio::test]
     async fn test_config_valid_configuration() {
         let config = Config {
             window_size: Duration::from_millis(100),
             start_time: None,
             buf_size: 4,
-            staleness_config: None,
+            ..Default::default()
         };
 
         let empty_stream = stream::empty::<eyre::Result<(&str, TestMessage)>>();
@@ -323,14 +1131,17 @@ mod tests {
             window_size: Duration::from_millis(100),
             start_time: None,
             buf_size: 1,
-            staleness_config: None,
+            ..Default::default()
         };
 
         let empty_stream = stream::empty::<eyre::Result<(&str, TestMessage)>>();
         let keys = ["A", "B"];
 
         let result = sync(empty_stream, keys, config);
-        assert!(result.is_err());
+        assert!(matches!(
+            result,
+            Err(SyncError::InsufficientBufferCapacity(1))
+        ));
     }
 
     #[tokio::test]
@@ -339,14 +1150,14 @@ mod tests {
             window_size: Duration::ZERO,
             start_time: None,
             buf_size: 4,
-            staleness_config: None,
+            ..Default::default()
         };
 
         let empty_stream = stream::empty::<eyre::Result<(&str, TestMessage)>>();
         let keys = ["A", "B"];
 
         let result = sync(empty_stream, keys, config);
-        assert!(result.is_err());
+        assert!(matches!(result, Err(SyncError::InvalidWindowSize)));
     }
 
     #[tokio::test]
@@ -355,23 +1166,295 @@ mod tests {
             window_size: Duration::from_millis(100),
             start_time: None,
             buf_size: 4,
-            staleness_config: None,
+            ..Default::default()
         };
 
         let empty_stream = stream::empty::<eyre::Result<(&str, TestMessage)>>();
         let keys: Vec<&str> = vec![];
 
         let result = sync(empty_stream, keys, config);
+        assert!(matches!(result, Err(SyncError::NoKeysProvided)));
+    }
+
+    #[tokio::test]
+    async fn test_config_negative_anchor_weight() {
+        let config = Config::basic(Duration::from_millis(100), None, 4);
+        let options = KeyOptions::default()
+            .with_anchor_weights([("A", 1.0), ("B", -1.0)].into_iter().collect());
+
+        let empty_stream = stream::empty::<eyre::Result<(&str, TestMessage)>>();
+        let keys = ["A", "B"];
+
+        let result = sync_with_options(empty_stream, keys, config, options);
+        assert!(matches!(result, Err(SyncError::InvalidConfig(_))));
+    }
+
+    #[tokio::test]
+    async fn test_config_invalid_adaptive_window_size_policy() {
+        let config = Config::basic(Duration::from_millis(100), None, 4).with_window_size_policy(
+            WindowSizePolicy::Adaptive {
+                min: Duration::from_millis(200),
+                max: Duration::from_millis(100),
+                percentile: 0.9,
+            },
+        );
+
+        let empty_stream = stream::empty::<eyre::Result<(&str, TestMessage)>>();
+        let result = sync(empty_stream, ["A", "B"], config);
         assert!(result.is_err());
     }
 
+    #[tokio::test]
+    async fn test_config_max_total_pending_too_small() {
+        let config = Config::basic(Duration::from_millis(100), None, 4).with_max_total_pending(3);
+
+        let empty_stream = stream::empty::<eyre::Result<(&str, TestMessage)>>();
+        let result = sync(empty_stream, ["A", "B"], config);
+        assert!(matches!(
+            result,
+            Err(SyncError::MaxTotalPendingTooSmall {
+                max_total_pending: 3,
+                required: 4
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_config_minimum_streams_required_out_of_range() {
+        let empty_stream = stream::empty::<eyre::Result<(&str, TestMessage)>>();
+        let config =
+            Config::basic(Duration::from_millis(100), None, 4).with_minimum_streams_required(0);
+        assert!(sync(empty_stream, ["A", "B"], config).is_err());
+
+        let empty_stream = stream::empty::<eyre::Result<(&str, TestMessage)>>();
+        let config =
+            Config::basic(Duration::from_millis(100), None, 4).with_minimum_streams_required(3);
+        assert!(matches!(
+            sync(empty_stream, ["A", "B"], config),
+            Err(SyncError::TooFewKeys {
+                keys: 2,
+                required: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn test_config_builder() {
+        let config: Config = Config::builder().build().unwrap();
+        assert_eq!(config.window_size, Duration::from_millis(100));
+        assert_eq!(config.start_time, None);
+        assert_eq!(config.buf_size, 16);
+
+        let config: Config = Config::builder()
+            .window_size(Duration::from_millis(50))
+            .start_time(Duration::from_secs(1))
+            .buf_size(4)
+            .build()
+            .unwrap();
+        assert_eq!(config.window_size, Duration::from_millis(50));
+        assert_eq!(config.start_time, Some(Duration::from_secs(1)));
+        assert_eq!(config.buf_size, 4);
+
+        let result = Config::builder().window_size(Duration::ZERO).build();
+        assert!(matches!(result, Err(SyncError::InvalidWindowSize)));
+
+        let result = Config::builder().buf_size(1).build();
+        assert!(matches!(
+            result,
+            Err(SyncError::InsufficientBufferCapacity(1))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_config_per_key_buf_sizes() {
+        let empty_stream = stream::empty::<eyre::Result<(&str, TestMessage)>>();
+        let config = Config::basic(Duration::from_millis(100), None, 4);
+        let options =
+            KeyOptions::default().with_per_key_buf_sizes(IndexMap::from([("A", 8), ("B", 1)]));
+        assert_eq!(
+            options.validate(&config).unwrap_err(),
+            vec![ConfigError::PerKeyBufSizeTooSmall {
+                key_index: 1,
+                buf_size: 1
+            }]
+        );
+        assert!(sync_with_options(empty_stream, ["A", "B"], config.clone(), options).is_err());
+
+        let (state, _feedback_rx) = init_state::<_, TestMessage, _, _>(
+            ["A", "B"],
+            config,
+            KeyOptions::default().with_per_key_buf_sizes(IndexMap::from([("A", 8)])),
+        )
+        .unwrap();
+        let SyncState::Sliding(state) = state else {
+            panic!("expect sliding window state");
+        };
+        assert_eq!(state.key_buf_size(&"A"), 8);
+        assert_eq!(state.key_buf_size(&"B"), 4);
+    }
+
+    #[tokio::test]
+    async fn test_sync_with_eof_continues_without_ended_stream() {
+        let messages: Vec<eyre::Result<_>> = vec![
+            Ok(("A", Some(create_message(1000)))),
+            Ok(("B", Some(create_message(1005)))),
+            Ok(("A", Some(create_message(1100)))),
+            Ok(("B", None)),
+            Ok(("A", Some(create_message(1200)))),
+            Ok(("A", Some(create_message(1300)))),
+        ];
+        let config = Config::basic(Duration::from_millis(50), None, 4);
+        let input = stream::iter(messages);
+        let (output, _feedback) = sync_with_eof(input, ["A", "B"], config).unwrap();
+        let groups: Vec<_> = output.try_collect().await.unwrap();
+
+        // Pending messages of the ended stream are discarded.
+        assert!(groups.iter().all(|group| group.len() == 1));
+        let timestamps: Vec<_> = groups.iter().map(|group| group["A"].timestamp).collect();
+        assert_eq!(
+            timestamps,
+            [1000, 1100, 1200, 1300].map(Duration::from_millis)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sync_with_eof_stops_below_minimum_streams() {
+        let messages: Vec<eyre::Result<_>> = vec![
+            Ok(("A", Some(create_message(1000)))),
+            Ok(("B", Some(create_message(1005)))),
+            Ok(("B", None)),
+            Ok(("A", Some(create_message(1100)))),
+        ];
+        let config =
+            Config::basic(Duration::from_millis(50), None, 4).with_minimum_streams_required(2);
+        let input = stream::iter(messages);
+        let (output, _feedback) = sync_with_eof(input, ["A", "B"], config).unwrap();
+        let groups: Vec<_> = output.try_collect().await.unwrap();
+
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0]["A"].timestamp, Duration::from_millis(1000));
+        assert_eq!(groups[0]["A"].data, "msg_1000");
+    }
+
+    #[test]
+    fn test_config_validate_collects_all_errors() {
+        let config = Config::basic(Duration::ZERO, None, 1);
+        assert_eq!(
+            config.validate(),
+            Err(vec![
+                ConfigError::WindowSizeZero,
+                ConfigError::BufSizeTooSmall(1)
+            ])
+        );
+
+        let config = Config::basic(Duration::from_millis(100), None, 4);
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_sync_accepts_validated_config() {
+        let config: ValidatedConfig = Config::basic(Duration::from_millis(100), None, 4)
+            .try_into()
+            .unwrap();
+
+        let empty_stream = stream::empty::<eyre::Result<(&str, TestMessage)>>();
+        let (output, _feedback) = sync(empty_stream, ["A", "B"], config).unwrap();
+        let groups: Vec<_> = output.try_collect().await.unwrap();
+        assert!(groups.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_config_unreasonable_start_time() {
+        let config = Config::basic(Duration::from_millis(100), None, 4)
+            .starting_at(Duration::from_secs(u32::MAX as u64 + 1));
+
+        let empty_stream = stream::empty::<eyre::Result<(&str, TestMessage)>>();
+        let result = sync(empty_stream, ["A", "B"], config);
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_start_time_is_exclusive() {
+        let config = Config::basic(Duration::from_millis(50), None, 4)
+            .starting_at(Duration::from_millis(1000));
+        let messages: Vec<eyre::Result<_>> = vec![
+            Ok(("A", create_message(1000))),
+            Ok(("B", create_message(1001))),
+            Ok(("A", create_message(1002))),
+        ];
+
+        let input = stream::iter(messages);
+        let (output, _feedback) = sync(input, ["A", "B"], config).unwrap();
+        let groups: Vec<_> = output.try_collect().await.unwrap();
+
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0]["A"].timestamp, Duration::from_millis(1002));
+        assert_eq!(groups[0]["B"].timestamp, Duration::from_millis(1001));
+    }
+
+    #[tokio::test]
+    async fn test_config_from_duration() {
+        let config: Config = Duration::from_millis(50).into();
+        assert_eq!(config.window_size, Duration::from_millis(50));
+        assert_eq!(config.start_time, None);
+        assert_eq!(config.buf_size, 16);
+
+        let empty_stream = stream::empty::<eyre::Result<(&str, TestMessage)>>();
+        let result = sync(
+            empty_stream,
+            ["A", "B"],
+            Config::from(Duration::from_millis(50)),
+        );
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_config_from_duration_and_buf_size() {
+        let config: Config = (Duration::from_millis(50), 4).into();
+        assert_eq!(config.window_size, Duration::from_millis(50));
+        assert_eq!(config.buf_size, 4);
+    }
+
+    #[test]
+    fn test_config_with_start_system_time() {
+        let start = std::time::SystemTime::UNIX_EPOCH + Duration::from_secs(10);
+        let config =
+            Config::basic(Duration::from_millis(50), None, 4).with_start_system_time(start);
+        assert_eq!(config.start_time, Some(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn test_config_from_rate() {
+        let config = Config::from_rate(4.0, 0.5, 2.0).unwrap();
+        assert_eq!(config.window_size, Duration::from_millis(125));
+        assert_eq!(config.buf_size, 8);
+        assert_eq!(config.validate(), Ok(()));
+
+        // The buffer holds a partial message period rounded up.
+        let config = Config::from_rate(4.0, 0.5, 0.6).unwrap();
+        assert_eq!(config.buf_size, 3);
+
+        assert_eq!(
+            Config::from_rate(0.0, f64::INFINITY, -1.0).unwrap_err(),
+            SyncError::InvalidConfig(vec![
+                ConfigError::InvalidRate(0.0),
+                ConfigError::InvalidJitterFraction(f64::INFINITY),
+                ConfigError::InvalidBufferSeconds(-1.0),
+            ])
+        );
+        assert_eq!(
+            Config::from_rate(4.0, 0.5, 0.25).unwrap_err(),
+            SyncError::InsufficientBufferCapacity(1)
+        );
+    }
+
     #[tokio::test]
     async fn test_config_minimum_valid_values() {
         let config = Config {
             window_size: Duration::from_nanos(1), // Minimum valid window
             start_time: None,
             buf_size: 2, // Minimum valid buffer size
-            staleness_config: None,
+            ..Default::default()
         };
 
         let empty_stream = stream::empty::<eyre::Result<(&str, TestMessage)>>();
@@ -380,4 +1463,400 @@ mod tests {
         let result = sync(empty_stream, keys, config);
         assert!(result.is_ok());
     }
+
+    #[cfg(feature = "humantime")]
+    #[test]
+    fn test_config_display() {
+        use crate::MatchingStrategy;
+
+        let config = Config::basic(Duration::from_millis(50), None, 16);
+        assert_eq!(
+            config.to_string(),
+            "Config { window_size: 50ms, buf_size: 16, start_time: none }"
+        );
+
+        let config = config.starting_at(Duration::from_millis(1500));
+        assert_eq!(
+            config.to_string(),
+            "Config { window_size: 50ms, buf_size: 16, start_time: 1s 500ms }"
+        );
+
+        let config = config
+            .with_matching_strategy(MatchingStrategy::Closest)
+            .with_flush_timeout(Duration::from_millis(200));
+        assert_eq!(
+            config.to_string(),
+            "Config { window_size: 50ms, buf_size: 16, start_time: 1s 500ms, \
+             matching_strategy: Closest, flush_timeout: 200ms }"
+        );
+    }
+
+    #[test]
+    fn test_config_dedup_history_zero() {
+        let config = Config::basic(Duration::from_millis(50), None, 4).with_dedup_output(0);
+        assert_eq!(config.validate(), Err(vec![ConfigError::DedupHistoryZero]));
+    }
+
+    #[test]
+    fn test_sync_per_key_feedback() {
+        let config = Config::basic(Duration::from_millis(50), None, 4).with_per_key_feedback();
+        let empty_stream = stream::empty::<eyre::Result<(&str, TestMessage)>>();
+        let (_output, feedback_rx) = sync(empty_stream, ["A", "B"], config).unwrap();
+
+        let key_receivers = feedback_rx.key_receivers().unwrap();
+        assert_eq!(key_receivers.len(), 2);
+        assert!(key_receivers.values().all(|rx| *rx.borrow()));
+        assert!(feedback_rx.key_receiver(&"C").is_none());
+
+        let config = Config::basic(Duration::from_millis(50), None, 4)
+            .with_window_mode(WindowMode::Session {
+                gap: Duration::from_millis(100),
+            })
+            .with_per_key_feedback();
+        assert_eq!(
+            config.validate(),
+            Err(vec![ConfigError::UnsupportedInSessionMode(
+                "per_key_feedback"
+            )])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sync_jitter_tolerance() {
+        // The spread of 30 ms exceeds the window size alone.
+        let messages = [1000, 1100, 1200]
+            .into_iter()
+            .flat_map(|ts| [("A", ts), ("B", ts + 30)])
+            .map(|(key, ts)| Ok((key, create_message(ts))));
+        let config = Config::basic(Duration::from_millis(20), None, 4)
+            .with_jitter_tolerance(Duration::from_millis(15));
+
+        let (output, feedback_rx) = sync(stream::iter(messages), ["A", "B"], config).unwrap();
+        assert_eq!(
+            feedback_rx.borrow().window_size,
+            Some(Duration::from_millis(35))
+        );
+
+        let groups: Vec<_> = output.try_collect().await.unwrap();
+        assert_eq!(groups.len(), 3);
+        for (group, ts) in groups.iter().zip([1000, 1100, 1200]) {
+            assert_eq!(group["A"], create_message(ts));
+            assert_eq!(group["B"], create_message(ts + 30));
+        }
+    }
+
+    #[test]
+    fn test_sync_required_keys_must_be_registered() {
+        let config = Config::basic(Duration::from_millis(50), None, 4);
+        let empty_stream = || stream::empty::<eyre::Result<(&str, TestMessage)>>();
+        let required = |keys| KeyOptions::default().with_required_keys(keys);
+
+        let result = sync_with_options(
+            empty_stream(),
+            ["A", "B"],
+            config.clone(),
+            required(vec!["C"]),
+        );
+        assert!(matches!(result, Err(SyncError::UnknownRequiredKey)));
+
+        let result =
+            sync_with_options(empty_stream(), ["A", "B"], config.clone(), required(vec![]));
+        assert!(result.is_err());
+
+        let result = sync_with_options(empty_stream(), ["A", "B"], config, required(vec!["A"]));
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_sync_concurrent() {
+        let (senders, output, _feedback_rx) = sync_concurrent(
+            ["A", "B"],
+            8,
+            Config::basic(Duration::from_millis(50), None, 4),
+        )
+        .unwrap();
+
+        let producers = senders.into_iter().map(|(key, tx)| {
+            let offset = if key == "A" { 0 } else { 5 };
+            tokio::spawn(async move {
+                for ts in [1000, 1100, 1200] {
+                    tx.send(create_message(ts + offset)).await.unwrap();
+                }
+            })
+        });
+        for producer in producers.collect::<Vec<_>>() {
+            producer.await.unwrap();
+        }
+
+        let groups: Vec<_> = output.try_collect().await.unwrap();
+        assert_eq!(groups.len(), 3);
+        for (group, ts) in groups.iter().zip([1000, 1100, 1200]) {
+            assert_eq!(group["A"], create_message(ts));
+            assert_eq!(group["B"], create_message(ts + 5));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sync_from_streams() {
+        let stream_of = |offset: u64| {
+            stream::iter([1000, 1100, 1200].map(|ts| eyre::Ok(create_message(ts + offset))))
+        };
+        let config = || Config::basic(Duration::from_millis(50), None, 4);
+
+        let (output, _feedback_rx) =
+            sync_from_streams([("A", stream_of(0)), ("B", stream_of(5))], config()).unwrap();
+        let groups: Vec<_> = output.try_collect().await.unwrap();
+        assert_eq!(groups.len(), 3);
+        for (group, ts) in groups.iter().zip([1000, 1100, 1200]) {
+            assert_eq!(group["A"], create_message(ts));
+            assert_eq!(group["B"], create_message(ts + 5));
+        }
+
+        let result = sync_from_streams([("A", stream_of(0)), ("A", stream_of(5))], config());
+        assert!(matches!(result, Err(SyncError::DuplicateKey)));
+    }
+
+    #[tokio::test]
+    async fn test_sync_with_envelope() {
+        let input = stream::iter(
+            [1000, 1005, 1100, 1108, 1200, 1210]
+                .into_iter()
+                .enumerate()
+                .map(|(index, ts)| {
+                    let key = if index % 2 == 0 { "A" } else { "B" };
+                    eyre::Ok((key, create_message(ts)))
+                }),
+        );
+        let config = Config::basic(Duration::from_millis(50), None, 4);
+        let (output, _feedback_rx) = sync_with_envelope(input, ["A", "B"], config).unwrap();
+        let envelopes: Vec<_> = output.try_collect().await.unwrap();
+
+        assert_eq!(envelopes.len(), 3);
+        for (index, (envelope, ts)) in envelopes.iter().zip([1000, 1100, 1200]).enumerate() {
+            assert_eq!(envelope.group_index, index as u64);
+            assert_eq!(envelope.commit_ts, Duration::from_millis(ts));
+            assert_eq!(envelope.messages["A"], create_message(ts));
+            assert!(envelope.window_start <= envelope.commit_ts);
+            for msg in envelope.messages.values() {
+                assert!(msg.timestamp <= envelope.window_end);
+            }
+        }
+
+        let config = Config::basic(Duration::from_millis(50), None, 4).with_window_mode(
+            WindowMode::Session {
+                gap: Duration::from_millis(10),
+            },
+        );
+        let input = stream::empty::<eyre::Result<(&str, TestMessage)>>();
+        let result = sync_with_envelope(input, ["A", "B"], config);
+        assert!(matches!(result, Err(SyncError::SessionModeUnsupported)));
+    }
+
+    #[tokio::test]
+    async fn test_sync_flush_timeout() {
+        // Both streams stall after one message each, which is not
+        // enough to form a group without flushing.
+        let input = || {
+            stream::iter([
+                eyre::Ok(("A", create_message(1000))),
+                eyre::Ok(("B", create_message(1010))),
+            ])
+            .chain(stream::pending())
+        };
+        let config = || Config::basic(Duration::from_millis(50), None, 4);
+
+        let (mut output, _feedback_rx) = sync(input(), ["A", "B"], config()).unwrap();
+        let result = tokio::time::timeout(Duration::from_millis(200), output.next()).await;
+        assert!(result.is_err());
+
+        let config = config().with_flush_timeout(Duration::from_millis(50));
+        let (mut output, _feedback_rx) = sync_with_envelope(input(), ["A", "B"], config).unwrap();
+        let envelope = tokio::time::timeout(Duration::from_secs(5), output.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert!(envelope.flushed);
+        assert_eq!(envelope.messages["A"], create_message(1000));
+        assert_eq!(envelope.messages["B"], create_message(1010));
+    }
+
+    #[tokio::test]
+    async fn test_sync_drop_policy_return_error() {
+        // Both buffers are full while the messages span less than the
+        // window, so no group can be formed.
+        let input = || {
+            stream::iter([
+                eyre::Ok(("A", create_message(10))),
+                eyre::Ok(("A", create_message(20))),
+                eyre::Ok(("B", create_message(30))),
+                eyre::Ok(("B", create_message(40))),
+            ])
+        };
+        let config = || Config::basic(Duration::from_millis(100), None, 2);
+
+        let (output, _feedback_rx) = sync(input(), ["A", "B"], config()).unwrap();
+        let results: Vec<_> = output.collect().await;
+        assert!(results.iter().all(|result| result.is_ok()));
+
+        let config = config().with_drop_policy(DropPolicy::ReturnError);
+        let (mut output, _feedback_rx) = sync(input(), ["A", "B"], config).unwrap();
+        let err = output.next().await.unwrap().unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<SyncError>(),
+            Some(&SyncError::BufferOverflow { key_index: 0 })
+        );
+    }
+
+    #[test]
+    fn test_config_flush_timeout() {
+        let config = Config::basic(Duration::from_millis(50), None, 4);
+        assert_eq!(
+            config.clone().with_flush_timeout(Duration::ZERO).validate(),
+            Err(vec![ConfigError::FlushTimeoutZero])
+        );
+
+        let config = config
+            .with_flush_timeout(Duration::from_millis(10))
+            .with_window_mode(WindowMode::Session {
+                gap: Duration::from_millis(10),
+            });
+        assert_eq!(
+            config.validate(),
+            Err(vec![ConfigError::UnsupportedInSessionMode("flush_timeout")])
+        );
+    }
+
+    #[test]
+    fn test_config_options_in_session_mode() {
+        let config = Config::basic(Duration::from_millis(50), None, 4)
+            .with_equal_timestamps()
+            .with_window_mode(WindowMode::Session {
+                gap: Duration::from_millis(10),
+            });
+        assert_eq!(
+            config.validate(),
+            Err(vec![ConfigError::UnsupportedInSessionMode(
+                "allow_equal_timestamps"
+            )])
+        );
+
+        let config = Config::basic(Duration::from_millis(50), None, 4)
+            .with_drop_policy(DropPolicy::DropNewest)
+            .with_window_mode(WindowMode::Session {
+                gap: Duration::from_millis(10),
+            });
+        assert_eq!(
+            config.validate(),
+            Err(vec![ConfigError::UnsupportedInSessionMode("drop_policy")])
+        );
+
+        let config = Config::basic(Duration::from_millis(50), None, 4)
+            .with_out_of_order_tolerance(Duration::from_millis(5))
+            .with_window_mode(WindowMode::Session {
+                gap: Duration::from_millis(10),
+            });
+        assert_eq!(
+            config.validate(),
+            Err(vec![ConfigError::UnsupportedInSessionMode(
+                "out_of_order_tolerance"
+            )])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sync_with_dead_letters() {
+        let input = stream::iter([
+            eyre::Ok(("A", create_message(10))),
+            eyre::Ok(("A", create_message(100))),
+            eyre::Ok(("B", create_message(110))),
+            eyre::Ok(("A", create_message(200))),
+            eyre::Ok(("B", create_message(210))),
+        ]);
+        let config = Config::basic(
+            Duration::from_millis(50),
+            Some(Duration::from_millis(50)),
+            4,
+        );
+
+        let (output, _feedback_rx, mut dead_letter_rx) =
+            sync_with_dead_letters(input, ["A", "B"], config).unwrap();
+        let groups: Vec<_> = output.try_collect().await.unwrap();
+        assert!(!groups.is_empty());
+
+        let dropped = dead_letter_rx.recv().await.unwrap();
+        assert_eq!(dropped.key, "A");
+        assert_eq!(dropped.item, create_message(10));
+        assert_eq!(dropped.drop_reason, DropReason::BeforeStartTime);
+        assert_eq!(dropped.dropped_at, Some(Duration::from_millis(50)));
+    }
+
+    #[tokio::test]
+    async fn test_sync_builder() {
+        let input = || {
+            stream::iter([
+                eyre::Ok(("A", create_message(1000))),
+                eyre::Ok(("B", create_message(1010))),
+                eyre::Ok(("A", create_message(1100))),
+                eyre::Ok(("B", create_message(1110))),
+            ])
+        };
+        let config = || Config::basic(Duration::from_millis(50), None, 4);
+
+        let (output, _feedback_rx) = ["A", "B"]
+            .into_iter()
+            .fold(SyncBuilder::new(config()), SyncBuilder::add_key)
+            .build(input())
+            .unwrap();
+        let groups: Vec<_> = output.try_collect().await.unwrap();
+
+        let (output, _feedback_rx) = sync(input(), ["A", "B"], config()).unwrap();
+        let expected: Vec<_> = output.try_collect().await.unwrap();
+        assert_eq!(groups, expected);
+
+        let result = SyncBuilder::<&str, TestMessage>::new(config()).build(input());
+        assert!(matches!(result, Err(SyncError::NoKeysProvided)));
+
+        let result = SyncBuilder::new(config())
+            .add_key("A")
+            .add_key("B")
+            .key_options(KeyOptions::default().with_required_keys(vec!["C"]))
+            .build(input());
+        assert!(matches!(result, Err(SyncError::UnknownRequiredKey)));
+    }
+
+    #[tokio::test]
+    async fn test_sync_any() {
+        let input = stream::iter([
+            eyre::Ok(("A", AnyMessage::new(create_message(1000)))),
+            eyre::Ok(("B", AnyMessage::new((Duration::from_millis(1010), 7u32)))),
+            eyre::Ok(("A", AnyMessage::new(create_message(1100)))),
+            eyre::Ok(("B", AnyMessage::new((Duration::from_millis(1110), 8u32)))),
+        ]);
+        let config = Config::basic(Duration::from_millis(50), None, 4);
+
+        let (output, _feedback_rx) = sync_any(input, ["A", "B"], config).unwrap();
+        let groups: Vec<_> = output.try_collect().await.unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(
+            groups[0].get_as::<TestMessage>(&"A"),
+            Some(&create_message(1000))
+        );
+        assert_eq!(
+            groups[0].get_as::<(Duration, u32)>(&"B"),
+            Some(&(Duration::from_millis(1010), 7))
+        );
+        assert_eq!(groups[1].get_as::<TestMessage>(&"B"), None);
+        assert_eq!(groups[1].get_as::<TestMessage>(&"C"), None);
+    }
+
+    #[tokio::test]
+    async fn test_sync_concurrent_zero_channel_size() {
+        let result = sync_concurrent::<_, TestMessage, _, _>(
+            ["A", "B"],
+            0,
+            Config::basic(Duration::from_millis(50), None, 4),
+        );
+        assert!(matches!(result, Err(SyncError::ChannelSizeZero)));
+    }
 }
@@ -0,0 +1,218 @@
+use crate::{
+    state::State,
+    sync::{init_state, poll, SyncState},
+    types::{Key, SyncStats, WithTimestamp},
+    KeyOptions, SyncError, ValidatedConfig,
+};
+use eyre::Result;
+use futures::{stream, task::noop_waker_ref, Stream, StreamExt};
+use indexmap::IndexMap;
+use std::{
+    collections::VecDeque,
+    fmt::{self, Debug},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Poll::*},
+};
+
+/// Feed an iterator of messages, each identified by a key, to the
+/// synchronizer without an async runtime.
+///
+/// The function returns the groups formed while feeding the messages
+/// and the internal state keeping the remaining buffered
+/// messages. Call [drain_to_vec] on the state to emit the rest of
+/// groups.
+#[allow(clippy::type_complexity)]
+pub fn sync_from_iter<K, T, M, I, C>(
+    messages: M,
+    keys: I,
+    config: C,
+) -> Result<(Vec<IndexMap<K, T>>, State<K, T>)>
+where
+    K: Key,
+    T: WithTimestamp + Clone,
+    M: IntoIterator<Item = (K, T)>,
+    I: IntoIterator<Item = K>,
+    C: TryInto<ValidatedConfig>,
+    C::Error: Into<SyncError>,
+{
+    let (state, _feedback_rx) = init_state(keys, config, KeyOptions::default())?;
+    let SyncState::Sliding(mut state) = state else {
+        return Err(SyncError::SessionModeUnsupported.into());
+    };
+
+    // The pending stream keeps the buffered messages in the state
+    // once the messages are exhausted.
+    let messages: Vec<_> = messages
+        .into_iter()
+        .map(|(key, item)| Ok((key, Some(item))))
+        .collect();
+    let mut input_stream = Some(stream::iter(messages).chain(stream::pending()));
+    let mut ctx = Context::from_waker(noop_waker_ref());
+    let mut groups = vec![];
+
+    loop {
+        match poll(Pin::new(&mut input_stream), &mut state, &mut ctx) {
+            Ready(Some(Ok(group))) => groups.push(group),
+            Ready(Some(Err(err))) => return Err(err),
+            Ready(None) | Pending => break,
+        }
+    }
+
+    Ok((groups, state))
+}
+
+/// Feed an iterator of messages, each identified by a key, to the
+/// synchronizer without an async runtime, and emit the groups
+/// through an iterator. It is a shorthand of [SyncIter::new].
+pub fn sync_iter<K, T, M, I, C>(
+    messages: M,
+    keys: I,
+    config: C,
+) -> Result<SyncIter<K, T>, SyncError>
+where
+    K: Key,
+    T: WithTimestamp + Clone,
+    M: IntoIterator<Item = (K, T)>,
+    I: IntoIterator<Item = K>,
+    C: TryInto<ValidatedConfig>,
+    C::Error: Into<SyncError>,
+{
+    SyncIter::new(messages, keys, config)
+}
+
+/// Emit all remaining groups from the state. See
+/// [State::drain_to_vec].
+pub fn drain_to_vec<K, T>(state: &mut State<K, T>) -> Vec<IndexMap<K, T>>
+where
+    K: Key,
+    T: WithTimestamp + Clone,
+{
+    state.drain_to_vec()
+}
+
+/// An iterator emitting groups from messages fed to the synchronizer
+/// without an async runtime. Unlike [sync_from_iter], the buffered
+/// messages are flushed once the messages are exhausted.
+pub struct SyncIter<K, T>
+where
+    K: Key,
+    T: WithTimestamp + Clone,
+{
+    state: State<K, T>,
+    input_stream: Option<MessageQueue<K, T>>,
+    stats: Option<Arc<Mutex<SyncStats<K>>>>,
+}
+
+impl<K, T> SyncIter<K, T>
+where
+    K: Key,
+    T: WithTimestamp + Clone,
+{
+    pub fn new<M, I, C>(messages: M, keys: I, config: C) -> Result<Self, SyncError>
+    where
+        M: IntoIterator<Item = (K, T)>,
+        I: IntoIterator<Item = K>,
+        C: TryInto<ValidatedConfig>,
+        C::Error: Into<SyncError>,
+    {
+        let (state, _feedback_rx) = init_state(keys, config, KeyOptions::default())?;
+        let SyncState::Sliding(state) = state else {
+            return Err(SyncError::SessionModeUnsupported);
+        };
+
+        Ok(Self {
+            state,
+            input_stream: Some(MessageQueue {
+                messages: messages.into_iter().collect(),
+            }),
+            stats: None,
+        })
+    }
+
+    /// Attaches statistics collection. The returned stats are updated
+    /// as the iterator advances.
+    pub fn with_stats(mut self) -> (Self, Arc<Mutex<SyncStats<K>>>) {
+        let stats = Arc::new(Mutex::new(SyncStats::default()));
+        self.stats = Some(stats.clone());
+        (self, stats)
+    }
+
+    /// Unwraps the internal state keeping the buffered messages.
+    pub fn into_state(self) -> State<K, T> {
+        self.state
+    }
+}
+
+impl<K, T> Iterator for SyncIter<K, T>
+where
+    K: Key,
+    T: WithTimestamp + Clone,
+{
+    type Item = Result<IndexMap<K, T>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut ctx = Context::from_waker(noop_waker_ref());
+        // The message queue never returns Pending.
+        let item = match poll(Pin::new(&mut self.input_stream), &mut self.state, &mut ctx) {
+            Ready(item) => item,
+            Pending => None,
+        };
+
+        if let Some(stats) = &self.stats {
+            *stats.lock().unwrap() = self.state.stats().clone();
+        }
+
+        item
+    }
+}
+
+impl<K, T> SyncIter<K, T>
+where
+    K: Key,
+    T: WithTimestamp + Clone,
+{
+    fn pending_messages(&self) -> usize {
+        self.input_stream
+            .as_ref()
+            .map_or(0, |queue| queue.messages.len())
+    }
+}
+
+impl<K, T> Debug for SyncIter<K, T>
+where
+    K: Key + Debug,
+    T: WithTimestamp + Clone,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let buffer_lens: IndexMap<_, _> = self
+            .state
+            .buffers
+            .iter()
+            .map(|(key, buffer)| (key, buffer.len()))
+            .collect();
+
+        f.debug_struct("SyncIter")
+            .field("pending_messages", &self.pending_messages())
+            .field("buffer_lens", &buffer_lens)
+            .field("commit_ts", &self.state.commit_ts)
+            .finish()
+    }
+}
+
+/// The input stream of [SyncIter], which is always ready.
+struct MessageQueue<K, T> {
+    messages: VecDeque<(K, T)>,
+}
+
+// The messages are never pinned.
+impl<K, T> Unpin for MessageQueue<K, T> {}
+
+impl<K, T> Stream for MessageQueue<K, T> {
+    type Item = Result<(K, Option<T>)>;
+
+    fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let item = self.get_mut().messages.pop_front();
+        Ready(item.map(|(key, item)| Ok((key, Some(item)))))
+    }
+}
@@ -0,0 +1,140 @@
+use crate::types::{FeedbackReceiver, Key};
+use eyre::Result;
+use futures::{future::BoxFuture, ready, Stream, StreamExt};
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// A source stream throttled by the feedback from
+/// [sync](crate::sync()).
+///
+/// A message is held back until its key is accepted by the
+/// synchronizer, i.e. the buffer for the key is not full. Once the
+/// synchronizer is dropped, messages are passed through without
+/// throttling.
+///
+/// Wrap each source separately before merging them into the input of
+/// the synchronizer, so that a throttled source does not block the
+/// others.
+#[must_use = "streams do nothing unless polled"]
+pub struct ThrottledSource<K, T, S>
+where
+    K: Key,
+{
+    stream: S,
+    feedback_rx: FeedbackReceiver<K>,
+    pending: Option<(K, T)>,
+    changed: Option<BoxFuture<'static, bool>>,
+    closed: bool,
+}
+
+impl<K, T, S> ThrottledSource<K, T, S>
+where
+    K: Key + 'static,
+    S: Stream<Item = Result<(K, T)>> + Unpin,
+{
+    pub fn new(stream: S, feedback_rx: FeedbackReceiver<K>) -> Self {
+        Self {
+            stream,
+            feedback_rx,
+            pending: None,
+            changed: None,
+            closed: false,
+        }
+    }
+
+    /// Unwraps the underlying stream. A message held back is
+    /// discarded.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+// The held message is never pinned.
+impl<K, T, S> Unpin for ThrottledSource<K, T, S>
+where
+    K: Key,
+    S: Unpin,
+{
+}
+
+impl<K, T, S> Stream for ThrottledSource<K, T, S>
+where
+    K: Key + 'static,
+    S: Stream<Item = Result<(K, T)>> + Unpin,
+{
+    type Item = Result<(K, T)>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            if let Some((key, _)) = &this.pending {
+                // Mark the feedback as seen so that the next change
+                // wakes up this stream.
+                let accepted =
+                    this.closed || this.feedback_rx.borrow_and_update().is_key_accepted(key);
+                if accepted {
+                    this.changed = None;
+                    let item = this.pending.take().unwrap();
+                    return Poll::Ready(Some(Ok(item)));
+                }
+
+                let changed = this.changed.get_or_insert_with(|| {
+                    let mut feedback_rx = this.feedback_rx.clone().into_inner();
+                    Box::pin(async move { feedback_rx.changed().await.is_ok() })
+                });
+                let is_open = ready!(changed.as_mut().poll(cx));
+                this.changed = None;
+
+                if !is_open {
+                    this.closed = true;
+                }
+                continue;
+            }
+
+            match ready!(this.stream.poll_next_unpin(cx)) {
+                Some(Ok(item)) => this.pending = Some(item),
+                item => return Poll::Ready(item),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Feedback;
+    use futures::{stream, FutureExt};
+    use tokio::sync::watch;
+
+    #[tokio::test]
+    async fn test_throttled_source_waits_for_accepted_key() {
+        let (feedback_tx, feedback_rx) = watch::channel(Feedback::new(vec![]));
+        let source = stream::iter(vec![Ok(("A", 1)), Ok(("A", 2))]);
+        let mut throttled = ThrottledSource::new(source, FeedbackReceiver::new(feedback_rx));
+
+        assert!(throttled.next().now_or_never().is_none());
+
+        feedback_tx.send(Feedback::new(vec!["A"])).unwrap();
+        let (key, value) = throttled.next().await.unwrap().unwrap();
+        assert_eq!((key, value), ("A", 1));
+        let (key, value) = throttled.next().await.unwrap().unwrap();
+        assert_eq!((key, value), ("A", 2));
+        assert!(throttled.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_throttled_source_passes_through_after_sync_dropped() {
+        let (feedback_tx, feedback_rx) = watch::channel(Feedback::new(vec![]));
+        let source = stream::iter(vec![Ok(("A", 1))]);
+        let mut throttled = ThrottledSource::new(source, FeedbackReceiver::new(feedback_rx));
+
+        assert!(throttled.next().now_or_never().is_none());
+
+        drop(feedback_tx);
+        let (key, value) = throttled.next().await.unwrap().unwrap();
+        assert_eq!((key, value), ("A", 1));
+    }
+}
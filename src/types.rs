@@ -1,8 +1,24 @@
+use crate::utils::duration_diff;
 use eyre::Result;
-use futures::stream::BoxStream;
+use futures::stream::{self, BoxStream, Stream, StreamExt};
 use indexmap::IndexMap;
-use std::{hash::Hash, time::Duration};
-use tokio::sync::watch;
+#[cfg(feature = "serde")]
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    any::Any,
+    fmt,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, SystemTime},
+};
+use tokio::{
+    sync::watch,
+    time::{error::Elapsed, timeout},
+};
 
 /// Creates a timestamp from the message passed to the synchronizer.
 pub trait WithTimestamp: Send {
@@ -15,27 +31,972 @@ pub trait WithTimestamp: Send {
     }
 }
 
+/// A message tagged with a timestamp externally.
+impl<T: Send> WithTimestamp for (Duration, T) {
+    fn timestamp(&self) -> Duration {
+        self.0
+    }
+}
+
+/// Delegates to the timestamp and the timeout of the inner message.
+impl<T: WithTimestamp + Sync> WithTimestamp for Arc<T> {
+    fn timestamp(&self) -> Duration {
+        T::timestamp(self)
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        T::timeout(self)
+    }
+}
+
+/// Delegates to the timestamp and the timeout of the inner message.
+impl<T: WithTimestamp + ?Sized> WithTimestamp for Box<T> {
+    fn timestamp(&self) -> Duration {
+        T::timestamp(self)
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        T::timeout(self)
+    }
+}
+
+/// Creates a timestamp in nanoseconds, the raw representation used by
+/// middlewares such as ROS. Implement [WithTimestamp] on top of it
+/// with [impl_with_timestamp_from_nanos](crate::impl_with_timestamp_from_nanos).
+pub trait NanosTimestamped: Send {
+    fn timestamp_ns(&self) -> u64;
+}
+
+/// Implements [WithTimestamp] for types implementing
+/// [NanosTimestamped]. A blanket implementation would conflict with
+/// the implementations for tuples, `Arc` and `Box`.
+///
+/// ```rust
+/// use multi_stream_synchronizer::{impl_with_timestamp_from_nanos, NanosTimestamped, WithTimestamp};
+/// use std::time::Duration;
+///
+/// struct Image {
+///     stamp_ns: u64,
+/// }
+///
+/// impl NanosTimestamped for Image {
+///     fn timestamp_ns(&self) -> u64 {
+///         self.stamp_ns
+///     }
+/// }
+///
+/// impl_with_timestamp_from_nanos!(Image);
+///
+/// let image = Image { stamp_ns: 1_500_000 };
+/// assert_eq!(image.timestamp(), Duration::from_micros(1500));
+/// ```
+#[macro_export]
+macro_rules! impl_with_timestamp_from_nanos {
+    ($($ty:ty),+ $(,)?) => {
+        $(
+            impl $crate::WithTimestamp for $ty {
+                fn timestamp(&self) -> ::std::time::Duration {
+                    ::std::time::Duration::from_nanos(
+                        $crate::NanosTimestamped::timestamp_ns(self),
+                    )
+                }
+            }
+        )+
+    };
+}
+
+/// Creates a timestamp as a system time, e.g. the modification time
+/// of a file. Implement [WithTimestamp] on top of it with
+/// [impl_with_timestamp_from_system_time](crate::impl_with_timestamp_from_system_time).
+///
+/// The timestamp is the duration since the Unix epoch computed by
+/// [duration_since_epoch](crate::duration_since_epoch), so times
+/// before the epoch are clamped to zero.
+pub trait SystemTimestamped: Send {
+    fn system_timestamp(&self) -> SystemTime;
+}
+
+/// Implements [WithTimestamp] for types implementing
+/// [SystemTimestamped], like
+/// [impl_with_timestamp_from_nanos](crate::impl_with_timestamp_from_nanos).
+///
+/// ```rust
+/// use multi_stream_synchronizer::{impl_with_timestamp_from_system_time, SystemTimestamped, WithTimestamp};
+/// use std::time::{Duration, SystemTime};
+///
+/// struct Recording {
+///     modified: SystemTime,
+/// }
+///
+/// impl SystemTimestamped for Recording {
+///     fn system_timestamp(&self) -> SystemTime {
+///         self.modified
+///     }
+/// }
+///
+/// impl_with_timestamp_from_system_time!(Recording);
+///
+/// let recording = Recording {
+///     modified: SystemTime::UNIX_EPOCH + Duration::from_secs(60),
+/// };
+/// assert_eq!(recording.timestamp(), Duration::from_secs(60));
+/// ```
+#[macro_export]
+macro_rules! impl_with_timestamp_from_system_time {
+    ($($ty:ty),+ $(,)?) => {
+        $(
+            impl $crate::WithTimestamp for $ty {
+                fn timestamp(&self) -> ::std::time::Duration {
+                    $crate::duration_since_epoch(
+                        $crate::SystemTimestamped::system_timestamp(self),
+                    )
+                }
+            }
+        )+
+    };
+}
+
+/// Adapts a message to [WithTimestamp] with a function extracting the
+/// timestamp, without declaring a newtype.
+///
+/// All messages fed to one synchronizer must share the function type,
+/// so pass the same closure or a function pointer to each adapter.
+#[derive(Clone)]
+pub struct TimestampedAdapter<T, F> {
+    inner: T,
+    extract: F,
+}
+
+impl<T, F> TimestampedAdapter<T, F>
+where
+    F: Fn(&T) -> Duration,
+{
+    pub fn new(inner: T, extract: F) -> Self {
+        Self { inner, extract }
+    }
+
+    /// Unwraps the adapted message.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T, F> WithTimestamp for TimestampedAdapter<T, F>
+where
+    T: Send,
+    F: Fn(&T) -> Duration + Send,
+{
+    fn timestamp(&self) -> Duration {
+        (self.extract)(&self.inner)
+    }
+}
+
+impl<T, F> Deref for TimestampedAdapter<T, F> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl<T: fmt::Debug, F> fmt::Debug for TimestampedAdapter<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("TimestampedAdapter")
+            .field(&self.inner)
+            .finish()
+    }
+}
+
 /// The key that identifies the queue in the synchronizer.
+///
+/// For string keys without heap allocation, prefer `&'static str`
+/// when keys are known at compile time. Otherwise, `SmolStr` stores
+/// short strings inline, and `SmolStrKey` from the `smolstr-key`
+/// feature additionally implements `Copy`.
 pub trait Key: Clone + PartialEq + Eq + Hash + Sync + Send {}
 
 impl<K> Key for K where K: Clone + PartialEq + Eq + Hash + Sync + Send {}
 
+/// A key tagged with the domain `D`, such that keys from different
+/// domains cannot be mixed up even if they hold the same type.
+///
+/// The domain is a marker type without runtime cost. Use
+/// [declare_key_domain](crate::declare_key_domain) to declare one.
+pub struct TypedKey<D, K>(K, PhantomData<fn() -> D>);
+
+impl<D, K> TypedKey<D, K> {
+    pub const fn new(key: K) -> Self {
+        Self(key, PhantomData)
+    }
+
+    pub fn get(&self) -> &K {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> K {
+        self.0
+    }
+}
+
+impl<D, K: Clone> Clone for TypedKey<D, K> {
+    fn clone(&self) -> Self {
+        Self::new(self.0.clone())
+    }
+}
+
+impl<D, K: Copy> Copy for TypedKey<D, K> {}
+
+impl<D, K: PartialEq> PartialEq for TypedKey<D, K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<D, K: Eq> Eq for TypedKey<D, K> {}
+
+impl<D, K: Hash> Hash for TypedKey<D, K> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state)
+    }
+}
+
+impl<D, K: fmt::Debug> fmt::Debug for TypedKey<D, K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<D, K> From<K> for TypedKey<D, K> {
+    fn from(key: K) -> Self {
+        Self::new(key)
+    }
+}
+
+/// Declares a key domain marker and a [TypedKey] alias in the domain.
+///
+/// ```rust
+/// use multi_stream_synchronizer::declare_key_domain;
+///
+/// declare_key_domain!(CameraDomain, CameraKey: u8);
+/// declare_key_domain!(LidarDomain, LidarKey: u8);
+///
+/// let camera = CameraKey::new(0);
+/// let lidar = LidarKey::new(0);
+/// // `camera == lidar` does not compile.
+/// ```
+#[macro_export]
+macro_rules! declare_key_domain {
+    ($vis:vis $domain:ident, $name:ident : $key:ty) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        $vis enum $domain {}
+
+        $vis type $name = $crate::TypedKey<$domain, $key>;
+    };
+}
+
 /// The feedback message generated from [sync](crate::sync()) to control
 /// the pace of input streams.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(serialize = "K: Serialize", deserialize = "K: DeserializeOwned"))
+)]
 pub struct Feedback<K>
 where
     K: Key,
 {
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::duration_millis::option")
+    )]
     pub accepted_max_timestamp: Option<Duration>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::duration_millis::option")
+    )]
     pub commit_timestamp: Option<Duration>,
     pub accepted_keys: Vec<K>,
+    /// The window size currently in use, which changes over time with
+    /// an adaptive window size policy. It is `None` in session window
+    /// mode.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::duration_millis::option")
+    )]
+    pub window_size: Option<Duration>,
+    /// The keys missing from the last emitted group, which are either
+    /// optional or paused.
+    pub absent_keys: Vec<K>,
+    /// The fraction of the buffer capacity in use for each key, from
+    /// 0.0 to 1.0. It is empty in session window mode.
+    pub buffer_usage: IndexMap<K, f64>,
+    /// How far the latest buffered timestamp of each key lags behind
+    /// the fastest stream, or `None` if the buffer is empty. See
+    /// [State::key_lag_map](crate::state::State::key_lag_map). It is
+    /// empty in session window mode.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::duration_millis::option_map")
+    )]
+    pub key_lag: IndexMap<K, Option<Duration>>,
+    /// The key of the slowest stream, which holds back the
+    /// synchronization.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub bottleneck_key: Option<K>,
+}
+
+impl<K> Feedback<K>
+where
+    K: Key,
+{
+    /// Create a feedback accepting messages from the keys, with no
+    /// timestamp bounds, window size or buffer statistics.
+    pub fn new(accepted_keys: Vec<K>) -> Self {
+        Self {
+            accepted_max_timestamp: None,
+            commit_timestamp: None,
+            accepted_keys,
+            window_size: None,
+            absent_keys: vec![],
+            buffer_usage: IndexMap::new(),
+            key_lag: IndexMap::new(),
+            bottleneck_key: None,
+        }
+    }
+
+    /// Checks if the synchronizer accepts messages from the stream
+    /// identified by the key.
+    pub fn is_key_accepted(&self, key: &K) -> bool {
+        self.accepted_keys.contains(key)
+    }
+}
+
+/// Breaks ties between candidate messages of a stream that the
+/// matching strategy ranks equally, e.g. two messages equally distant
+/// from the target timestamp. The tied message with the highest
+/// priority joins the group and earlier messages are dropped. Equal
+/// priorities are broken by preferring the earlier message.
+///
+/// Without a priority, [ClosestTimestamp] is used. See
+/// [State::set_priority](crate::state::State::set_priority).
+pub trait MessagePriority<K, T> {
+    fn priority(key: &K, msg: &T, anchor: Duration) -> i64;
+}
+
+/// Prefers the message closest to the window anchor. This is the
+/// default priority.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ClosestTimestamp;
+
+impl<K, T> MessagePriority<K, T> for ClosestTimestamp
+where
+    T: WithTimestamp,
+{
+    fn priority(_key: &K, msg: &T, anchor: Duration) -> i64 {
+        let distance = duration_diff(msg.timestamp(), anchor);
+        -i64::try_from(distance.as_nanos()).unwrap_or(i64::MAX)
+    }
+}
+
+/// Statistics collected while synchronizing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncStats<K>
+where
+    K: Key,
+{
+    /// The number of messages consumed from the input.
+    pub total_messages_received: u64,
+    /// The number of groups formed by the state. Groups skipped by
+    /// [dedup_output](crate::Config::dedup_output) are counted as well.
+    pub total_groups_emitted: u64,
+    /// The number of discarded messages for each key, either rejected
+    /// on arrival or dropped from the buffer without joining a group.
+    /// Keys without discarded messages are not listed.
+    pub drop_counts: IndexMap<K, u64>,
+}
+
+impl<K> SyncStats<K>
+where
+    K: Key,
+{
+    /// Gets the number of discarded messages across all keys.
+    pub fn total_drops(&self) -> u64 {
+        self.drop_counts.values().sum()
+    }
+
+    pub(crate) fn record_received(&mut self) {
+        self.total_messages_received += 1;
+        #[cfg(feature = "metrics")]
+        metrics::counter!("sync.messages_received").increment(1);
+    }
+
+    pub(crate) fn record_group(&mut self) {
+        self.total_groups_emitted += 1;
+        #[cfg(feature = "metrics")]
+        metrics::counter!("sync.groups_emitted").increment(1);
+    }
+
+    pub(crate) fn record_drops(&mut self, key: &K, count: usize) {
+        if count > 0 {
+            *self.drop_counts.entry(key.clone()).or_default() += count as u64;
+            #[cfg(feature = "metrics")]
+            metrics::counter!("sync.messages_dropped").increment(count as u64);
+        }
+    }
+}
+
+impl<K> Default for SyncStats<K>
+where
+    K: Key,
+{
+    fn default() -> Self {
+        Self {
+            total_messages_received: 0,
+            total_groups_emitted: 0,
+            drop_counts: IndexMap::new(),
+        }
+    }
+}
+
+/// Tells why a message is dropped. See [DroppedMessage].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropReason {
+    /// The message arrives at or before the commit timestamp, or
+    /// behind the latest message of the stream by more than the
+    /// out-of-order tolerance.
+    LateArrival,
+    /// The message is at or before the start time while no group is
+    /// committed yet.
+    BeforeStartTime,
+    /// The message is passed over by the time window without joining
+    /// a group.
+    OutOfWindow,
+    /// The buffer of the stream is full.
+    BufferFull,
+    /// The message stays buffered beyond its timeout or the staleness
+    /// timeout.
+    Expired,
+}
+
+/// A message dropped by the synchronizer, which is sent to the
+/// receiver returned by
+/// [sync_with_dead_letters](crate::sync_with_dead_letters()).
+#[derive(Debug, Clone)]
+pub struct DroppedMessage<K, T> {
+    pub key: K,
+    pub item: T,
+    pub drop_reason: DropReason,
+    /// The commit timestamp when the message is dropped.
+    pub dropped_at: Option<Duration>,
 }
 
 /// The stream is returned by [sync](crate::sync()), emitting batches of
 /// messages within a time window.
-pub type OutputStream<'a, K, T> = BoxStream<'a, Result<IndexMap<K, T>>>;
+#[must_use = "the output stream must be polled to drive synchronization; dropping it immediately is likely a bug"]
+pub struct OutputStream<'a, K, T> {
+    inner: BoxStream<'a, Result<IndexMap<K, T>>>,
+}
+
+impl<'a, K, T> OutputStream<'a, K, T> {
+    pub(crate) fn new(inner: BoxStream<'a, Result<IndexMap<K, T>>>) -> Self {
+        Self { inner }
+    }
+
+    /// Unwraps the underlying boxed stream.
+    pub fn into_inner(self) -> BoxStream<'a, Result<IndexMap<K, T>>> {
+        self.inner
+    }
+}
+
+impl<K, T> Stream for OutputStream<'_, K, T> {
+    type Item = Result<IndexMap<K, T>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.poll_next_unpin(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
 
 /// The stream is returned by [sync](crate::sync()) to control the pace
 /// of input stream.
-pub type FeedbackReceiver<K> = watch::Receiver<Feedback<K>>;
+///
+/// Waiting on [changed](watch::Receiver::changed) blocks forever if
+/// the synchronizer stalls without being dropped. Use
+/// [changed_or_timeout](FeedbackReceiver::changed_or_timeout) or
+/// [wait_for_key_accepted](FeedbackReceiver::wait_for_key_accepted)
+/// to wait in bounded time.
+#[must_use = "the feedback receiver controls the pace of input streams; dropping it immediately loses all backpressure"]
+#[derive(Debug, Clone)]
+pub struct FeedbackReceiver<K>
+where
+    K: Key,
+{
+    inner: watch::Receiver<Feedback<K>>,
+    key_rx: Option<IndexMap<K, watch::Receiver<bool>>>,
+}
+
+impl<K> FeedbackReceiver<K>
+where
+    K: Key,
+{
+    pub(crate) fn new(inner: watch::Receiver<Feedback<K>>) -> Self {
+        Self {
+            inner,
+            key_rx: None,
+        }
+    }
+
+    pub(crate) fn with_key_receivers(mut self, key_rx: IndexMap<K, watch::Receiver<bool>>) -> Self {
+        self.key_rx = Some(key_rx);
+        self
+    }
+
+    /// Gets the per-key feedback receivers if
+    /// [per_key_feedback](crate::Config::per_key_feedback) is
+    /// enabled. Each receiver holds `true` if the stream should keep
+    /// sending, or `false` if it should pause, so that a full buffer
+    /// of one stream does not throttle the others.
+    pub fn key_receivers(&self) -> Option<&IndexMap<K, watch::Receiver<bool>>> {
+        self.key_rx.as_ref()
+    }
+
+    /// Gets the per-key feedback receiver for the key. See
+    /// [key_receivers](FeedbackReceiver::key_receivers).
+    pub fn key_receiver(&self, key: &K) -> Option<watch::Receiver<bool>> {
+        self.key_rx.as_ref()?.get(key).cloned()
+    }
+
+    /// Unwraps the underlying watch receiver.
+    pub fn into_inner(self) -> watch::Receiver<Feedback<K>> {
+        self.inner
+    }
+
+    /// Converts the receiver into a [FeedbackStream] yielding the
+    /// current feedback and then each update.
+    pub fn into_stream(self) -> FeedbackStream<K>
+    where
+        K: 'static,
+    {
+        FeedbackStream::new(self.inner)
+    }
+
+    /// Waits for a new feedback message for at most `duration`. The
+    /// inner result is an error if the synchronizer is dropped.
+    pub async fn changed_or_timeout(
+        &mut self,
+        duration: Duration,
+    ) -> Result<Result<(), watch::error::RecvError>, Elapsed> {
+        timeout(duration, self.inner.changed()).await
+    }
+
+    /// Waits until the synchronizer accepts messages from the stream
+    /// identified by the key. Returns false if `duration` elapses or
+    /// the synchronizer is dropped before that.
+    pub async fn wait_for_key_accepted(&mut self, key: &K, duration: Duration) -> bool {
+        let wait = async {
+            loop {
+                if self.inner.borrow_and_update().is_key_accepted(key) {
+                    return true;
+                }
+                if self.inner.changed().await.is_err() {
+                    return false;
+                }
+            }
+        };
+        timeout(duration, wait).await.unwrap_or(false)
+    }
+}
+
+impl<K> Deref for FeedbackReceiver<K>
+where
+    K: Key,
+{
+    type Target = watch::Receiver<Feedback<K>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl<K> DerefMut for FeedbackReceiver<K>
+where
+    K: Key,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
+/// The stream returned by
+/// [into_stream](FeedbackReceiver::into_stream), yielding the current
+/// feedback and then each update until the synchronizer is dropped.
+///
+/// Updates sent in quick succession are coalesced, so that only the
+/// latest one is yielded.
+#[must_use = "streams do nothing unless polled"]
+pub struct FeedbackStream<K>
+where
+    K: Key,
+{
+    inner: BoxStream<'static, Feedback<K>>,
+}
+
+impl<K> FeedbackStream<K>
+where
+    K: Key + 'static,
+{
+    fn new(rx: watch::Receiver<Feedback<K>>) -> Self {
+        let inner = stream::unfold((rx, true), |(mut rx, first)| async move {
+            if !first && rx.changed().await.is_err() {
+                return None;
+            }
+            let feedback = rx.borrow_and_update().clone();
+            Some((feedback, (rx, false)))
+        })
+        .boxed();
+        Self { inner }
+    }
+}
+
+impl<K> Stream for FeedbackStream<K>
+where
+    K: Key,
+{
+    type Item = Feedback<K>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.poll_next_unpin(cx)
+    }
+}
+
+/// A group of messages, one from each stream, identified by keys.
+#[derive(Debug, Clone)]
+pub struct Group<K, T>
+where
+    K: Key,
+    T: WithTimestamp,
+{
+    pub items: IndexMap<K, T>,
+    pub annotation: GroupAnnotation,
+}
+
+/// A group emitted by
+/// [sync_with_envelope](crate::sync_with_envelope()) along with the
+/// time window used to form it.
+#[derive(Debug, Clone)]
+pub struct GroupEnvelope<K, T>
+where
+    K: Key,
+{
+    /// The number of groups emitted before this one.
+    pub group_index: u64,
+    /// The inclusive start of the time window.
+    pub window_start: Duration,
+    /// The inclusive end of the time window.
+    pub window_end: Duration,
+    /// The commit timestamp after the group is emitted, which is the
+    /// earliest timestamp in the group.
+    pub commit_ts: Duration,
+    /// Whether the group is formed by flushing the buffered messages
+    /// after [flush_timeout](crate::Config::flush_timeout) elapses.
+    pub flushed: bool,
+    pub messages: IndexMap<K, T>,
+}
+
+/// A type-erased message synchronized by
+/// [sync_any](crate::sync_any()), which lets streams carry different
+/// message types.
+///
+/// The timestamp and the timeout are taken from the wrapped message.
+/// The message is shared by an [Arc] since the synchronizer clones
+/// buffered messages.
+#[derive(Clone)]
+pub struct AnyMessage {
+    timestamp: Duration,
+    timeout: Option<Duration>,
+    value: Arc<dyn Any + Send + Sync>,
+}
+
+impl AnyMessage {
+    pub fn new<T>(item: T) -> Self
+    where
+        T: WithTimestamp + Sync + 'static,
+    {
+        Self {
+            timestamp: item.timestamp(),
+            timeout: item.timeout(),
+            value: Arc::new(item),
+        }
+    }
+
+    /// Gets the wrapped message if it has the type `T`.
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.value.downcast_ref()
+    }
+}
+
+impl WithTimestamp for AnyMessage {
+    fn timestamp(&self) -> Duration {
+        self.timestamp
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+}
+
+impl fmt::Debug for AnyMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AnyMessage")
+            .field("timestamp", &self.timestamp)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A group emitted by [sync_any](crate::sync_any()).
+#[derive(Debug, Clone)]
+pub struct GroupAny<K>
+where
+    K: Key,
+{
+    pub messages: IndexMap<K, AnyMessage>,
+}
+
+impl<K> GroupAny<K>
+where
+    K: Key,
+{
+    /// Gets the message identified by the key if it has the type `T`.
+    pub fn get_as<T: Any>(&self, key: &K) -> Option<&T> {
+        self.messages.get(key)?.downcast_ref()
+    }
+}
+
+/// Tells how a [Group] is produced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GroupAnnotation {
+    /// The group is emitted by the synchronizer.
+    #[default]
+    Real,
+    /// The group fills a missing window by repeating the messages of
+    /// the last real group. The messages keep their original
+    /// timestamps, while `timestamp` is the time slot being filled.
+    Synthetic { timestamp: Duration },
+}
+
+impl<K, T> Group<K, T>
+where
+    K: Key,
+    T: WithTimestamp,
+{
+    /// Gets the message from the stream identified by the key.
+    pub fn get(&self, key: &K) -> Option<&T> {
+        self.items.get(key)
+    }
+
+    /// Gets the number of messages in the group.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Checks if the group has no messages.
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Checks if the group is inserted to fill a missing window.
+    pub fn is_synthetic(&self) -> bool {
+        matches!(self.annotation, GroupAnnotation::Synthetic { .. })
+    }
+}
+
+impl<K, T> From<IndexMap<K, T>> for Group<K, T>
+where
+    K: Key,
+    T: WithTimestamp,
+{
+    fn from(items: IndexMap<K, T>) -> Self {
+        Self {
+            items,
+            annotation: GroupAnnotation::Real,
+        }
+    }
+}
+
+impl<K, T> IntoIterator for Group<K, T>
+where
+    K: Key,
+    T: WithTimestamp,
+{
+    type Item = (K, T);
+    type IntoIter = indexmap::map::IntoIter<K, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.into_iter()
+    }
+}
+
+impl<'a, K, T> IntoIterator for &'a Group<K, T>
+where
+    K: Key,
+    T: WithTimestamp,
+{
+    type Item = (&'a K, &'a T);
+    type IntoIter = indexmap::map::Iter<'a, K, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    declare_key_domain!(CameraDomain, CameraKey: u8);
+
+    #[test]
+    fn test_typed_key() {
+        fn assert_key<K: Key>(_key: &K) {}
+
+        let key = CameraKey::new(3);
+        assert_key(&key);
+        assert_eq!(key, CameraKey::from(3));
+        assert_ne!(key, CameraKey::new(4));
+        assert_eq!(*key.get(), 3);
+        assert_eq!(format!("{key:?}"), "3");
+    }
+
+    #[test]
+    fn test_with_timestamp_wrappers() {
+        struct Frame(Duration);
+
+        impl WithTimestamp for Frame {
+            fn timestamp(&self) -> Duration {
+                self.0
+            }
+
+            fn timeout(&self) -> Option<Duration> {
+                Some(Duration::from_secs(1))
+            }
+        }
+
+        let tagged = (Duration::from_millis(5), "payload");
+        assert_eq!(tagged.timestamp(), Duration::from_millis(5));
+        assert_eq!(tagged.timeout(), None);
+
+        let shared = Arc::new(Frame(Duration::from_millis(7)));
+        assert_eq!(shared.timestamp(), Duration::from_millis(7));
+        assert_eq!(shared.timeout(), Some(Duration::from_secs(1)));
+
+        let boxed: Box<dyn WithTimestamp> = Box::new(Frame(Duration::from_millis(9)));
+        assert_eq!(boxed.timestamp(), Duration::from_millis(9));
+        assert_eq!(boxed.timeout(), Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_nanos_timestamp_adapters() {
+        struct Scan {
+            stamp_ns: u64,
+        }
+
+        impl NanosTimestamped for Scan {
+            fn timestamp_ns(&self) -> u64 {
+                self.stamp_ns
+            }
+        }
+
+        impl_with_timestamp_from_nanos!(Scan);
+
+        let scan = Scan {
+            stamp_ns: 1_700_000_000_123_456_789,
+        };
+        assert_eq!(scan.timestamp(), Duration::new(1_700_000_000, 123_456_789));
+        assert_eq!(scan.timeout(), None);
+
+        let extract = |stamp_ms: &u64| Duration::from_millis(*stamp_ms);
+        let messages = [10, 20].map(|stamp_ms| TimestampedAdapter::new(stamp_ms, extract));
+        assert_eq!(messages[1].timestamp(), Duration::from_millis(20));
+        assert_eq!(*messages[0], 10);
+        assert_eq!(messages[0].clone().into_inner(), 10);
+        assert_eq!(format!("{:?}", messages[0]), "TimestampedAdapter(10)");
+    }
+
+    #[test]
+    fn test_system_timestamp_adapter() {
+        struct Recording {
+            modified: SystemTime,
+        }
+
+        impl SystemTimestamped for Recording {
+            fn system_timestamp(&self) -> SystemTime {
+                self.modified
+            }
+        }
+
+        impl_with_timestamp_from_system_time!(Recording);
+
+        let recording = Recording {
+            modified: SystemTime::UNIX_EPOCH + Duration::new(1_700_000_000, 5),
+        };
+        assert_eq!(recording.timestamp(), Duration::new(1_700_000_000, 5));
+
+        let before_epoch = Recording {
+            modified: SystemTime::UNIX_EPOCH - Duration::from_secs(1),
+        };
+        assert_eq!(before_epoch.timestamp(), Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_feedback_receiver_changed_or_timeout() {
+        let (feedback_tx, feedback_rx) = watch::channel(Feedback::new(vec![]));
+        let mut feedback_rx = FeedbackReceiver::new(feedback_rx);
+
+        let result = feedback_rx
+            .changed_or_timeout(Duration::from_millis(10))
+            .await;
+        assert!(result.is_err());
+
+        feedback_tx.send(Feedback::new(vec!["A"])).unwrap();
+        let result = feedback_rx
+            .changed_or_timeout(Duration::from_millis(10))
+            .await;
+        assert!(matches!(result, Ok(Ok(()))));
+    }
+
+    #[tokio::test]
+    async fn test_feedback_receiver_wait_for_key_accepted() {
+        let (feedback_tx, feedback_rx) = watch::channel(Feedback::new(vec!["A"]));
+        let mut feedback_rx = FeedbackReceiver::new(feedback_rx);
+        let timeout = Duration::from_millis(10);
+
+        assert!(feedback_rx.wait_for_key_accepted(&"A", timeout).await);
+        assert!(!feedback_rx.wait_for_key_accepted(&"B", timeout).await);
+
+        feedback_tx.send(Feedback::new(vec!["B"])).unwrap();
+        assert!(feedback_rx.wait_for_key_accepted(&"B", timeout).await);
+
+        drop(feedback_tx);
+        assert!(!feedback_rx.wait_for_key_accepted(&"A", timeout).await);
+    }
+
+    #[tokio::test]
+    async fn test_feedback_stream() {
+        let (feedback_tx, feedback_rx) = watch::channel(Feedback::new(vec![]));
+        let mut stream = FeedbackReceiver::new(feedback_rx).into_stream();
+
+        let first = stream.next().await.unwrap();
+        assert!(first.accepted_keys.is_empty());
+
+        feedback_tx.send(Feedback::new(vec!["A"])).unwrap();
+        let update = stream.next().await.unwrap();
+        assert_eq!(update.accepted_keys, vec!["A"]);
+
+        drop(feedback_tx);
+        assert!(stream.next().await.is_none());
+    }
+}
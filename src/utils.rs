@@ -1,3 +1,12 @@
+use crate::types::{Key, OutputStream, WithTimestamp};
+use futures::{future::BoxFuture, FutureExt, TryStreamExt};
+use indexmap::IndexMap;
+use std::{
+    collections::BTreeMap,
+    fmt,
+    time::{Duration, SystemTime},
+};
+
 // use crate::common::*;
 
 // pub fn duration_to_unix_timestamp(duration: Duration) -> NaiveDateTime {
@@ -6,3 +15,357 @@
 //     let nsecs = nanos % 1_000_000_000;
 //     NaiveDateTime::from_timestamp(secs as i64, nsecs as u32)
 // }
+
+/// Computes the time window `(start, end)` around the anchor
+/// timestamp as used by the synchronizer to match messages. The start
+/// saturates at zero.
+pub fn compute_window(anchor: Duration, window_size: Duration) -> (Duration, Duration) {
+    (
+        anchor.saturating_sub(window_size),
+        anchor.saturating_add(window_size),
+    )
+}
+
+/// Filters messages with timestamps within the window, where both
+/// ends are inclusive.
+pub fn messages_in_window<'a, T, I>(
+    messages: I,
+    window: (Duration, Duration),
+) -> impl Iterator<Item = &'a T>
+where
+    T: WithTimestamp + 'a,
+    I: IntoIterator<Item = &'a T>,
+{
+    let (start, end) = window;
+    messages.into_iter().filter(move |message| {
+        let ts = message.timestamp();
+        start <= ts && ts <= end
+    })
+}
+
+/// Converts nanoseconds to a duration. It saturates at
+/// [Duration::MAX] instead of panicking on overflow.
+pub fn duration_safe_from_nanos(nanos: u128) -> Duration {
+    const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+    match u64::try_from(nanos / NANOS_PER_SEC) {
+        Ok(secs) => Duration::new(secs, (nanos % NANOS_PER_SEC) as u32),
+        Err(_) => Duration::MAX,
+    }
+}
+
+/// Converts a system time to the duration since the Unix epoch, the
+/// timestamp representation used by the synchronizer.
+///
+/// Times before the epoch are clamped to zero, so that all of them
+/// collapse into one timestamp. Shift such timestamps with
+/// [timestamp_offsets](crate::KeyOptions::timestamp_offsets) or convert
+/// them manually instead.
+pub fn duration_since_epoch(time: SystemTime) -> Duration {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+}
+
+/// Computes the absolute difference between two durations. It never
+/// overflows.
+pub fn duration_diff(lhs: Duration, rhs: Duration) -> Duration {
+    lhs.abs_diff(rhs)
+}
+
+/// Linearly interpolates from `a` to `b`, where `t` of 0.0 gives `a`
+/// and 1.0 gives `b`. The result saturates at zero and
+/// [Duration::MAX].
+pub fn duration_lerp(a: Duration, b: Duration, t: f64) -> Duration {
+    let secs = a.as_secs_f64() + (b.as_secs_f64() - a.as_secs_f64()) * t;
+    Duration::try_from_secs_f64(secs.max(0.0)).unwrap_or(Duration::MAX)
+}
+
+/// Restricts the duration to the range from `min` to `max`.
+///
+/// # Panics
+/// Panics if `min > max`.
+pub fn duration_clamp(val: Duration, min: Duration, max: Duration) -> Duration {
+    val.clamp(min, max)
+}
+
+/// Checks that all messages in the group fit within
+/// `[anchor - window_size, anchor + window_size]` for some anchor,
+/// i.e. the timestamps span at most twice the window size.
+///
+/// It can be used as a runtime sanity check on groups emitted by
+/// [sync](crate::sync()). Groups formed with anchor weights or
+/// timestamp offsets are not guaranteed to pass.
+pub fn validate_group<K, T>(
+    group: &IndexMap<K, T>,
+    window_size: Duration,
+) -> Result<(), GroupValidationError>
+where
+    T: WithTimestamp,
+{
+    let Some(start) = group.values().map(|item| item.timestamp()).min() else {
+        return Ok(());
+    };
+    let (_, end) = compute_window(start.saturating_add(window_size), window_size);
+
+    let (key_index, timestamp) = group
+        .values()
+        .map(|item| item.timestamp())
+        .enumerate()
+        .max_by_key(|&(_, timestamp)| timestamp)
+        .unwrap();
+    if timestamp > end {
+        return Err(GroupValidationError {
+            key_index,
+            timestamp,
+            excess: timestamp - end,
+        });
+    }
+
+    Ok(())
+}
+
+/// The error returned by [validate_group].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupValidationError {
+    /// The index of the key whose message is out of range.
+    pub key_index: usize,
+    /// The timestamp of the message.
+    pub timestamp: Duration,
+    /// How far the timestamp is beyond the window.
+    pub excess: Duration,
+}
+
+impl fmt::Display for GroupValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self {
+            key_index,
+            timestamp,
+            excess,
+        } = self;
+        write!(
+            f,
+            "the message of key {key_index} at {timestamp:?} is {excess:?} beyond the window"
+        )
+    }
+}
+
+impl std::error::Error for GroupValidationError {}
+
+/// Groups indexed by the anchor timestamp, which is the minimum
+/// timestamp of each group.
+pub type Timeline<K, T> = BTreeMap<Duration, IndexMap<K, T>>;
+
+/// Collects all groups from the stream into a [Timeline] for range
+/// queries by time. Empty groups are skipped, and a group replaces
+/// the previous one with the same anchor timestamp.
+pub async fn collect_timeline<K, T>(stream: OutputStream<'_, K, T>) -> eyre::Result<Timeline<K, T>>
+where
+    K: Key,
+    T: WithTimestamp,
+{
+    stream
+        .try_fold(Timeline::new(), |mut timeline, group| async move {
+            if let Some(anchor) = group.values().map(|item| item.timestamp()).min() {
+                timeline.insert(anchor, group);
+            }
+            Ok(timeline)
+        })
+        .await
+}
+
+/// Converts the timeline to groups ordered by the anchor timestamp.
+pub fn timeline_to_vec<K, T>(timeline: Timeline<K, T>) -> Vec<IndexMap<K, T>> {
+    timeline.into_values().collect()
+}
+
+/// Collects the groups of an [OutputStream] into a [Timeline].
+pub trait TimelineExt<'a, K, T> {
+    /// See [collect_timeline].
+    fn into_timeline(self) -> BoxFuture<'a, eyre::Result<Timeline<K, T>>>;
+}
+
+impl<'a, K, T> TimelineExt<'a, K, T> for OutputStream<'a, K, T>
+where
+    K: Key + 'a,
+    T: WithTimestamp + 'a,
+{
+    fn into_timeline(self) -> BoxFuture<'a, eyre::Result<Timeline<K, T>>> {
+        collect_timeline(self).boxed()
+    }
+}
+
+/// Wraps the items of a [StreamMap](tokio_stream::StreamMap) in `Ok`
+/// to form the input of [sync](crate::sync()).
+#[cfg(feature = "tokio-stream")]
+pub fn from_stream_map<K, S>(
+    map: tokio_stream::StreamMap<K, S>,
+) -> impl futures::Stream<Item = eyre::Result<(K, S::Item)>> + Unpin + Send
+where
+    K: Clone + Unpin + Send,
+    S: futures::Stream + Unpin + Send,
+{
+    use futures::StreamExt;
+
+    map.map(Ok)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestMessage(Duration);
+
+    impl WithTimestamp for TestMessage {
+        fn timestamp(&self) -> Duration {
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_compute_window() {
+        let window = compute_window(Duration::from_millis(1000), Duration::from_millis(100));
+        assert_eq!(
+            window,
+            (Duration::from_millis(900), Duration::from_millis(1100))
+        );
+
+        let window = compute_window(Duration::from_millis(10), Duration::from_millis(100));
+        assert_eq!(window, (Duration::ZERO, Duration::from_millis(110)));
+    }
+
+    #[test]
+    fn test_messages_in_window() {
+        let messages: Vec<_> = [850, 900, 1000, 1100, 1150]
+            .into_iter()
+            .map(|ms| TestMessage(Duration::from_millis(ms)))
+            .collect();
+        let window = compute_window(Duration::from_millis(1000), Duration::from_millis(100));
+
+        let selected: Vec<_> = messages_in_window(&messages, window)
+            .map(|message| message.0.as_millis())
+            .collect();
+        assert_eq!(selected, [900, 1000, 1100]);
+    }
+
+    #[test]
+    fn test_duration_diff() {
+        let a = Duration::from_millis(1000);
+        let b = Duration::from_millis(1200);
+        assert_eq!(duration_diff(a, b), Duration::from_millis(200));
+        assert_eq!(duration_diff(b, a), Duration::from_millis(200));
+        assert_eq!(duration_diff(a, a), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_duration_since_epoch() {
+        let time = SystemTime::UNIX_EPOCH + Duration::from_millis(1500);
+        assert_eq!(duration_since_epoch(time), Duration::from_millis(1500));
+
+        let before_epoch = SystemTime::UNIX_EPOCH - Duration::from_secs(1);
+        assert_eq!(duration_since_epoch(before_epoch), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_duration_safe_from_nanos() {
+        assert_eq!(
+            duration_safe_from_nanos(1_500_000_000),
+            Duration::from_millis(1500)
+        );
+        assert_eq!(
+            duration_safe_from_nanos(Duration::MAX.as_nanos()),
+            Duration::MAX
+        );
+        assert_eq!(duration_safe_from_nanos(u128::MAX), Duration::MAX);
+        assert_eq!(
+            duration_lerp(Duration::ZERO, Duration::MAX, 2.0),
+            Duration::MAX
+        );
+    }
+
+    // The system clock on Windows may be set far from the Unix epoch.
+    #[cfg(target_os = "windows")]
+    #[test]
+    fn test_large_system_timestamps() {
+        use std::time::UNIX_EPOCH;
+
+        // Year 2600 is beyond the range of u64 nanoseconds.
+        let year_2600 = Duration::from_secs(19_880_899_200);
+        let time = UNIX_EPOCH + year_2600;
+        let since_epoch = time.duration_since(UNIX_EPOCH).unwrap();
+        assert!(since_epoch.as_nanos() > u64::MAX as u128);
+        assert_eq!(duration_safe_from_nanos(since_epoch.as_nanos()), year_2600);
+
+        let later = year_2600 + Duration::from_millis(10);
+        assert_eq!(duration_diff(year_2600, later), Duration::from_millis(10));
+        assert_eq!(duration_diff(Duration::MAX, Duration::ZERO), Duration::MAX);
+    }
+
+    #[test]
+    fn test_duration_lerp_and_clamp() {
+        let a = Duration::from_millis(1000);
+        let b = Duration::from_millis(2000);
+        assert_eq!(duration_lerp(a, b, 0.0), a);
+        assert_eq!(duration_lerp(a, b, 1.0), b);
+        assert_eq!(duration_lerp(a, b, 0.5), Duration::from_millis(1500));
+        assert_eq!(duration_lerp(a, b, -2.0), Duration::ZERO);
+
+        assert_eq!(duration_clamp(Duration::from_millis(500), a, b), a);
+        assert_eq!(duration_clamp(Duration::from_millis(2500), a, b), b);
+        assert_eq!(
+            duration_clamp(Duration::from_millis(1500), a, b),
+            Duration::from_millis(1500)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_collect_timeline() {
+        use futures::{stream, StreamExt};
+
+        let output = || {
+            let groups = [1200, 1000, 1100].map(|ms| {
+                let group = IndexMap::from([
+                    ("A", TestMessage(Duration::from_millis(ms))),
+                    ("B", TestMessage(Duration::from_millis(ms + 5))),
+                ]);
+                eyre::Ok(group)
+            });
+            OutputStream::new(stream::iter(groups).boxed())
+        };
+
+        let timeline = collect_timeline(output()).await.unwrap();
+        let anchors: Vec<_> = timeline.keys().map(|ts| ts.as_millis()).collect();
+        assert_eq!(anchors, [1000, 1100, 1200]);
+
+        let range: Vec<_> = timeline
+            .range(Duration::from_millis(1050)..)
+            .map(|(ts, _)| ts.as_millis())
+            .collect();
+        assert_eq!(range, [1100, 1200]);
+
+        let groups = timeline_to_vec(output().into_timeline().await.unwrap());
+        assert_eq!(groups[0]["B"].0, Duration::from_millis(1005));
+    }
+
+    #[test]
+    fn test_validate_group() {
+        let group = |timestamps: &[u64]| -> IndexMap<usize, TestMessage> {
+            timestamps
+                .iter()
+                .map(|&ms| TestMessage(Duration::from_millis(ms)))
+                .enumerate()
+                .collect()
+        };
+        let window_size = Duration::from_millis(50);
+
+        assert!(validate_group(&group(&[]), window_size).is_ok());
+        assert!(validate_group(&group(&[1000, 1100, 1040]), window_size).is_ok());
+        assert_eq!(
+            validate_group(&group(&[1000, 1130, 1040]), window_size),
+            Err(GroupValidationError {
+                key_index: 1,
+                timestamp: Duration::from_millis(1130),
+                excess: Duration::from_millis(30),
+            })
+        );
+    }
+}
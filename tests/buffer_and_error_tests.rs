@@ -45,10 +45,9 @@ async fn test_window_based_dropping() {
     let config = config_with_window(200); // 200ms window
     let groups = run_sync(stream, ["A", "B"], config).await.unwrap();
 
-    // Algorithm creates 3 groups, with the later messages (1900ms, 1950ms)
-    // pairing together instead of the earlier pair (1800ms, 1850ms)
-    // This demonstrates the synchronizer's matching strategy
-    assert_eq!(groups.len(), 3);
+    // Algorithm creates 4 groups. The trailing messages are flushed
+    // once the input ends instead of being dropped.
+    assert_eq!(groups.len(), 4);
 
     // Verify specific group contents
     assert_eq!(groups[0]["A"].timestamp(), Duration::from_millis(1000));
@@ -57,10 +56,11 @@ async fn test_window_based_dropping() {
     assert_eq!(groups[1]["A"].timestamp(), Duration::from_millis(1100));
     assert_eq!(groups[1]["B"].timestamp(), Duration::from_millis(1150));
 
-    // The algorithm chooses the later pair (1900ms, 1950ms) over (1800ms, 1850ms)
-    // This shows the synchronizer's preference for more recent matches
-    assert_eq!(groups[2]["A"].timestamp(), Duration::from_millis(1900));
-    assert_eq!(groups[2]["B"].timestamp(), Duration::from_millis(1950));
+    assert_eq!(groups[2]["A"].timestamp(), Duration::from_millis(1800));
+    assert_eq!(groups[2]["B"].timestamp(), Duration::from_millis(1850));
+
+    assert_eq!(groups[3]["A"].timestamp(), Duration::from_millis(1900));
+    assert_eq!(groups[3]["B"].timestamp(), Duration::from_millis(1950));
 
     assert_groups_valid(&groups, Duration::from_millis(200));
     assert_timestamp_ordering(&groups);
@@ -203,12 +203,11 @@ async fn test_late_message_handling() {
         .add_message("B", 2100)
         .build();
 
-    let config = Config {
-        window_size: Duration::from_millis(100),
-        start_time: Some(Duration::from_millis(1500)), // Start after the "late" message
-        buf_size: 16,
-        staleness_config: None,
-    };
+    let config = Config::basic(
+        Duration::from_millis(100),
+        Some(Duration::from_millis(1500)), // Start after the "late" message
+        16,
+    );
 
     let groups = run_sync(stream, ["A", "B"], config).await.unwrap();
 
@@ -239,12 +238,11 @@ async fn test_rapid_state_changes() {
         .add_message("C", 1080)
         .build();
 
-    let config = Config {
-        window_size: Duration::from_millis(50),
-        start_time: None,
-        buf_size: 2, // Small buffer to force rapid state changes
-        staleness_config: None,
-    };
+    let config = Config::basic(
+        Duration::from_millis(50),
+        None,
+        2, // Small buffer to force rapid state changes
+    );
 
     let groups = run_sync(stream, ["A", "B", "C"], config).await.unwrap();
 
@@ -0,0 +1,56 @@
+use futures::{stream, TryStreamExt};
+use multi_stream_synchronizer::{sync, Config, WithTimestamp};
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, WithTimestamp)]
+struct MarkedMessage {
+    #[timestamp]
+    ts: std::time::Duration,
+    data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, WithTimestamp)]
+struct NamedMessage<T: Send> {
+    timestamp: Duration,
+    payload: T,
+}
+
+#[test]
+fn test_derive_marked_field() {
+    let msg = MarkedMessage {
+        ts: Duration::from_millis(10),
+        data: vec![1, 2, 3],
+    };
+    assert_eq!(msg.timestamp(), Duration::from_millis(10));
+    assert_eq!(msg.timeout(), None);
+}
+
+#[test]
+fn test_derive_field_named_timestamp() {
+    let msg = NamedMessage {
+        timestamp: Duration::from_millis(20),
+        payload: "lidar",
+    };
+    assert_eq!(msg.timestamp(), Duration::from_millis(20));
+}
+
+#[tokio::test]
+async fn test_derive_with_sync() {
+    let message = |ms| NamedMessage {
+        timestamp: Duration::from_millis(ms),
+        payload: ms,
+    };
+    let input = stream::iter(vec![
+        Ok(("A", message(1000))),
+        Ok(("B", message(1010))),
+        Ok(("A", message(1100))),
+        Ok(("B", message(1110))),
+    ]);
+
+    let config = Config::basic(Duration::from_millis(50), None, 4);
+    let (output, _feedback_rx) = sync(input, ["A", "B"], config).unwrap();
+    let groups: Vec<_> = output.try_collect().await.unwrap();
+
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups[0]["B"], message(1010));
+}
@@ -0,0 +1,75 @@
+use futures::{StreamExt, TryStreamExt};
+use indexmap::IndexMap;
+use multi_stream_synchronizer::{from_stream_map, sync, Config, WithTimestamp};
+use std::time::Duration;
+use tokio_stream::StreamMap;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TestMessage {
+    timestamp: Duration,
+}
+
+impl TestMessage {
+    fn new(timestamp_ms: u64) -> Self {
+        Self {
+            timestamp: Duration::from_millis(timestamp_ms),
+        }
+    }
+}
+
+impl WithTimestamp for TestMessage {
+    fn timestamp(&self) -> Duration {
+        self.timestamp
+    }
+}
+
+fn stream_map() -> StreamMap<&'static str, tokio_stream::Iter<std::vec::IntoIter<TestMessage>>> {
+    let mut map = StreamMap::new();
+    map.insert(
+        "A",
+        tokio_stream::iter(vec![
+            TestMessage::new(1000),
+            TestMessage::new(1100),
+            TestMessage::new(1200),
+        ]),
+    );
+    map.insert(
+        "B",
+        tokio_stream::iter(vec![
+            TestMessage::new(1005),
+            TestMessage::new(1105),
+            TestMessage::new(1205),
+        ]),
+    );
+    map
+}
+
+fn assert_paired(groups: &[IndexMap<&str, TestMessage>]) {
+    assert_eq!(groups.len(), 3);
+    for (group, ts) in groups.iter().zip([1000, 1100, 1200]) {
+        assert_eq!(group["A"], TestMessage::new(ts));
+        assert_eq!(group["B"], TestMessage::new(ts + 5));
+    }
+}
+
+#[tokio::test]
+async fn test_stream_map_wrapped_in_ok() {
+    let input = stream_map().map(eyre::Ok);
+    let config = Config::basic(Duration::from_millis(50), None, 4);
+
+    let (output, _feedback_rx) = sync(input, ["A", "B"], config).unwrap();
+    let groups: Vec<_> = output.try_collect().await.unwrap();
+
+    assert_paired(&groups);
+}
+
+#[tokio::test]
+async fn test_from_stream_map() {
+    let input = from_stream_map(stream_map());
+    let config = Config::basic(Duration::from_millis(50), None, 4);
+
+    let (output, _feedback_rx) = sync(input, ["A", "B"], config).unwrap();
+    let groups: Vec<_> = output.try_collect().await.unwrap();
+
+    assert_paired(&groups);
+}
@@ -0,0 +1,138 @@
+use multi_stream_synchronizer::{
+    Config, DropPolicy, Feedback, IndexMap, MatchingStrategy, StalenessConfig, WindowMode,
+    WindowSizePolicy,
+};
+use std::time::Duration;
+
+fn round_trip(config: &Config) -> Config {
+    let json = serde_json::to_string(config).unwrap();
+    serde_json::from_str(&json).unwrap()
+}
+
+fn assert_config_eq(lhs: &Config, rhs: &Config) {
+    assert_eq!(lhs.window_size, rhs.window_size);
+    assert_eq!(lhs.start_time, rhs.start_time);
+    assert_eq!(lhs.buf_size, rhs.buf_size);
+    assert_eq!(
+        lhs.staleness_config.as_ref().map(|c| (
+            c.heap_max_size,
+            c.heap_time_horizon,
+            c.precision_gap,
+            c.timer_wheel_slots,
+            c.timer_wheel_slot_duration,
+            c.enable_immediate_expiration
+        )),
+        rhs.staleness_config.as_ref().map(|c| (
+            c.heap_max_size,
+            c.heap_time_horizon,
+            c.precision_gap,
+            c.timer_wheel_slots,
+            c.timer_wheel_slot_duration,
+            c.enable_immediate_expiration
+        ))
+    );
+    assert_eq!(lhs.window_mode, rhs.window_mode);
+    assert_eq!(lhs.window_size_policy, rhs.window_size_policy);
+    assert_eq!(lhs.max_total_pending, rhs.max_total_pending);
+    assert_eq!(lhs.minimum_streams_required, rhs.minimum_streams_required);
+    assert_eq!(lhs.dedup_output, rhs.dedup_output);
+    assert_eq!(lhs.dedup_history, rhs.dedup_history);
+    assert_eq!(lhs.per_key_feedback, rhs.per_key_feedback);
+    assert_eq!(lhs.jitter_tolerance, rhs.jitter_tolerance);
+    assert_eq!(lhs.matching_strategy, rhs.matching_strategy);
+    assert_eq!(lhs.flush_timeout, rhs.flush_timeout);
+    assert_eq!(lhs.allow_equal_timestamps, rhs.allow_equal_timestamps);
+    assert_eq!(lhs.drop_policy, rhs.drop_policy);
+    assert_eq!(lhs.out_of_order_tolerance, rhs.out_of_order_tolerance);
+}
+
+#[test]
+fn test_config_round_trip_without_start_time() {
+    let config: Config = Config::basic(Duration::from_millis(50), None, 16);
+    let restored = round_trip(&config);
+    assert_eq!(restored.start_time, None);
+    assert_config_eq(&config, &restored);
+}
+
+#[test]
+fn test_config_round_trip_with_all_fields() {
+    let mut config: Config = Config::with_staleness(
+        Duration::from_micros(33_500),
+        Some(Duration::from_secs(1)),
+        8,
+        StalenessConfig::default(),
+    )
+    .with_jitter_tolerance(Duration::from_millis(2))
+    .with_matching_strategy(MatchingStrategy::ClosestToCenter)
+    .with_flush_timeout(Duration::from_millis(250))
+    .with_drop_policy(DropPolicy::DropNewest)
+    .with_out_of_order_tolerance(Duration::from_millis(3));
+    config.window_size_policy = WindowSizePolicy::Adaptive {
+        min: Duration::from_millis(10),
+        max: Duration::from_millis(100),
+        percentile: 0.9,
+    };
+    config.max_total_pending = Some(64);
+    config.dedup_output = true;
+    config.per_key_feedback = true;
+
+    let restored = round_trip(&config);
+    assert_eq!(restored.start_time, Some(Duration::from_secs(1)));
+    assert_config_eq(&config, &restored);
+}
+
+#[test]
+fn test_config_round_trip_session_mode() {
+    let mut config: Config = Config::basic(Duration::from_millis(50), None, 16);
+    config.window_mode = WindowMode::Session {
+        gap: Duration::from_millis(20),
+    };
+    assert_config_eq(&config, &round_trip(&config));
+}
+
+#[test]
+fn test_config_durations_as_millis() {
+    let config: Config =
+        Config::basic(Duration::from_micros(1500), Some(Duration::from_secs(2)), 4);
+    let value = serde_json::to_value(&config).unwrap();
+    assert_eq!(value["window_size"], 1.5);
+    assert_eq!(value["start_time"], 2000.0);
+}
+
+#[test]
+fn test_config_rejects_negative_duration() {
+    let config: Config = Config::basic(Duration::from_millis(50), None, 16);
+    let mut value = serde_json::to_value(&config).unwrap();
+    value["window_size"] = (-1.0).into();
+    assert!(serde_json::from_value::<Config>(value).is_err());
+}
+
+#[test]
+fn test_feedback_round_trip() {
+    let feedback = Feedback {
+        accepted_max_timestamp: Some(Duration::from_millis(120)),
+        commit_timestamp: None,
+        accepted_keys: vec!["camera".to_string()],
+        window_size: Some(Duration::from_millis(50)),
+        absent_keys: vec![],
+        buffer_usage: IndexMap::from([("camera".to_string(), 0.25)]),
+        key_lag: IndexMap::from([
+            ("camera".to_string(), Some(Duration::from_micros(1500))),
+            ("lidar".to_string(), None),
+        ]),
+        bottleneck_key: Some("camera".to_string()),
+    };
+    let json = serde_json::to_string(&feedback).unwrap();
+    let restored: Feedback<String> = serde_json::from_str(&json).unwrap();
+    assert_eq!(
+        restored.accepted_max_timestamp,
+        feedback.accepted_max_timestamp
+    );
+    assert_eq!(restored.commit_timestamp, None);
+    assert_eq!(restored.accepted_keys, feedback.accepted_keys);
+    assert_eq!(restored.window_size, feedback.window_size);
+    assert_eq!(restored.absent_keys, feedback.absent_keys);
+    assert_eq!(restored.buffer_usage, feedback.buffer_usage);
+    assert_eq!(restored.key_lag, feedback.key_lag);
+    assert_eq!(restored.bottleneck_key, feedback.bottleneck_key);
+}
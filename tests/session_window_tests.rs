@@ -0,0 +1,45 @@
+mod common;
+
+use common::*;
+use multi_stream_synchronizer::WindowMode;
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_session_window_groups_by_activity() {
+    let stream = StreamBuilder::new()
+        .add_message("A", 1000)
+        .add_message("B", 1010)
+        .add_message("A", 1020)
+        .add_message("A", 2000)
+        .add_message("B", 2010)
+        .add_message("A", 2030)
+        .build();
+
+    let config = config_with_window(100).with_window_mode(WindowMode::Session {
+        gap: Duration::from_millis(200),
+    });
+    let groups = run_sync(stream, ["A", "B"], config).await.unwrap();
+
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups[0]["A"].timestamp, Duration::from_millis(1020));
+    assert_eq!(groups[0]["B"].timestamp, Duration::from_millis(1010));
+    assert_eq!(groups[1]["A"].timestamp, Duration::from_millis(2030));
+    assert_eq!(groups[1]["B"].timestamp, Duration::from_millis(2010));
+
+    assert_groups_valid(&groups, Duration::from_millis(100));
+    assert_timestamp_ordering(&groups);
+}
+
+#[tokio::test]
+async fn test_session_window_zero_gap_rejected() {
+    let stream = StreamBuilder::new()
+        .add_messages("A", &[1000, 1010])
+        .build();
+
+    let config = config_with_window(100).with_window_mode(WindowMode::Session {
+        gap: Duration::ZERO,
+    });
+    let result = run_sync(stream, ["A", "B"], config).await;
+
+    assert!(result.is_err());
+}
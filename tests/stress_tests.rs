@@ -129,12 +129,7 @@ async fn test_large_buffer_capacity() {
         .add_messages("B", &stream_b)
         .build();
 
-    let config = Config {
-        window_size: Duration::from_millis(100),
-        start_time: None,
-        buf_size: buffer_size,
-        staleness_config: None,
-    };
+    let config = Config::basic(Duration::from_millis(100), None, buffer_size);
 
     let start_time = Instant::now();
     let groups = run_sync(stream, ["A", "B"], config).await.unwrap();
@@ -235,12 +230,11 @@ async fn test_rapid_state_changes() {
         .add_messages("C", &timestamps_c)
         .build();
 
-    let config = Config {
-        window_size: Duration::from_millis(50),
-        start_time: None,
-        buf_size: 8, // Small buffer to force rapid state changes
-        staleness_config: None,
-    };
+    let config = Config::basic(
+        Duration::from_millis(50),
+        None,
+        8, // Small buffer to force rapid state changes
+    );
 
     let start_time = Instant::now();
     let groups = run_sync(stream, ["A", "B", "C"], config).await.unwrap();
@@ -284,12 +278,11 @@ async fn test_memory_efficiency() {
         }
         let stream = builder.build();
 
-        let config = Config {
-            window_size: Duration::from_millis(100),
-            start_time: None,
-            buf_size: 100, // Reasonable buffer size
-            staleness_config: None,
-        };
+        let config = Config::basic(
+            Duration::from_millis(100),
+            None,
+            100, // Reasonable buffer size
+        );
 
         let start_time = Instant::now();
         let groups = run_sync(stream, key_slice.to_vec(), config).await.unwrap();
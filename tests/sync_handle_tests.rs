@@ -0,0 +1,153 @@
+use futures::{stream, TryStreamExt};
+use indexmap::IndexMap;
+use multi_stream_synchronizer::{sync_with_handle, Config, WithTimestamp};
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TestMessage {
+    timestamp: Duration,
+}
+
+impl TestMessage {
+    fn new(timestamp_ms: u64) -> Self {
+        Self {
+            timestamp: Duration::from_millis(timestamp_ms),
+        }
+    }
+}
+
+impl WithTimestamp for TestMessage {
+    fn timestamp(&self) -> Duration {
+        self.timestamp
+    }
+}
+
+#[tokio::test]
+async fn test_sync_handle_pause_key() {
+    let messages = [1000, 1100, 1200, 1300].map(|ts| Ok(("A", TestMessage::new(ts))));
+
+    let config = Config::basic(Duration::from_millis(50), None, 16);
+    let (output_stream, _feedback_rx, handle) =
+        sync_with_handle(stream::iter(messages), ["A", "B"], config).unwrap();
+
+    // Stream B never sends any message. Pause it so that stream A
+    // can proceed alone.
+    handle.pause_key("B").unwrap();
+
+    let groups: Vec<IndexMap<&str, TestMessage>> = output_stream.try_collect().await.unwrap();
+    assert_eq!(groups.len(), 4);
+    assert!(groups
+        .iter()
+        .all(|group| group.len() == 1 && group.contains_key("A")));
+}
+
+#[tokio::test]
+async fn test_sync_handle_fast_forward() {
+    let messages = [
+        ("A", 1000),
+        ("B", 1010),
+        ("A", 1100),
+        ("B", 1110),
+        ("A", 1200),
+        ("B", 1210),
+        ("A", 1300),
+        ("B", 1310),
+    ]
+    .map(|(key, ts)| Ok((key, TestMessage::new(ts))));
+
+    let config = Config::basic(Duration::from_millis(50), None, 16);
+    let (output_stream, _feedback_rx, handle) =
+        sync_with_handle(stream::iter(messages), ["A", "B"], config).unwrap();
+
+    handle.fast_forward(Duration::from_millis(1150)).unwrap();
+
+    let groups: Vec<IndexMap<&str, TestMessage>> = output_stream.try_collect().await.unwrap();
+    assert!(!groups.is_empty());
+    assert!(groups
+        .iter()
+        .flat_map(|group| group.values())
+        .all(|msg| msg.timestamp >= Duration::from_millis(1150)));
+}
+
+#[tokio::test]
+async fn test_sync_handle_dropped_synchronizer() {
+    let messages = stream::empty::<eyre::Result<(&str, TestMessage)>>();
+    let config = Config::basic(Duration::from_millis(50), None, 16);
+    let (output_stream, _feedback_rx, handle) =
+        sync_with_handle(messages, ["A", "B"], config).unwrap();
+
+    drop(output_stream);
+    assert!(handle.pause_key("A").is_err());
+}
+
+#[tokio::test]
+async fn test_sync_handle_stats() {
+    let messages = [
+        ("A", 1000),
+        ("B", 1010),
+        ("A", 1100),
+        ("B", 1110),
+        ("A", 1200),
+        ("B", 1210),
+        // Late message after the groups above are committed.
+        ("B", 1005),
+    ]
+    .map(|(key, ts)| Ok((key, TestMessage::new(ts))));
+
+    let config = Config::basic(Duration::from_millis(50), None, 16);
+    let (output_stream, _feedback_rx, handle) =
+        sync_with_handle(stream::iter(messages), ["A", "B"], config).unwrap();
+    assert_eq!(handle.stats().total_messages_received, 0);
+
+    let groups: Vec<IndexMap<&str, TestMessage>> = output_stream.try_collect().await.unwrap();
+
+    let stats = handle.stats();
+    assert_eq!(stats.total_messages_received, 7);
+    assert_eq!(stats.total_groups_emitted, groups.len() as u64);
+    assert_eq!(stats.drop_counts.get("B"), Some(&1));
+}
+
+#[tokio::test]
+async fn test_sync_handle_add_key() {
+    let messages = [
+        ("A", 1000),
+        ("B", 1010),
+        ("C", 1020),
+        ("A", 1100),
+        ("B", 1110),
+        ("C", 1120),
+    ]
+    .map(|(key, ts)| Ok((key, TestMessage::new(ts))));
+
+    let config = Config::basic(Duration::from_millis(50), None, 16);
+    let (output_stream, _feedback_rx, handle) =
+        sync_with_handle(stream::iter(messages), ["A", "B"], config).unwrap();
+
+    // Stream C is discovered after the synchronizer starts.
+    handle.add_key("C").unwrap();
+
+    let groups: Vec<IndexMap<&str, TestMessage>> = output_stream.try_collect().await.unwrap();
+    assert_eq!(groups.len(), 2);
+    assert!(groups.iter().all(|group| group.len() == 3));
+    assert_eq!(groups[1]["C"], TestMessage::new(1120));
+}
+
+#[tokio::test]
+async fn test_sync_handle_remove_key() {
+    let messages = [1000, 1100, 1200].map(|ts| Ok(("A", TestMessage::new(ts))));
+
+    let config = Config::basic(Duration::from_millis(50), None, 16);
+    let (output_stream, _feedback_rx, handle) =
+        sync_with_handle(stream::iter(messages), ["A", "B"], config).unwrap();
+
+    // Stream B goes away without sending any message.
+    handle.remove_key("B").unwrap();
+    // The last stream cannot be removed.
+    handle.remove_key("A").unwrap();
+
+    let groups: Vec<IndexMap<&str, TestMessage>> = output_stream.try_collect().await.unwrap();
+    assert_eq!(groups.len(), 3);
+    assert!(groups
+        .iter()
+        .all(|group| group.len() == 1 && group.contains_key("A")));
+}
@@ -0,0 +1,195 @@
+mod common;
+
+use common::*;
+use std::time::Duration;
+
+/// Builds a stream delivering one message from each key in turn.
+fn interleaved(streams: &[(&'static str, &[u64])]) -> StreamBuilder<&'static str> {
+    let max_len = streams.iter().map(|(_, ts)| ts.len()).max().unwrap_or(0);
+
+    (0..max_len).fold(StreamBuilder::new(), |builder, index| {
+        streams.iter().fold(builder, |builder, &(key, timestamps)| {
+            match timestamps.get(index) {
+                Some(&ts) => builder.add_message(key, ts),
+                None => builder,
+            }
+        })
+    })
+}
+
+fn timestamps_of(groups: &[indexmap::IndexMap<&str, TestMessage>], key: &str) -> Vec<u64> {
+    groups
+        .iter()
+        .map(|group| group[key].timestamp.as_millis() as u64)
+        .collect()
+}
+
+#[tokio::test]
+async fn test_two_streams_perfect_alignment() {
+    let timestamps = [1000, 1100, 1200, 1300];
+    let stream = interleaved(&[("A", &timestamps), ("B", &timestamps)]).build();
+
+    let groups = run_sync(stream, ["A", "B"], config_with_window(50))
+        .await
+        .unwrap();
+
+    assert_eq!(groups.len(), 4);
+    assert_eq!(timestamps_of(&groups, "A"), timestamps);
+    assert_eq!(timestamps_of(&groups, "B"), timestamps);
+    assert_groups_valid(&groups, Duration::from_millis(50));
+    assert_timestamp_ordering(&groups);
+}
+
+#[tokio::test]
+async fn test_three_streams_with_one_lagging() {
+    let timestamps = [1000, 1100, 1200, 1300];
+    let stream = interleaved(&[("A", &timestamps), ("B", &timestamps)])
+        .add_messages("C", &[1010, 1110, 1210, 1310])
+        .build();
+
+    let groups = run_sync(stream, ["A", "B", "C"], config_with_window(50))
+        .await
+        .unwrap();
+
+    assert_eq!(groups.len(), 4);
+    assert!(groups.iter().all(|group| group.len() == 3));
+    assert_eq!(timestamps_of(&groups, "C"), [1010, 1110, 1210, 1310]);
+    assert_groups_valid(&groups, Duration::from_millis(50));
+    assert_timestamp_ordering(&groups);
+}
+
+#[tokio::test]
+async fn test_input_end_flushes_buffered_groups() {
+    // Each stream arrives in a burst, so most messages are still
+    // buffered when the input ends.
+    let stream = StreamBuilder::new()
+        .add_messages("A", &[1000, 1100, 1200])
+        .add_messages("B", &[1005, 1105, 1205])
+        .build();
+
+    let groups = run_sync(stream, ["A", "B"], config_with_window(50))
+        .await
+        .unwrap();
+
+    assert_eq!(timestamps_of(&groups, "A"), [1000, 1100, 1200]);
+    assert_eq!(timestamps_of(&groups, "B"), [1005, 1105, 1205]);
+    assert_groups_valid(&groups, Duration::from_millis(50));
+}
+
+#[tokio::test]
+async fn test_out_of_order_message_is_dropped() {
+    let stream = interleaved(&[
+        ("A", &[1000, 1200, 1100, 1300]),
+        ("B", &[1000, 1100, 1200, 1300]),
+    ])
+    .build();
+
+    let groups = run_sync(stream, ["A", "B"], config_with_window(50))
+        .await
+        .unwrap();
+
+    // A@1100 arrives after A@1200 and is rejected, leaving B@1100
+    // without a partner.
+    assert_eq!(timestamps_of(&groups, "A"), [1000, 1200, 1300]);
+    assert_eq!(timestamps_of(&groups, "B"), [1000, 1200, 1300]);
+    assert_timestamp_ordering(&groups);
+}
+
+#[tokio::test]
+async fn test_duplicate_timestamps_keep_last() {
+    // The second A@1000 replaces the first one in the buffer.
+    let stream =
+        interleaved(&[("A", &[1000, 1000, 1100, 1200]), ("B", &[1000, 1100, 1200])]).build();
+
+    let groups = run_sync(stream, ["A", "B"], config_with_window(50))
+        .await
+        .unwrap();
+
+    assert_eq!(timestamps_of(&groups, "A"), [1000, 1100, 1200]);
+    assert_eq!(timestamps_of(&groups, "B"), [1000, 1100, 1200]);
+}
+
+#[tokio::test]
+async fn test_all_buffers_full_simultaneously() {
+    // The window is wider than what four buffered messages span, so
+    // every buffer fills up before any group can be formed.
+    let timestamps: Vec<u64> = (0..10).map(|index| 1000 + index * 100).collect();
+    let stream = interleaved(&[("A", &timestamps), ("B", &timestamps)]).build();
+    let mut config = config_with_window(500);
+    config.buf_size = 4;
+
+    let groups = run_sync(stream, ["A", "B"], config).await.unwrap();
+
+    // The oldest messages are dropped to make room and the remaining
+    // pairs are flushed once the input ends.
+    assert_eq!(timestamps_of(&groups, "A"), [1700, 1800, 1900]);
+    assert_eq!(timestamps_of(&groups, "B"), [1700, 1800, 1900]);
+}
+
+#[tokio::test]
+async fn test_start_time_filters_early_messages() {
+    let timestamps = [1000, 1100, 1200];
+    let stream = interleaved(&[("A", &timestamps), ("B", &timestamps)]).build();
+    let mut config = config_with_window(50);
+    config.start_time = Some(Duration::from_millis(1050));
+
+    let groups = run_sync(stream, ["A", "B"], config).await.unwrap();
+
+    assert_eq!(timestamps_of(&groups, "A"), [1100, 1200]);
+    assert_eq!(timestamps_of(&groups, "B"), [1100, 1200]);
+}
+
+#[tokio::test]
+async fn test_tiny_window_drops_unaligned_messages() {
+    let stream = interleaved(&[
+        ("A", &[1000, 1100, 1200, 1300]),
+        ("B", &[1050, 1150, 1250, 1350]),
+    ])
+    .build();
+
+    let groups = run_sync(stream, ["A", "B"], config_with_window(1))
+        .await
+        .unwrap();
+
+    assert!(groups.is_empty());
+}
+
+#[tokio::test]
+async fn test_heap_allocated_keys() {
+    let camera = "camera".to_string();
+    let lidar = "lidar".to_string();
+    let stream = StreamBuilder::new()
+        .add_messages(camera.clone(), &[1000, 1100, 1200])
+        .add_messages(lidar.clone(), &[1010, 1110, 1210])
+        .build();
+
+    let groups = run_sync(
+        stream,
+        [camera.clone(), lidar.clone()],
+        config_with_window(50),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(groups.len(), 3);
+    for (group, ts) in groups.iter().zip([1000, 1100, 1200]) {
+        assert_eq!(group[&camera].timestamp, Duration::from_millis(ts));
+        assert_eq!(group[&lidar].timestamp, Duration::from_millis(ts + 10));
+    }
+
+    let stream = StreamBuilder::new()
+        .add_messages(b"left".to_vec(), &[1000, 1100])
+        .add_messages(b"right".to_vec(), &[1005, 1105])
+        .build();
+
+    let groups = run_sync(
+        stream,
+        [b"left".to_vec(), b"right".to_vec()],
+        config_with_window(50),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(groups.len(), 2);
+    assert!(groups.iter().all(|group| group.len() == 2));
+}
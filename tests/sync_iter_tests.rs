@@ -0,0 +1,109 @@
+mod common;
+
+use common::*;
+use multi_stream_synchronizer::{
+    drain_to_vec, sync_from_iter, sync_iter, Config, SyncError, SyncIter, WindowMode,
+};
+use std::time::Duration;
+
+fn tagged(key: &'static str, timestamps_ms: &[u64]) -> Vec<(&'static str, TestMessage)> {
+    create_messages(timestamps_ms)
+        .into_iter()
+        .map(|msg| (key, msg))
+        .collect()
+}
+
+#[tokio::test]
+async fn test_sync_from_iter_matches_async_sync() {
+    let a_ts = [1000, 1100, 1200, 1300, 1400];
+    let b_ts = [1010, 1090, 1220, 1330, 1380];
+
+    let stream = StreamBuilder::new()
+        .add_messages("A", &a_ts)
+        .add_messages("B", &b_ts)
+        .build();
+    let async_groups = run_sync(stream, ["A", "B"], config_with_window(50))
+        .await
+        .unwrap();
+
+    let messages = tagged("A", &a_ts).into_iter().chain(tagged("B", &b_ts));
+    let (mut iter_groups, mut state) =
+        sync_from_iter(messages, ["A", "B"], config_with_window(50)).unwrap();
+    iter_groups.extend(drain_to_vec(&mut state));
+
+    assert_eq!(iter_groups, async_groups);
+    assert_groups_valid(&iter_groups, Duration::from_millis(50));
+    assert_timestamp_ordering(&iter_groups);
+}
+
+#[test]
+fn test_drain_to_vec_empties_state() {
+    let messages = tagged("A", &[1000]).into_iter().chain(tagged("B", &[1005]));
+    let (groups, mut state) = sync_from_iter(messages, ["A", "B"], config_with_window(50)).unwrap();
+    assert!(groups.is_empty());
+
+    let remaining = state.drain_to_vec();
+    assert_eq!(remaining.len(), 1);
+    assert!(state.is_empty());
+    assert!(state.drain_to_vec().is_empty());
+}
+
+#[tokio::test]
+async fn test_sync_iter_matches_async_sync() {
+    let a_ts = [1000, 1100, 1200, 1300, 1400];
+    let b_ts = [1010, 1090, 1220, 1330, 1380];
+
+    let stream = StreamBuilder::new()
+        .add_messages("A", &a_ts)
+        .add_messages("B", &b_ts)
+        .build();
+    let async_groups = run_sync(stream, ["A", "B"], config_with_window(50))
+        .await
+        .unwrap();
+
+    let messages = tagged("A", &a_ts).into_iter().chain(tagged("B", &b_ts));
+    let (iter, stats) = SyncIter::new(messages, ["A", "B"], config_with_window(50))
+        .unwrap()
+        .with_stats();
+    let iter_groups: Vec<_> = iter.collect::<eyre::Result<_>>().unwrap();
+
+    assert_eq!(iter_groups, async_groups);
+    let stats = stats.lock().unwrap();
+    assert_eq!(stats.total_messages_received, 10);
+    assert_eq!(stats.total_groups_emitted, iter_groups.len() as u64);
+    assert!(stats.total_drops() <= 10 - 2 * iter_groups.len() as u64);
+}
+
+#[test]
+fn test_sync_iter_debug_shows_buffers() {
+    let messages = tagged("A", &[1000]).into_iter().chain(tagged("B", &[1005]));
+    let iter = SyncIter::new(messages, ["A", "B"], config_with_window(50)).unwrap();
+
+    let debug = format!("{iter:?}");
+    assert!(debug.contains("pending_messages: 2"));
+    assert!(debug.contains("\"A\": 0"));
+}
+
+#[test]
+fn test_sync_iter_flushes_remaining_groups() {
+    let messages = tagged("A", &[1000, 1100])
+        .into_iter()
+        .chain(tagged("B", &[1005, 1110]));
+    let groups: Vec<_> = sync_iter(messages, ["A", "B"], config_with_window(50))
+        .unwrap()
+        .collect::<eyre::Result<_>>()
+        .unwrap();
+
+    assert_eq!(groups.len(), 2);
+    assert_groups_valid(&groups, Duration::from_millis(50));
+}
+
+#[test]
+fn test_sync_iter_rejects_session_mode() {
+    let config =
+        Config::basic(Duration::from_millis(50), None, 4).with_window_mode(WindowMode::Session {
+            gap: Duration::from_millis(10),
+        });
+    let result = sync_iter(Vec::<(&str, TestMessage)>::new(), ["A", "B"], config);
+    assert!(matches!(result, Err(SyncError::SessionModeUnsupported)));
+}
@@ -249,3 +249,37 @@ async fn test_complex_interleaving() {
     assert_groups_valid(&groups, Duration::from_millis(150));
     assert_timestamp_ordering(&groups);
 }
+
+#[tokio::test]
+async fn test_final_group_emitted_with_extra_message() {
+    // Stream C sends one extra message after the other streams end.
+    // Stream A: [1000ms, 1100ms, 1200ms]
+    // Stream B: [1005ms, 1105ms, 1205ms]
+    // Stream C: [1010ms, 1110ms, 1210ms, 1300ms]
+    // Window: 50ms
+
+    let stream = StreamBuilder::new()
+        .add_message("A", 1000)
+        .add_message("B", 1005)
+        .add_message("C", 1010)
+        .add_message("A", 1100)
+        .add_message("B", 1105)
+        .add_message("C", 1110)
+        .add_message("A", 1200)
+        .add_message("B", 1205)
+        .add_message("C", 1210)
+        .add_message("C", 1300)
+        .build();
+
+    let config = config_with_window(50);
+    let groups = run_sync(stream, ["A", "B", "C"], config).await.unwrap();
+
+    assert_eq!(groups.len(), 3);
+    let last = groups.last().unwrap();
+    assert_eq!(last["A"].timestamp(), Duration::from_millis(1200));
+    assert_eq!(last["B"].timestamp(), Duration::from_millis(1205));
+    assert_eq!(last["C"].timestamp(), Duration::from_millis(1210));
+
+    assert_groups_valid(&groups, Duration::from_millis(50));
+    assert_timestamp_ordering(&groups);
+}